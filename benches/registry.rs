@@ -0,0 +1,65 @@
+//! Adding, listing and searching questions at different registry sizes.
+//!
+//! Runs at 10k and 100k questions by default. Set `DECIS_BENCH_SIZES` to a
+//! comma-separated list to change that, e.g. `DECIS_BENCH_SIZES=1000000`.
+
+use std::collections::HashSet;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use decis::{Actor, Question, Registry};
+use decis::query::UndecidedFilter;
+
+const TAGS: [&str; 20] = [
+    "infra", "billing", "security", "docs", "ci", "frontend", "backend", "mobile", "data", "ml",
+    "hiring", "legal", "support", "sales", "design", "ops", "api", "search", "payments", "growth"
+];
+
+fn sizes() -> Vec<usize> {
+    std::env::var("DECIS_BENCH_SIZES").ok()
+        .map(|sizes| sizes.split(',').filter_map(|size| size.trim().parse().ok()).collect())
+        .unwrap_or_else(|| vec![10_000, 100_000])
+}
+
+fn question(number: usize) -> Question {
+    let tags: HashSet<String> = [TAGS[number % TAGS.len()], TAGS[(number / TAGS.len()) % TAGS.len()]].iter().map(|tag| tag.to_string()).collect();
+    let options: HashSet<String> = ["Yes".to_string(), "No".to_string()].iter().cloned().collect();
+    Question::new(format!("Question {} about latency?", number), tags, HashSet::new(), options)
+}
+
+fn registry(size: usize) -> Registry {
+    let actor = Actor::new("bench");
+    let mut registry = Registry::new();
+    for tag in &TAGS {
+        registry.add_tag(&tag.to_string(), &actor).unwrap();
+    }
+    registry.add_questions((0..size).map(question).collect(), &actor);
+    registry
+}
+
+fn benches(c: &mut Criterion) {
+    let actor = Actor::new("bench");
+    for size in sizes() {
+        let populated = registry(size);
+        let mut group = c.benchmark_group(format!("{} questions", size));
+        group.sample_size(10);
+        group.bench_function(BenchmarkId::new("add_question", size), |b| b.iter_batched(
+            || (populated.clone(), question(size)),
+            // Returned so the copy is dropped outside the measurement.
+            |(mut registry, question)| {
+                registry.add_question(question, &actor).unwrap();
+                registry
+            },
+            BatchSize::LargeInput
+        ));
+        group.bench_function(BenchmarkId::new("list_questions", size), |b| b.iter(|| populated.list_questions()));
+        group.bench_function(BenchmarkId::new("list_questions_with_tag", size), |b| b.iter(|| populated.list_questions_with_tag("security")));
+        group.bench_function(BenchmarkId::new("undecided", size), |b| {
+            let filter = UndecidedFilter { tags: vec!["security".to_string(), "infra".to_string()], ..UndecidedFilter::default() };
+            b.iter(|| populated.undecided(&filter))
+        });
+        group.bench_function(BenchmarkId::new("search", size), |b| b.iter(|| populated.search("latency tag:security")));
+        group.finish();
+    }
+}
+
+criterion_group!(registry_benches, benches);
+criterion_main!(registry_benches);
@@ -0,0 +1,216 @@
+//! When questions last saw any activity.
+//!
+//! Activity is read from the audit log, which records every change made
+//! through the registry, and from the timestamps on the question's own
+//! context, comments and status records, which survive audit compaction.
+//!
+//! `Registry::recent_activity` turns the audit log into a feed of events,
+//! and `Registry::needs_attention` combines the time-based queries into the one
+//! list a planning meeting goes through.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::{Actor, Question, QuestionSummary, Registry};
+use crate::audit::{AuditAction, AuditEntry};
+use crate::status::QuestionStatus;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ActivityEvent {
+    pub at: DateTime<Utc>,
+    pub actor: Actor,
+    /// The question's identifier and content, for events about a question
+    /// that is still in the registry.
+    pub question: Option<(String, String)>,
+    pub action: AuditAction
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NeedsAttention {
+    /// Deferred questions past their wake-up date.
+    pub waking_up: Vec<QuestionSummary>,
+    /// Decisions past their expiry.
+    pub expired_decisions: Vec<QuestionSummary>,
+    /// Decisions that expire soon, see `Registry::decisions_due_for_review`.
+    pub due_for_review: Vec<QuestionSummary>,
+    /// Open questions with no recent activity.
+    pub stale: Vec<QuestionSummary>
+}
+
+impl NeedsAttention {
+    pub fn is_empty(&self) -> bool {
+        self.waking_up.is_empty() && self.expired_decisions.is_empty() && self.due_for_review.is_empty() && self.stale.is_empty()
+    }
+}
+
+impl Question {
+    /// The latest timestamp on the question itself, if it has any.
+    fn last_recorded_activity(&self) -> Option<DateTime<Utc>> {
+        let context = self.context.iter().flat_map(|item| std::iter::once(item.added_at).chain(item.retraction.as_ref().map(|retraction| retraction.at)));
+        let comments = self.comments.iter().flat_map(|comment| std::iter::once(comment.posted_at).chain(comment.history.iter().map(|revision| revision.replaced_at)));
+        let arguments = self.arguments.values().flat_map(|arguments| arguments.pros.iter().chain(arguments.cons.iter())).map(|argument| argument.added_at);
+        let attachments = self.attachments.iter().map(|attachment| attachment.added_at);
+        let status = self.deferral.as_ref().map(|deferral| deferral.at)
+            .into_iter()
+            .chain(self.cancellation.as_ref().map(|cancellation| cancellation.at))
+            .chain(self.decision_history.iter().map(|past| past.at));
+        let history = self.history.iter().map(|change| change.at);
+        context.chain(comments).chain(arguments).chain(attachments).chain(status).chain(history).max()
+    }
+}
+
+impl Registry {
+    /// When each question was last changed, for questions with any record
+    /// of activity.
+    pub fn last_activity(&self) -> HashMap<Uuid, DateTime<Utc>> {
+        let mut latest: HashMap<Uuid, DateTime<Utc>> = self.questions.values()
+            .filter_map(|question| question.last_recorded_activity().map(|at| (question.identifier, at)))
+            .collect();
+        for entry in self.audit_log.entries() {
+            if let Some(question) = entry.question {
+                let at = latest.entry(question).or_insert(entry.at);
+                *at = (*at).max(entry.at);
+            }
+        }
+        latest
+    }
+
+    /// Open questions with no activity in the last `inactive_for`, longest
+    /// idle first.
+    pub fn stale_questions(&self, inactive_for: Duration) -> Vec<QuestionSummary> {
+        self.stale_questions_at(self.now(), inactive_for)
+    }
+
+    /// `stale_questions` as of `now`. Questions without any record of
+    /// activity count as stale.
+    pub fn stale_questions_at(&self, now: DateTime<Utc>, inactive_for: Duration) -> Vec<QuestionSummary> {
+        let latest = self.last_activity();
+        let cutoff = now - inactive_for;
+        let mut stale: Vec<(DateTime<Utc>, &Question)> = self.questions.values()
+            .filter(|question| question.status() == QuestionStatus::Open)
+            .map(|question| (latest.get(&question.identifier).copied().unwrap_or(DateTime::<Utc>::MIN_UTC), question))
+            .filter(|(at, _)| *at < cutoff)
+            .collect();
+        stale.sort_by_key(|(at, _)| *at);
+        stale.into_iter().map(|(_, question)| question.summary_at(now)).collect()
+    }
+
+    /// The latest `limit` events at or after `since` (all of them without
+    /// it), oldest first. Events are ordered by timestamp, since merges and
+    /// clock changes can leave the audit log out of time order.
+    pub fn recent_activity(&self, limit: usize, since: Option<DateTime<Utc>>) -> Vec<ActivityEvent> {
+        let mut entries: Vec<&AuditEntry> = self.audit_log.entries().iter()
+            .filter(|entry| since.is_none_or(|since| entry.at >= since))
+            .collect();
+        entries.sort_by_key(|entry| entry.at);
+        let start = entries.len().saturating_sub(limit);
+        entries[start..].iter().map(|entry| ActivityEvent {
+            at: entry.at,
+            actor: entry.actor.clone(),
+            question: entry.question
+                .and_then(|uuid| self.questions.get(&uuid))
+                .map(|question| (question.identifier.to_string(), question.content.clone())),
+            action: entry.action.clone()
+        }).collect()
+    }
+
+    /// Everything due for a look as of `now`. Open questions idle for
+    /// longer than `stale_after` count as stale, and decisions expiring within
+    /// `review_within` are due for review.
+    pub fn needs_attention(&self, now: DateTime<Utc>, stale_after: Duration, review_within: Duration) -> NeedsAttention {
+        NeedsAttention {
+            waking_up: self.questions_waking_up(now.date_naive()),
+            expired_decisions: self.expired_decisions(now),
+            due_for_review: self.decisions_due_for_review(now, review_within),
+            stale: self.stale_questions_at(now, stale_after)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{Duration, Utc};
+    use crate::*;
+    use crate::clock::FixedClock;
+
+    #[test]
+    fn test_only_idle_open_questions_are_stale() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_question(Question::new("Idle?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question(Question::new("Busy?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let deferred = registry.add_question(Question::new("Later?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.defer_question(deferred, "Not now".to_string(), None, 0, &luke).unwrap();
+
+        let week_later = Utc::now() + Duration::days(7);
+        assert!(registry.stale_questions_at(week_later, Duration::days(10)).is_empty());
+        let stale = registry.stale_questions_at(week_later, Duration::days(5));
+        assert_eq!(stale.len(), 2);
+        assert!(registry.stale_questions(Duration::days(5)).is_empty());
+
+        // The questions' own histories outlive the audit log.
+        registry.audit_log.compact(week_later, None).unwrap();
+        assert!(registry.stale_questions(Duration::days(5)).is_empty());
+        assert_eq!(registry.stale_questions_at(week_later, Duration::days(5)).len(), stale.len());
+    }
+
+    #[test]
+    fn test_recent_activity_is_a_bounded_feed() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let before = Utc::now();
+        let identifier = registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_comment(identifier.clone(), "GitHub Actions?".to_string(), None, &luke).unwrap();
+        registry.set_question_priority(identifier.clone(), priority::Priority::High, 0, &luke).unwrap();
+
+        let feed = registry.recent_activity(2, Some(before));
+        assert_eq!(feed.len(), 2);
+        assert!(matches!(feed[0].action, audit::AuditAction::CommentAdded(_)));
+        assert_eq!(feed[1].question, Some((identifier, "Which CI?".to_string())));
+        assert!(registry.recent_activity(10, Some(Utc::now() + Duration::days(1))).is_empty());
+    }
+
+    #[test]
+    fn test_recent_activity_goes_by_timestamp() {
+        let luke = Actor::new("luke");
+        let start = Utc::now();
+        let clock = FixedClock::new(start);
+        let mut registry = Registry::new();
+        registry.set_clock(clock.clone());
+        registry.add_tag(&"New".to_string(), &luke).unwrap();
+        clock.set(start - Duration::hours(2));
+        registry.add_tag(&"Old".to_string(), &luke).unwrap();
+
+        let feed = registry.recent_activity(1, Some(start - Duration::hours(1)));
+        assert_eq!(feed.len(), 1);
+        assert_eq!(feed[0].action, audit::AuditAction::TagAdded("New".to_string()));
+        let all = registry.recent_activity(10, None);
+        assert_eq!(all[0].action, audit::AuditAction::TagAdded("Old".to_string()));
+    }
+
+    #[test]
+    fn test_needs_attention_combines_the_queries() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let temporary = registry.add_question(Question::new("Free tier?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        let deferred = registry.add_question(Question::new("Later?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question(Question::new("Idle?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let now = Utc::now();
+        let decision = Decision::new("Yes".to_string(), "For now".to_string(), HashSet::new()).with_expiry(now + Duration::days(3));
+        registry.set_question_decision(temporary.clone(), decision, 0, &luke).unwrap();
+        registry.defer_question(deferred.clone(), "Next sprint".to_string(), Some((now + Duration::days(5)).date_naive()), 0, &luke).unwrap();
+
+        assert!(registry.needs_attention(now, Duration::days(1), Duration::days(1)).is_empty());
+        let soon = registry.needs_attention(now, Duration::days(1), Duration::days(3));
+        assert_eq!(soon.due_for_review[0].identifier, temporary);
+        assert!(soon.expired_decisions.is_empty());
+        let attention = registry.needs_attention(now + Duration::days(7), Duration::days(6), Duration::days(30));
+        assert!(attention.due_for_review.is_empty());
+        assert_eq!(attention.expired_decisions[0].identifier, temporary);
+        assert_eq!(attention.waking_up[0].identifier, deferred);
+        assert_eq!(attention.stale[0].content, "Idle?");
+    }
+}
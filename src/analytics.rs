@@ -0,0 +1,468 @@
+//! Statistics over the registry's questions and how they get decided.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use crate::{Actor, Question, QuestionSummary, Registry};
+use crate::audit::AuditAction;
+use crate::search::{SearchAnalysis, SearchField};
+
+/// The spread of a set of durations. Percentiles use the nearest-rank
+/// method, so each is one of the measured durations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DurationStats {
+    pub count: usize,
+    pub mean: Duration,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p95: Duration,
+    pub max: Duration
+}
+
+impl DurationStats {
+    /// `None` for no durations.
+    pub fn from_durations(mut durations: Vec<Duration>) -> Option<DurationStats> {
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort();
+        let count = durations.len();
+        let total: Duration = durations.iter().fold(Duration::zero(), |total, duration| total + *duration);
+        let percentile = |percent: usize| durations[(count * percent).div_ceil(100).max(1) - 1];
+        Some(DurationStats {
+            count,
+            mean: total / count as i32,
+            min: durations[0],
+            p50: percentile(50),
+            p90: percentile(90),
+            p95: percentile(95),
+            max: durations[count - 1]
+        })
+    }
+}
+
+/// How long questions took from being asked to being decided.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeToDecision {
+    /// `None` if nothing has been decided.
+    pub overall: Option<DurationStats>,
+    pub by_tag: BTreeMap<String, DurationStats>,
+    /// Keyed by the month of the decision, as `YYYY-MM`.
+    pub by_month: BTreeMap<String, DurationStats>
+}
+
+/// Two tags used on the same questions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagPair {
+    /// The alphabetically first of the two.
+    pub first: String,
+    pub second: String,
+    /// Questions tagged with both.
+    pub together: usize,
+    /// `together` over the questions tagged with either, from 0 to 1. Near 1
+    /// the tags are as good as synonyms.
+    pub overlap: f64
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bucket {
+    Day,
+    /// Starting on Monday.
+    Week,
+    Month
+}
+
+impl Bucket {
+    /// The first day of the bucket `date` falls in.
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Bucket::Day => date,
+            Bucket::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Bucket::Month => date.with_day(1).expect("every month has a first day")
+        }
+    }
+
+    fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Bucket::Day => start + Duration::days(1),
+            Bucket::Week => start + Duration::weeks(1),
+            Bucket::Month => start.checked_add_months(chrono::Months::new(1)).expect("dates stay in range")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HistogramBucket {
+    pub start: NaiveDate,
+    /// Questions added.
+    pub opened: usize,
+    /// Decisions set, including ones since reopened.
+    pub decided: usize
+}
+
+/// Questions that look like they are about the same thing.
+#[derive(Clone, Debug)]
+pub struct Cluster {
+    /// Ordered by content.
+    pub questions: Vec<QuestionSummary>,
+    /// The tags (written `#tag`) and words most members share, commonest
+    /// first. Words are as search reads them.
+    pub label: Vec<String>,
+    /// The question most like the rest of the cluster.
+    pub representative: QuestionSummary
+}
+
+/// How many shared tags and words a cluster's label shows.
+const LABEL_LENGTH: usize = 3;
+
+/// One person's part in the registry's decisions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Participation {
+    /// Standing decisions they are among the decision makers of.
+    pub decisions: usize,
+    /// Questions they own, decided or not.
+    pub owned: usize,
+    /// From being made owner to the decision, over the decided questions
+    /// they own. `None` if there are none with a record of both.
+    pub mean_assignment_to_decision: Option<Duration>
+}
+
+impl Question {
+    /// When the current owner was assigned, from the question's history.
+    fn assigned_at(&self) -> Option<DateTime<Utc>> {
+        let owner = self.owner.as_ref()?;
+        self.history().iter().rev()
+            .find(|change| change.action == AuditAction::OwnerChanged(Some(owner.clone())))
+            .map(|change| change.at)
+    }
+}
+
+impl Registry {
+    /// Everyone who owns a question or helped make a standing decision.
+    pub fn participation(&self) -> BTreeMap<Actor, Participation> {
+        let mut people: BTreeMap<Actor, Participation> = BTreeMap::new();
+        let mut waits: HashMap<&Actor, Vec<Duration>> = HashMap::new();
+        for question in self.questions.values() {
+            if let Some(decision) = &question.decision {
+                for person in &decision.decision_makers {
+                    people.entry(person.clone()).or_default().decisions += 1;
+                }
+            }
+            if let Some(owner) = &question.owner {
+                people.entry(owner.clone()).or_default().owned += 1;
+                if let (Some(assigned), Some(decided)) = (question.assigned_at(), self.decided_at(question)) {
+                    waits.entry(owner).or_default().push(decided - assigned);
+                }
+            }
+        }
+        for (owner, waits) in waits {
+            let total = waits.iter().fold(Duration::zero(), |total, wait| total + *wait);
+            people.get_mut(owner).expect("owners are counted").mean_assignment_to_decision = Some(total / waits.len() as i32);
+        }
+        people
+    }
+
+    /// Standing decisions made by a single person, a measure of how much
+    /// rests on individuals. Ordered by that person, then content.
+    pub fn single_person_decisions(&self) -> Vec<(Actor, QuestionSummary)> {
+        let mut decisions: Vec<(&Actor, &Question)> = self.questions.values()
+            .filter_map(|question| question.decision.as_ref()
+                .filter(|decision| decision.decision_makers.len() == 1)
+                .and_then(|decision| decision.decision_makers.iter().next())
+                .map(|person| (person, question)))
+            .collect();
+        let now = self.now();
+        decisions.sort_by(|(a_person, a), (b_person, b)| a_person.cmp(b_person).then_with(|| a.content.cmp(&b.content)));
+        decisions.into_iter().map(|(person, question)| (person.clone(), question.summary_at(now))).collect()
+    }
+
+    /// Questions opened and decisions made per `bucket`, over the questions
+    /// tagged `tag` if given. Buckets run without gaps from the first
+    /// activity to the last, so empty ones are included.
+    pub fn activity_histogram(&self, bucket: Bucket, tag: Option<&str>) -> Vec<HistogramBucket> {
+        let questions: Vec<&Question> = match tag {
+            Some(tag) => self.questions_with_tag(tag),
+            None => self.questions.values().collect()
+        };
+        let mut counts: BTreeMap<NaiveDate, (usize, usize)> = BTreeMap::new();
+        for question in questions {
+            if let Some(created) = self.created_at(question) {
+                counts.entry(bucket.start(created.date_naive())).or_default().0 += 1;
+            }
+            for change in question.history().iter().filter(|change| matches!(change.action, AuditAction::DecisionSet(_))) {
+                counts.entry(bucket.start(change.at.date_naive())).or_default().1 += 1;
+            }
+        }
+        let (first, last) = match (counts.keys().next(), counts.keys().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Vec::new()
+        };
+        let mut histogram = Vec::new();
+        let mut start = first;
+        while start <= last {
+            let (opened, decided) = counts.get(&start).copied().unwrap_or_default();
+            histogram.push(HistogramBucket { start, opened, decided });
+            start = bucket.next(start);
+        }
+        histogram
+    }
+
+    /// Every pair of tags that appear together on at least one question,
+    /// most frequent first.
+    pub fn tag_cooccurrence(&self) -> Vec<TagPair> {
+        let mut uses: HashMap<&String, usize> = HashMap::new();
+        let mut together: HashMap<(&String, &String), usize> = HashMap::new();
+        for question in self.questions.values() {
+            let mut tags: Vec<&String> = question.tags.iter().collect();
+            tags.sort();
+            for (position, first) in tags.iter().enumerate() {
+                *uses.entry(first).or_insert(0) += 1;
+                for second in &tags[position + 1..] {
+                    *together.entry((first, second)).or_insert(0) += 1;
+                }
+            }
+        }
+        let mut pairs: Vec<TagPair> = together.into_iter().map(|((first, second), together)| TagPair {
+            first: first.clone(),
+            second: second.clone(),
+            together,
+            overlap: together as f64 / (uses[first] + uses[second] - together) as f64
+        }).collect();
+        pairs.sort_by(|a, b| b.together.cmp(&a.together)
+            .then_with(|| a.first.cmp(&b.first))
+            .then_with(|| a.second.cmp(&b.second)));
+        pairs
+    }
+
+    /// Groups questions that share enough tags and words, so separate teams
+    /// deciding the same thing show up together. Similarity is the share of
+    /// their tags and words two questions have in common, from 0 to 1, and
+    /// ignores words used by more than half of all questions. A cluster
+    /// holds every question linked to another by a similarity of at least
+    /// `min_similarity`, directly or through other members. Questions
+    /// similar to none are left out. The biggest clusters come first.
+    pub fn cluster_questions(&self, min_similarity: f64) -> Vec<Cluster> {
+        let analysis = SearchAnalysis { tokenize: true, ..self.settings.search.clone() };
+        let mut questions: Vec<&Question> = self.questions.values().collect();
+        questions.sort_by(|a, b| a.content.cmp(&b.content).then_with(|| a.identifier.cmp(&b.identifier)));
+        let words: Vec<BTreeSet<String>> = questions.iter()
+            .map(|question| [SearchField::Content, SearchField::Context].iter()
+                .flat_map(|field| question.field_texts(*field))
+                .flat_map(|text| analysis.words(text).into_iter().map(|(_, word)| word))
+                .collect())
+            .collect();
+        let mut uses: HashMap<&String, usize> = HashMap::new();
+        words.iter().flatten().for_each(|word| *uses.entry(word).or_default() += 1);
+        let features: Vec<BTreeSet<String>> = questions.iter().zip(&words)
+            .map(|(question, words)| question.tags.iter().map(|tag| format!("#{}", tag))
+                .chain(words.iter().filter(|word| uses[word] * 2 <= questions.len()).cloned())
+                .collect())
+            .collect();
+        let similarity = |a: usize, b: usize| {
+            let shared = features[a].intersection(&features[b]).count();
+            let either = features[a].union(&features[b]).count();
+            if either == 0 { 0.0 } else { shared as f64 / either as f64 }
+        };
+
+        fn root(parents: &mut [usize], node: usize) -> usize {
+            let mut node = node;
+            while parents[node] != node {
+                parents[node] = parents[parents[node]];
+                node = parents[node];
+            }
+            node
+        }
+        let mut parents: Vec<usize> = (0..questions.len()).collect();
+        let mut linked = vec![false; questions.len()];
+        for a in 0..questions.len() {
+            for b in a + 1..questions.len() {
+                if similarity(a, b) >= min_similarity {
+                    let (root_a, root_b) = (root(&mut parents, a), root(&mut parents, b));
+                    parents[root_b] = root_a;
+                    linked[a] = true;
+                    linked[b] = true;
+                }
+            }
+        }
+        let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for member in (0..questions.len()).filter(|member| linked[*member]) {
+            let group = root(&mut parents, member);
+            groups.entry(group).or_default().push(member);
+        }
+
+        let now = self.now();
+        let mut clusters: Vec<Cluster> = groups.into_values().map(|members| {
+            let closeness = |member: usize| members.iter().filter(|other| **other != member).map(|other| similarity(member, *other)).sum::<f64>();
+            let representative = *members.iter()
+                .max_by(|a, b| closeness(**a).total_cmp(&closeness(**b)).then_with(|| b.cmp(a)))
+                .expect("clusters are never empty");
+            let mut shared: HashMap<&String, usize> = HashMap::new();
+            members.iter().flat_map(|member| &features[*member]).for_each(|feature| *shared.entry(feature).or_default() += 1);
+            let mut label: Vec<(&String, usize)> = shared.into_iter().filter(|(_, count)| *count > 1).collect();
+            label.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+            Cluster {
+                questions: members.iter().map(|member| questions[*member].summary_at(now)).collect(),
+                label: label.into_iter().take(LABEL_LENGTH).map(|(feature, _)| feature.clone()).collect(),
+                representative: questions[representative].summary_at(now)
+            }
+        }).collect();
+        clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.questions.len()));
+        clusters
+    }
+
+    /// Over the questions' current decisions. Questions whose creation or
+    /// decision predates the records kept are left out.
+    pub fn time_to_decision(&self) -> TimeToDecision {
+        let mut overall = Vec::new();
+        let mut by_tag: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+        let mut by_month: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+        for question in self.questions.values() {
+            let (created, decided) = match (self.created_at(question), self.decided_at(question)) {
+                (Some(created), Some(decided)) => (created, decided),
+                _ => continue
+            };
+            let elapsed = decided - created;
+            overall.push(elapsed);
+            for tag in &question.tags {
+                by_tag.entry(tag.clone()).or_default().push(elapsed);
+            }
+            by_month.entry(decided.format("%Y-%m").to_string()).or_default().push(elapsed);
+        }
+        let summarize = |groups: BTreeMap<String, Vec<Duration>>| groups.into_iter()
+            .filter_map(|(key, durations)| DurationStats::from_durations(durations).map(|stats| (key, stats)))
+            .collect();
+        TimeToDecision {
+            overall: DurationStats::from_durations(overall),
+            by_tag: summarize(by_tag),
+            by_month: summarize(by_month)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{Duration, Utc};
+    use crate::*;
+    use crate::analytics::*;
+
+    #[test]
+    fn test_duration_percentiles() {
+        let stats = DurationStats::from_durations((1..=20).rev().map(Duration::days).collect()).unwrap();
+        assert_eq!(stats.count, 20);
+        assert_eq!((stats.min, stats.max), (Duration::days(1), Duration::days(20)));
+        assert_eq!((stats.p50, stats.p90, stats.p95), (Duration::days(10), Duration::days(18), Duration::days(19)));
+        assert_eq!(stats.mean, Duration::hours(252));
+        assert!(DurationStats::from_durations(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_time_to_decision_groups() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let infra: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let decided = registry.add_question(Question::new("Use Terraform?".to_string(), infra, HashSet::new(), options), &luke).unwrap();
+        registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.set_question_decision(decided, Decision::new("Yes".to_string(), "Already in use".to_string(), HashSet::new()), 0, &luke).unwrap();
+
+        let times = registry.time_to_decision();
+        assert_eq!(times.overall.unwrap().count, 1);
+        assert_eq!(times.by_tag["infra"].count, 1);
+        assert!(times.by_month.contains_key(&Utc::now().format("%Y-%m").to_string()));
+    }
+
+    #[test]
+    fn test_tag_cooccurrence() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        for tag in &["infra", "ops", "billing"] {
+            registry.add_tag(&tag.to_string(), &luke).unwrap();
+        }
+        let tagged = |tags: &[&str]| -> HashSet<String> { tags.iter().map(|tag| tag.to_string()).collect() };
+        registry.add_question(Question::new("Which CI?".to_string(), tagged(&["infra", "ops"]), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question(Question::new("Which CD?".to_string(), tagged(&["infra", "ops", "billing"]), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question(Question::new("Which cloud?".to_string(), tagged(&["infra"]), HashSet::new(), HashSet::new()), &luke).unwrap();
+
+        let pairs = registry.tag_cooccurrence();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!((pairs[0].first.as_str(), pairs[0].second.as_str(), pairs[0].together), ("infra", "ops", 2));
+        assert!((pairs[0].overlap - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!((pairs[1].first.as_str(), pairs[1].second.as_str()), ("billing", "infra"));
+    }
+
+    #[test]
+    fn test_similar_questions_cluster() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        for tag in &["payments", "search", "mobile"] {
+            registry.add_tag(&tag.to_string(), &luke).unwrap();
+        }
+        let tagged = |tag: &str| -> HashSet<String> { [tag.to_string()].iter().cloned().collect() };
+        for (content, tag) in &[("Which queue for payment events?", "payments"), ("Queue for payment retries?", "payments"),
+                                ("Should payment events use a queue?", "search"), ("Which search engine?", "search"),
+                                ("Which icons in the app?", "mobile"), ("Dark mode in the app?", "mobile")] {
+            registry.add_question(Question::new(content.to_string(), tagged(tag), HashSet::new(), HashSet::new()), &luke).unwrap();
+        }
+
+        let clusters = registry.cluster_questions(0.3);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].questions.len(), 3);
+        assert_eq!(clusters[0].label, vec!["payment", "queue", "#payments"]);
+        assert_eq!(clusters[0].representative.content, "Which queue for payment events?");
+        assert_eq!(clusters[1].label, vec!["#mobile", "app", "in"]);
+        assert!(registry.cluster_questions(0.9).is_empty());
+    }
+
+    #[test]
+    fn test_bucket_starts() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 14).unwrap();
+        assert_eq!(Bucket::Day.start(date), date);
+        assert_eq!(Bucket::Week.start(date), chrono::NaiveDate::from_ymd_opt(2024, 3, 11).unwrap());
+        assert_eq!(Bucket::Month.start(date), chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+    }
+
+    #[test]
+    fn test_activity_histogram() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let infra: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let decided = registry.add_question(Question::new("Use Terraform?".to_string(), infra, HashSet::new(), options), &luke).unwrap();
+        registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.set_question_decision(decided, Decision::new("Yes".to_string(), "Already in use".to_string(), HashSet::new()), 0, &luke).unwrap();
+
+        let today = Bucket::Day.start(Utc::now().date_naive());
+        let histogram = registry.activity_histogram(Bucket::Day, None);
+        assert_eq!(histogram, vec![HistogramBucket { start: today, opened: 2, decided: 1 }]);
+        assert_eq!(registry.activity_histogram(Bucket::Month, Some("infra"))[0].opened, 1);
+        assert!(registry.activity_histogram(Bucket::Week, Some("nope")).is_empty());
+    }
+
+    #[test]
+    fn test_participation() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let solo = registry.add_question(Question::new("Use Terraform?".to_string(), HashSet::new(), HashSet::new(), options.clone()), &luke).unwrap();
+        let joint = registry.add_question(Question::new("Use Vault?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        registry.set_question_owner(solo.clone(), Some(ana.clone()), 0, &luke).unwrap();
+        registry.set_question_decision(solo.clone(), Decision::new("Yes".to_string(), "Already in use".to_string(), HashSet::new()), 1, &ana).unwrap();
+        let both: HashSet<Actor> = [luke.clone()].iter().cloned().collect();
+        registry.set_question_decision(joint, Decision::new("Yes".to_string(), "Audit asks for it".to_string(), both), 0, &ana).unwrap();
+
+        let people = registry.participation();
+        assert_eq!((people[&ana].decisions, people[&ana].owned), (2, 1));
+        assert!(people[&ana].mean_assignment_to_decision.unwrap() >= Duration::zero());
+        assert_eq!((people[&luke].decisions, people[&luke].owned), (1, 0));
+        assert!(people[&luke].mean_assignment_to_decision.is_none());
+
+        let single = registry.single_person_decisions();
+        assert_eq!(single.len(), 1);
+        assert_eq!((&single[0].0, &single[0].1.identifier), (&ana, &solo));
+    }
+}
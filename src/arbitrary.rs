@@ -0,0 +1,200 @@
+//! `proptest` strategies for property-testing code built on decis, behind
+//! the `proptest` feature.
+//!
+//! Besides `Arbitrary` for the model types there is `Operation`, one call
+//! against a registry, so sequences of them can be generated with
+//! `operations` and replayed with `Operation::apply`.
+
+use proptest::collection::{hash_set, vec};
+use proptest::prelude::*;
+use proptest::sample::Index;
+use crate::{Actor, Decision, Question, Registry};
+use crate::priority::Priority;
+
+/// The tags generated questions use, kept few so they overlap.
+pub const TAGS: [&str; 4] = ["infra", "billing", "security", "docs"];
+
+fn tag() -> impl Strategy<Value = String> {
+    proptest::sample::select(&TAGS[..]).prop_map(str::to_string)
+}
+
+fn text() -> impl Strategy<Value = String> {
+    "[A-Za-z][A-Za-z ?]{0,30}"
+}
+
+impl Arbitrary for Actor {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Actor>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        "[a-z]{1,8}".prop_map(|name| Actor::new(&name)).boxed()
+    }
+}
+
+impl Arbitrary for Priority {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Priority>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![Just(Priority::Low), Just(Priority::Normal), Just(Priority::High), Just(Priority::Urgent)].boxed()
+    }
+}
+
+impl Arbitrary for Decision {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Decision>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (text(), text(), hash_set(any::<Actor>(), 0..3))
+            .prop_map(|(choice, rationale, decision_makers)| Decision::new(choice, rationale, decision_makers))
+            .boxed()
+    }
+}
+
+/// Undecided questions, without an identifier until added to a registry.
+impl Arbitrary for Question {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Question>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        (text(), hash_set(tag(), 0..3), hash_set(text(), 0..3), hash_set(text(), 0..4))
+            .prop_map(|(content, tags, context, options)| Question::new(content, tags, context, options))
+            .boxed()
+    }
+}
+
+/// One change to a registry. Questions are picked by position among the
+/// registry's questions ordered by identifier, so a sequence replays the same
+/// way on registries with the same `IdGenerator`.
+#[derive(Clone, Debug)]
+pub enum Operation {
+    AddTag(String),
+    AddQuestion(Box<Question>),
+    Decide { question: Index, option: Index, rationale: String },
+    Comment { question: Index, body: String },
+    SetPriority { question: Index, priority: Priority },
+    Defer { question: Index, reason: String },
+    Reopen { question: Index, reason: String }
+}
+
+impl Arbitrary for Operation {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Operation>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            1 => tag().prop_map(Operation::AddTag),
+            3 => any::<Question>().prop_map(|question| Operation::AddQuestion(Box::new(question))),
+            2 => (any::<Index>(), any::<Index>(), text()).prop_map(|(question, option, rationale)| Operation::Decide { question, option, rationale }),
+            2 => (any::<Index>(), text()).prop_map(|(question, body)| Operation::Comment { question, body }),
+            1 => (any::<Index>(), any::<Priority>()).prop_map(|(question, priority)| Operation::SetPriority { question, priority }),
+            1 => (any::<Index>(), text()).prop_map(|(question, reason)| Operation::Defer { question, reason }),
+            1 => (any::<Index>(), text()).prop_map(|(question, reason)| Operation::Reopen { question, reason })
+        ].boxed()
+    }
+}
+
+/// Up to `max` operations.
+pub fn operations(max: usize) -> impl Strategy<Value = Vec<Operation>> {
+    vec(any::<Operation>(), 0..max)
+}
+
+impl Operation {
+    /// Applies the operation as `actor`. Operations the registry refuses,
+    /// like deciding an already decided question, leave it unchanged.
+    pub fn apply(&self, registry: &mut Registry, actor: &Actor) {
+        let pick = |registry: &Registry, index: &Index| -> Option<Question> {
+            let mut identifiers: Vec<_> = registry.questions.keys().copied().collect();
+            if identifiers.is_empty() {
+                return None;
+            }
+            identifiers.sort();
+            registry.questions.get(&identifiers[index.index(identifiers.len())]).cloned()
+        };
+        match self {
+            Operation::AddTag(tag) => {
+                let _ = registry.add_tag(tag, actor);
+            }
+            Operation::AddQuestion(question) => {
+                let _ = registry.add_question((**question).clone(), actor);
+            }
+            Operation::Decide { question, option, rationale } => {
+                if let Some(question) = pick(registry, question).filter(|question| !question.options.is_empty()) {
+                    let choice = question.options[option.index(question.options.len())].clone();
+                    let decision = Decision::new(choice, rationale.clone(), Default::default());
+                    let _ = registry.set_question_decision(question.identifier.to_string(), decision, question.version, actor);
+                }
+            }
+            Operation::Comment { question, body } => {
+                if let Some(question) = pick(registry, question) {
+                    let _ = registry.add_comment(question.identifier.to_string(), body.clone(), None, actor);
+                }
+            }
+            Operation::SetPriority { question, priority } => {
+                if let Some(question) = pick(registry, question) {
+                    let _ = registry.set_question_priority(question.identifier.to_string(), *priority, question.version, actor);
+                }
+            }
+            Operation::Defer { question, reason } => {
+                if let Some(question) = pick(registry, question) {
+                    let _ = registry.defer_question(question.identifier.to_string(), reason.clone(), None, question.version, actor);
+                }
+            }
+            Operation::Reopen { question, reason } => {
+                if let Some(question) = pick(registry, question) {
+                    let _ = registry.reopen_question(question.identifier.to_string(), reason.clone(), question.version, actor);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use proptest::prelude::*;
+    use crate::*;
+    use crate::arbitrary::*;
+    use crate::clock::SteppingClock;
+    use crate::ids::SeededIds;
+
+    fn replay(operations: &[Operation]) -> Registry {
+        let mut registry = Registry::new();
+        registry.set_clock(SteppingClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap(), chrono::Duration::seconds(1)));
+        registry.set_id_generator(SeededIds::new(1));
+        for operation in operations {
+            operation.apply(&mut registry, &Actor::new("luke"));
+        }
+        registry
+    }
+
+    fn state(registry: &Registry) -> Vec<(String, u64, status::QuestionStatus, usize)> {
+        let mut state: Vec<_> = registry.questions.values()
+            .map(|question| (question.identifier.to_string(), question.version, question.status(), question.history().len()))
+            .collect();
+        state.sort_by(|a, b| a.0.cmp(&b.0));
+        state
+    }
+
+    proptest! {
+        #[test]
+        fn replaying_operations_reproduces_the_registry(operations in operations(30)) {
+            let first = replay(&operations);
+            let second = replay(&operations);
+            prop_assert_eq!(state(&first), state(&second));
+            prop_assert_eq!(serde_json::to_string(first.audit_log().entries()).unwrap(), serde_json::to_string(second.audit_log().entries()).unwrap());
+        }
+
+        #[test]
+        fn histories_mirror_the_audit_log(operations in operations(30)) {
+            let registry = replay(&operations);
+            for question in registry.questions.values() {
+                let audited: Vec<_> = registry.audit_log().entries().iter().filter(|entry| entry.question == Some(question.identifier)).map(|entry| (entry.at, &entry.action)).collect();
+                let history: Vec<_> = question.history().iter().map(|change| (change.at, &change.action)).collect();
+                prop_assert_eq!(audited, history);
+            }
+            let reloaded = Registry::deserialize_json(&registry.serialize_json()).unwrap();
+            prop_assert_eq!(state(&registry), state(&reloaded));
+        }
+    }
+}
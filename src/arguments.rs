@@ -0,0 +1,152 @@
+//! Pros and cons raised for a question's options, attributed to whoever
+//! raised them.
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use crate::{Actor, GetQuestionError, Question, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stance {
+    Pro,
+    Con
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Argument {
+    pub text: String,
+    pub author: Actor,
+    pub added_at: DateTime<Utc>,
+    /// How much the argument matters, at least 1.
+    pub weight: u8
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProsCons {
+    pub pros: Vec<Argument>,
+    pub cons: Vec<Argument>
+}
+
+#[derive(Debug)]
+pub enum AddArgumentError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    UnknownOption(String),
+    ZeroWeight
+}
+
+impl Localized for AddArgumentError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            AddArgumentError::Question(error) => error.localize(catalog),
+            AddArgumentError::Conflict(question) => messages::conflict(catalog, question),
+            AddArgumentError::UnknownOption(option) => messages::text(catalog, "option.unknown", &[("option", option.clone())]),
+            AddArgumentError::ZeroWeight => messages::text(catalog, "argument.zero-weight", &[])
+        }
+    }
+}
+
+impl Coded for AddArgumentError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AddArgumentError::Question(error) => error.code(),
+            AddArgumentError::Conflict(_) => ErrorCode::VersionConflict,
+            AddArgumentError::UnknownOption(_) => ErrorCode::UnknownOption,
+            AddArgumentError::ZeroWeight => ErrorCode::ZeroWeight
+        }
+    }
+}
+
+messages::display_localized!(AddArgumentError);
+
+impl From<UpdateQuestionError> for AddArgumentError {
+    fn from(error: UpdateQuestionError) -> AddArgumentError {
+        match error {
+            UpdateQuestionError::Question(error) => AddArgumentError::Question(error),
+            UpdateQuestionError::Conflict(current) => AddArgumentError::Conflict(current)
+        }
+    }
+}
+
+fn strongest(arguments: &[Argument], count: usize) -> Vec<&Argument> {
+    let mut sorted: Vec<&Argument> = arguments.iter().collect();
+    // Stable, so equally weighted arguments stay in the order they were raised.
+    sorted.sort_by_key(|argument| std::cmp::Reverse(argument.weight));
+    sorted.truncate(count);
+    sorted
+}
+
+impl Question {
+    pub fn get_arguments(&self, option: &str) -> ProsCons {
+        self.arguments.get(option).cloned().unwrap_or_default()
+    }
+
+    /// Up to `count` of the heaviest pros and cons for an option.
+    pub fn strongest_arguments(&self, option: &str, count: usize) -> (Vec<&Argument>, Vec<&Argument>) {
+        match self.arguments.get(option) {
+            Some(arguments) => (strongest(&arguments.pros, count), strongest(&arguments.cons, count)),
+            None => (Vec::new(), Vec::new())
+        }
+    }
+}
+
+impl Registry {
+    /// Records an argument for or against one of the question's options, by
+    /// the acting user, and returns the question's new version.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_argument(&mut self, identifier: String, option: &str, stance: Stance, text: String, weight: u8, expected_version: u64, actor: &Actor) -> Result<u64, AddArgumentError> {
+        if weight == 0 {
+            return Result::Err(AddArgumentError::ZeroWeight);
+        }
+        let now = self.now();
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        if !question.has_option(option) {
+            return Result::Err(AddArgumentError::UnknownOption(option.to_string()));
+        }
+        let uuid = question.identifier;
+        let argument = Argument { text, author: actor.clone(), added_at: now, weight };
+        let arguments = question.arguments.entry(option.to_string()).or_default();
+        match stance {
+            Stance::Pro => arguments.pros.push(argument),
+            Stance::Con => arguments.cons.push(argument)
+        }
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::ArgumentAdded(option.to_string()));
+        Result::Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::arguments::*;
+
+    #[test]
+    fn test_arguments_are_attributed_and_ranked() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        let mut options: HashSet<String> = HashSet::new();
+        options.insert("Monorepo".to_string());
+        let identifier = registry.add_question(Question::new("Repository layout?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        registry.add_argument(identifier.clone(), "Monorepo", Stance::Pro, "Atomic changes".to_string(), 2, 0, &luke).unwrap();
+        registry.add_argument(identifier.clone(), "Monorepo", Stance::Pro, "One CI config".to_string(), 1, 1, &ana).unwrap();
+        registry.add_argument(identifier.clone(), "Monorepo", Stance::Con, "Slow clones".to_string(), 3, 2, &ana).unwrap();
+
+        let question = registry.get_question(identifier.clone()).unwrap();
+        let (pros, cons) = question.strongest_arguments("Monorepo", 1);
+        assert_eq!(pros[0].text, "Atomic changes");
+        assert_eq!(pros[0].author, luke);
+        assert_eq!(cons[0].author, ana);
+        assert_eq!(question.get_arguments("Monorepo").pros.len(), 2);
+
+        match registry.add_argument(identifier, "Polyrepo", Stance::Con, "Drift".to_string(), 1, 3, &luke) {
+            Err(AddArgumentError::UnknownOption(_)) => (),
+            _ => panic!("Arguments about unknown options should be rejected")
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Supporting material referenced from questions: links, files and named
+//! documents. Files can be pinned to a SHA-256 of their contents so a changed
+//! benchmark or spreadsheet is noticed, with the `hashing` feature.
+
+#[cfg(feature = "hashing")]
+use std::fs::File;
+#[cfg(feature = "hashing")]
+use std::io::Read;
+#[cfg(feature = "hashing")]
+use std::path::Path;
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "hashing")]
+use sha2::{Digest, Sha256};
+use crate::{Actor, Question, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Reference {
+    Url(String),
+    File { path: PathBuf, sha256: Option<String> },
+    /// A document known by title, e.g. a wiki page or a printed report.
+    Document(String)
+}
+
+#[cfg(feature = "hashing")]
+impl Reference {
+    /// A file reference pinned to the file's current contents.
+    pub fn hashed_file<P: AsRef<Path>>(path: P) -> std::io::Result<Reference> {
+        let sha256 = hash_file(path.as_ref())?;
+        Result::Ok(Reference::File { path: path.as_ref().to_path_buf(), sha256: Some(sha256) })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub reference: Reference,
+    pub description: Option<String>,
+    pub added_by: Actor,
+    pub added_at: DateTime<Utc>
+}
+
+#[cfg(feature = "hashing")]
+impl Attachment {
+    /// Whether a hashed file still has the contents it was attached with.
+    /// `None` for references without a hash.
+    pub fn verify(&self) -> std::io::Result<Option<bool>> {
+        match &self.reference {
+            Reference::File { path, sha256: Some(expected) } => Result::Ok(Some(&hash_file(path)? == expected)),
+            _ => Result::Ok(None)
+        }
+    }
+}
+
+#[cfg(feature = "hashing")]
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut contents = Vec::new();
+    File::open(path)?.read_to_end(&mut contents)?;
+    Result::Ok(Sha256::digest(&contents).iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+impl Question {
+    pub fn get_attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+}
+
+impl Registry {
+    /// Attaches a reference on behalf of the acting user and returns the
+    /// question's new version.
+    pub fn add_attachment(&mut self, identifier: String, reference: Reference, description: Option<String>, expected_version: u64, actor: &Actor) -> Result<u64, UpdateQuestionError> {
+        let now = self.now();
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        let label = match &reference {
+            Reference::Url(url) => url.clone(),
+            Reference::File { path, .. } => path.display().to_string(),
+            Reference::Document(title) => title.clone()
+        };
+        question.attachments.push(Attachment { reference, description, added_by: actor.clone(), added_at: now });
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::AttachmentAdded(label));
+        Result::Ok(version)
+    }
+}
+
+#[cfg(all(test, feature = "hashing"))]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs;
+    use crate::*;
+    use crate::attachments::*;
+
+    #[test]
+    fn test_hashed_files_detect_changes() {
+        let path = std::env::temp_dir().join(format!("decis-attachment-{}.csv", uuid::Uuid::new_v4()));
+        fs::write(&path, "latency,42\n").unwrap();
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(Question::new("Which cache?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_attachment(identifier.clone(), Reference::hashed_file(&path).unwrap(), Some("Benchmark".to_string()), 0, &luke).unwrap();
+        registry.add_attachment(identifier.clone(), Reference::Url("https://example.com/spike".to_string()), None, 1, &luke).unwrap();
+
+        let question = registry.get_question(identifier).unwrap();
+        let attachments = question.get_attachments();
+        assert_eq!(attachments[0].verify().unwrap(), Some(true));
+        assert_eq!(attachments[1].verify().unwrap(), None);
+        fs::write(&path, "latency,99\n").unwrap();
+        assert_eq!(attachments[0].verify().unwrap(), Some(false));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::Actor;
+use crate::priority::Priority;
+use crate::status::QuestionStatus;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AuditAction {
+    TagAdded(String),
+    TagRenamed { from: String, to: String },
+    SettingsChanged,
+    QuestionAdded,
+    /// Copied or merged in from another registry, see `sync` and `merge`.
+    QuestionSynced,
+    /// With the content it replaced.
+    ContentEdited(String),
+    ContextAdded(String),
+    ContextRetracted(String),
+    OptionAdded(String),
+    OptionsReordered,
+    DecisionSet(String),
+    StatusChanged(QuestionStatus),
+    /// With the reason given.
+    QuestionReopened(String),
+    OwnerChanged(Option<Actor>),
+    PrioritySet(Priority),
+    /// Someone's identity was removed; deliberately doesn't say whose.
+    PersonErased,
+    QuestionTagged(String),
+    QuestionUntagged(String),
+    OutcomeSet(String),
+    BranchesSet(String),
+    EstimateSet(String),
+    CriterionAdded(String),
+    /// Superseded by `CriterionScored`; older histories still hold it.
+    OptionScored(String),
+    CriterionScored { option: String, criterion: String },
+    ArgumentAdded(String),
+    CommentAdded(String),
+    CommentEdited(String),
+    AttachmentAdded(String),
+    /// With the system the identifier is from.
+    ExternalIdSet(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub actor: Actor,
+    pub question: Option<Uuid>,
+    pub action: AuditAction,
+}
+
+/// Which audit entries to export. The default matches every entry; each
+/// field set narrows it further.
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    /// Inclusive.
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub until: Option<DateTime<Utc>>,
+    /// Entries by any of these actors, when not empty.
+    pub actors: Vec<Actor>,
+    /// Entries about any of these questions, when not empty.
+    pub questions: Vec<Uuid>
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.from.is_none_or(|from| entry.at >= from)
+            && self.until.is_none_or(|until| entry.at < until)
+            && (self.actors.is_empty() || self.actors.contains(&entry.actor))
+            && (self.questions.is_empty() || entry.question.is_some_and(|question| self.questions.contains(&question)))
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn new() -> AuditLog {
+        AuditLog { entries: Vec::new() }
+    }
+
+    pub(crate) fn from_entries(entries: Vec<AuditEntry>) -> AuditLog {
+        AuditLog { entries }
+    }
+
+    pub(crate) fn record(&mut self, at: DateTime<Utc>, actor: &Actor, question: Option<Uuid>, action: AuditAction) {
+        self.entries.push(AuditEntry {
+            at,
+            actor: actor.clone(),
+            question,
+            action
+        });
+    }
+
+    /// Entries in the order they were recorded.
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    pub fn entries_by<'a>(&'a self, actor: &'a Actor) -> impl Iterator<Item = &'a AuditEntry> + 'a {
+        self.entries.iter().filter(move |entry| &entry.actor == actor)
+    }
+
+    /// Writes the entries matching `filter` to `writer`, one JSON object per
+    /// line, and returns how many were written.
+    pub fn export_jsonl<W: Write>(&self, mut writer: W, filter: &AuditFilter) -> std::io::Result<usize> {
+        let mut written = 0;
+        for entry in self.entries.iter().filter(|entry| filter.matches(entry)) {
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+            written += 1;
+        }
+        writer.flush()?;
+        Result::Ok(written)
+    }
+
+    /// Drops entries recorded before `before`, first appending them to
+    /// `archive` as JSON lines when given. Returns how many entries were
+    /// removed. Nothing is dropped if writing the archive fails.
+    ///
+    /// Entries are judged by their timestamps rather than their place in the
+    /// log, which merges and clock changes can leave out of time order.
+    pub fn compact(&mut self, before: DateTime<Utc>, archive: Option<&Path>) -> std::io::Result<usize> {
+        let (old, kept): (Vec<AuditEntry>, Vec<AuditEntry>) = self.entries.iter().cloned().partition(|entry| entry.at < before);
+        if old.is_empty() {
+            return Result::Ok(0);
+        }
+        if let Some(path) = archive {
+            let mut lines = String::new();
+            for entry in &old {
+                lines.push_str(&serde_json::to_string(entry)?);
+                lines.push('\n');
+            }
+            OpenOptions::new().create(true).append(true).open(path)?.write_all(lines.as_bytes())?;
+        }
+        self.entries = kept;
+        Result::Ok(old.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::Utc;
+    use crate::*;
+    use crate::audit::*;
+    use crate::clock::FixedClock;
+
+    #[test]
+    fn test_compact_archives_old_entries() {
+        let mut registry = Registry::new();
+        registry.add_tag(&"Old".to_string(), &Actor::new("luke")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let cutoff = Utc::now();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        registry.add_question(Question::new("New?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &Actor::new("luke")).unwrap();
+
+        let archive = std::env::temp_dir().join(format!("decis_test_audit_archive_{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&archive);
+        assert_eq!(registry.compact(cutoff, Some(&archive)).unwrap(), 1);
+
+        assert_eq!(registry.audit_log().entries().len(), 1);
+        let archived = std::fs::read_to_string(&archive).unwrap();
+        assert_eq!(archived.lines().count(), 1);
+        assert!(archived.contains("Old"));
+    }
+
+    #[test]
+    fn test_compact_goes_by_timestamp() {
+        let luke = Actor::new("luke");
+        let start = Utc::now();
+        let clock = FixedClock::new(start);
+        let mut registry = Registry::new();
+        registry.set_clock(clock.clone());
+        registry.add_tag(&"New".to_string(), &luke).unwrap();
+        // The clock was wrong, and has been set back.
+        clock.set(start - chrono::Duration::hours(1));
+        registry.add_tag(&"Old".to_string(), &luke).unwrap();
+
+        assert_eq!(registry.compact(start - chrono::Duration::minutes(30), None).unwrap(), 1);
+        assert_eq!(registry.audit_log().entries()[0].action, AuditAction::TagAdded("New".to_string()));
+    }
+
+    #[test]
+    fn test_export_jsonl_filters_entries() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let identifier = registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &ana).unwrap();
+        registry.add_comment(identifier.clone(), "Any".to_string(), None, &luke).unwrap();
+
+        let filter = AuditFilter { actors: vec![luke], questions: vec![uuid::Uuid::parse_str(&identifier).unwrap()], ..AuditFilter::default() };
+        let mut output = Vec::new();
+        assert_eq!(registry.audit_log().export_jsonl(&mut output, &filter).unwrap(), 1);
+        let lines: Vec<serde_json::Value> = String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines[0]["actor"], "luke");
+        assert!(lines[0]["action"]["CommentAdded"].is_string());
+
+        let future = AuditFilter { from: Some(Utc::now() + chrono::Duration::days(1)), ..AuditFilter::default() };
+        assert_eq!(registry.audit_log().export_jsonl(std::io::sink(), &future).unwrap(), 0);
+    }
+}
@@ -0,0 +1,194 @@
+//! Compressed, checksummed registry backups.
+//!
+//! A backup is `MAGIC | sha256(cbor) | gzip(cbor)`, where `cbor` is the
+//! registry as written by `Registry::serialize_cbor`. Restoring verifies the
+//! checksum before the registry is decoded.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+use crate::Registry;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+use crate::migrations::{self, LoadError, Versioned};
+
+const MAGIC: &[u8; 8] = b"DECISBK1";
+const CHECKSUM_LEN: usize = 32;
+/// Extension used by `timestamped_path` and recognised by `keep_last`.
+pub const EXTENSION: &str = "decisbak";
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(std::io::Error),
+    Serialization(serde_cbor::Error),
+    /// The file is not a backup, is truncated, or doesn't decompress.
+    InvalidFormat,
+    /// The decompressed contents don't match the recorded checksum.
+    ChecksumMismatch,
+    Load(LoadError)
+}
+
+impl Localized for BackupError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            BackupError::Io(error) => messages::io(catalog, error),
+            BackupError::Serialization(error) => messages::encoding(catalog, error),
+            BackupError::InvalidFormat => messages::text(catalog, "backup.invalid-format", &[]),
+            BackupError::ChecksumMismatch => messages::text(catalog, "backup.checksum-mismatch", &[]),
+            BackupError::Load(error) => error.localize(catalog)
+        }
+    }
+}
+
+impl Coded for BackupError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            BackupError::Io(_) => ErrorCode::Io,
+            BackupError::Serialization(_) => ErrorCode::Encoding,
+            BackupError::InvalidFormat => ErrorCode::InvalidBackup,
+            BackupError::ChecksumMismatch => ErrorCode::BackupChecksumMismatch,
+            BackupError::Load(error) => error.code()
+        }
+    }
+}
+
+messages::display_localized!(BackupError);
+
+impl From<std::io::Error> for BackupError {
+    fn from(error: std::io::Error) -> BackupError {
+        BackupError::Io(error)
+    }
+}
+
+impl From<serde_cbor::Error> for BackupError {
+    fn from(error: serde_cbor::Error) -> BackupError {
+        BackupError::Serialization(error)
+    }
+}
+
+impl From<LoadError> for BackupError {
+    fn from(error: LoadError) -> BackupError {
+        BackupError::Load(error)
+    }
+}
+
+impl Registry {
+    pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<(), BackupError> {
+        let contents = serde_cbor::to_vec(&Versioned::new(self))?;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&contents)?;
+        let compressed = encoder.finish()?;
+
+        let mut archive = Vec::with_capacity(MAGIC.len() + CHECKSUM_LEN + compressed.len());
+        archive.extend_from_slice(MAGIC);
+        archive.extend_from_slice(&Sha256::digest(&contents));
+        archive.extend_from_slice(&compressed);
+        fs::write(path, archive)?;
+        Result::Ok(())
+    }
+
+    pub fn restore<P: AsRef<Path>>(path: P) -> Result<Registry, BackupError> {
+        let archive = fs::read(path)?;
+        if archive.len() < MAGIC.len() + CHECKSUM_LEN || !archive.starts_with(MAGIC) {
+            return Result::Err(BackupError::InvalidFormat);
+        }
+        let (checksum, compressed) = archive[MAGIC.len()..].split_at(CHECKSUM_LEN);
+        let mut contents = Vec::new();
+        GzDecoder::new(compressed)
+            .read_to_end(&mut contents)
+            .map_err(|_| BackupError::InvalidFormat)?;
+        if Sha256::digest(&contents).as_slice() != checksum {
+            return Result::Err(BackupError::ChecksumMismatch);
+        }
+        Result::Ok(migrations::from_cbor(&contents)?)
+    }
+}
+
+/// A path in `directory` named after the current UTC time, so that backups
+/// sort chronologically by name.
+pub fn timestamped_path<P: AsRef<Path>>(directory: P) -> PathBuf {
+    directory.as_ref().join(format!("registry-{}.{}", Utc::now().format("%Y%m%dT%H%M%S%.3fZ"), EXTENSION))
+}
+
+/// Deletes all but the `n` newest backups (by name) in `directory`, returning
+/// the paths that were removed. Files without the backup extension are left alone.
+pub fn keep_last<P: AsRef<Path>>(directory: P, n: usize) -> std::io::Result<Vec<PathBuf>> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == EXTENSION))
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(n);
+    let removed: Vec<PathBuf> = backups.drain(..excess).collect();
+    for path in &removed {
+        fs::remove_file(path)?;
+    }
+    Result::Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::*;
+    use crate::backup::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("decis_test_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.add_tag(&"Infra".to_string(), &Actor::new("luke")).unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_backup_round_trip() {
+        let path = timestamped_path(temp_dir("backup_round_trip"));
+        registry().backup(&path).unwrap();
+        assert!(Registry::restore(&path).unwrap().get_tags().contains("Infra"));
+    }
+
+    #[test]
+    fn test_corrupted_backup_is_rejected() {
+        let path = temp_dir("backup_corrupted").join("registry.decisbak");
+        registry().backup(&path).unwrap();
+        let archive = fs::read(&path).unwrap();
+        let mut checksum = archive.clone();
+        checksum[MAGIC.len()] ^= 0xff;
+        fs::write(&path, checksum).unwrap();
+        match Registry::restore(&path) {
+            Err(BackupError::ChecksumMismatch) => (),
+            _ => panic!("A backup with the wrong checksum should fail its checksum")
+        }
+
+        let mut compressed = archive;
+        compressed[MAGIC.len() + CHECKSUM_LEN] ^= 0xff;
+        fs::write(&path, compressed).unwrap();
+        match Registry::restore(&path) {
+            Err(BackupError::InvalidFormat) => (),
+            _ => panic!("A backup that doesn't decompress should be invalid")
+        }
+    }
+
+    #[test]
+    fn test_keep_last_removes_oldest() {
+        let directory = temp_dir("backup_rotation");
+        for name in ["registry-1.decisbak", "registry-2.decisbak", "registry-3.decisbak", "notes.txt"].iter() {
+            fs::write(directory.join(name), b"").unwrap();
+        }
+
+        let removed = keep_last(&directory, 2).unwrap();
+        assert_eq!(removed, vec![directory.join("registry-1.decisbak")]);
+        assert!(directory.join("registry-3.decisbak").exists());
+        assert!(directory.join("notes.txt").exists());
+    }
+}
@@ -0,0 +1,168 @@
+//! An iCalendar feed of the dates questions need attention on.
+//!
+//! Each expiring decision gets an all-day event on its expiry date, and each
+//! deferred question one on the day it is due back. Calendar apps can
+//! subscribe to the feed, so reviews land where people will see them.
+//! Event UIDs are built from question identifiers, so a refreshed feed moves
+//! events instead of duplicating them.
+
+use chrono::{Duration, NaiveDate};
+use crate::{Question, Registry};
+use crate::status::QuestionStatus;
+
+/// The longest content line iCalendar allows, in bytes.
+const MAX_LINE: usize = 75;
+
+/// `text` escaped for an iCalendar text value.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n").replace('\r', "")
+}
+
+/// Appends `line` folded to `MAX_LINE` bytes, ended with CRLF.
+fn push_line(calendar: &mut String, line: &str) {
+    let mut width = 0;
+    for character in line.chars() {
+        if width + character.len_utf8() > MAX_LINE {
+            calendar.push_str("\r\n ");
+            // The leading space counts towards the continued line.
+            width = 1;
+        }
+        calendar.push(character);
+        width += character.len_utf8();
+    }
+    calendar.push_str("\r\n");
+}
+
+struct Event<'a> {
+    question: &'a Question,
+    kind: &'static str,
+    date: NaiveDate,
+    summary: String,
+    description: String
+}
+
+impl Registry {
+    fn calendar_events(&self) -> Vec<Event<'_>> {
+        let mut events = Vec::new();
+        for question in self.questions.values() {
+            match question.status() {
+                QuestionStatus::Decided => {
+                    let decision = question.decision.as_ref().expect("decided questions have a decision");
+                    if let Some(expires_at) = decision.expires_at {
+                        events.push(Event {
+                            question,
+                            kind: "review",
+                            date: expires_at.date_naive(),
+                            summary: self.text("calendar.review", &[("question", question.content.clone())]),
+                            description: format!("{}\n\n{}", self.text("calendar.review-description", &[("choice", decision.choice.clone())]), decision.rationale)
+                        });
+                    }
+                }
+                QuestionStatus::Deferred => {
+                    if let Some(deferral) = &question.deferral {
+                        if let Some(until) = deferral.until {
+                            events.push(Event {
+                                question,
+                                kind: "resume",
+                                date: until,
+                                summary: self.text("calendar.resume", &[("question", question.content.clone())]),
+                                description: self.text("calendar.resume-description", &[("reason", deferral.reason.clone())])
+                            });
+                        }
+                    }
+                }
+                _ => ()
+            }
+        }
+        events.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.question.identifier.cmp(&b.question.identifier)));
+        events
+    }
+
+    /// The feed, without links back to the questions.
+    pub fn to_ics(&self) -> String {
+        self.calendar(None)
+    }
+
+    /// The feed, with each event linking to `base_url` followed by the
+    /// question's identifier.
+    pub fn to_ics_with_links(&self, base_url: &str) -> String {
+        self.calendar(Some(base_url))
+    }
+
+    fn calendar(&self, base_url: Option<&str>) -> String {
+        let stamp = self.now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut calendar = String::new();
+        for line in &["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//decis//decis//EN", "CALSCALE:GREGORIAN"] {
+            push_line(&mut calendar, line);
+        }
+        for event in self.calendar_events() {
+            let identifier = event.question.identifier;
+            push_line(&mut calendar, "BEGIN:VEVENT");
+            push_line(&mut calendar, &format!("UID:{}-{}@decis", identifier, event.kind));
+            push_line(&mut calendar, &format!("DTSTAMP:{}", stamp));
+            push_line(&mut calendar, &format!("DTSTART;VALUE=DATE:{}", event.date.format("%Y%m%d")));
+            push_line(&mut calendar, &format!("DTEND;VALUE=DATE:{}", (event.date + Duration::days(1)).format("%Y%m%d")));
+            push_line(&mut calendar, &format!("SUMMARY:{}", escape(&event.summary)));
+            push_line(&mut calendar, &format!("DESCRIPTION:{}", escape(&event.description)));
+            if !event.question.tags.is_empty() {
+                let mut tags: Vec<String> = event.question.tags.iter().map(|tag| escape(tag)).collect();
+                tags.sort();
+                push_line(&mut calendar, &format!("CATEGORIES:{}", tags.join(",")));
+            }
+            if let Some(base_url) = base_url {
+                push_line(&mut calendar, &format!("URL:{}{}", base_url, identifier));
+            }
+            push_line(&mut calendar, "END:VEVENT");
+        }
+        push_line(&mut calendar, "END:VCALENDAR");
+        calendar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use crate::*;
+    use crate::calendar::*;
+    use crate::clock::FixedClock;
+
+    #[test]
+    fn test_long_lines_are_folded() {
+        let mut calendar = String::new();
+        push_line(&mut calendar, &format!("SUMMARY:{}", "é".repeat(40)));
+        let lines: Vec<&str> = calendar.split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE));
+        assert!(lines[1].starts_with(' '));
+    }
+
+    #[test]
+    fn test_reviews_and_resumptions_become_events() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.set_clock(FixedClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap()));
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let tags: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Kafka".to_string()].iter().cloned().collect();
+        let queue = registry.add_question(Question::new("Which queue, for now?".to_string(), tags, HashSet::new(), options), &luke).unwrap();
+        let expiry = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        registry.set_question_decision(queue.clone(), Decision::new("Kafka".to_string(), "Cheapest".to_string(), HashSet::new()).with_expiry(expiry), 0, &luke).unwrap();
+        let cache = registry.add_question(Question::new("Which cache?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.defer_question(cache.clone(), "After the launch".to_string(), NaiveDate::from_ymd_opt(2024, 2, 1), 0, &luke).unwrap();
+        registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+
+        let feed = registry.to_ics_with_links("https://decis.example/questions/");
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n") && feed.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(feed.matches("BEGIN:VEVENT").count(), 2);
+        let resume = feed.find(&format!("UID:{}-resume@decis", cache)).unwrap();
+        let review = feed.find(&format!("UID:{}-review@decis", queue)).unwrap();
+        assert!(resume < review);
+        assert!(feed.contains("DTSTART;VALUE=DATE:20240301\r\nDTEND;VALUE=DATE:20240302\r\n"));
+        assert!(feed.contains("SUMMARY:Review: Which queue\\, for now?\r\n"));
+        assert!(feed.contains("DESCRIPTION:Decided: Kafka\\n\\nCheapest\r\nCATEGORIES:infra\r\n"));
+        assert!(feed.contains("DTSTAMP:20240101T090000Z"));
+        assert!(feed.contains(&format!("URL:https://decis.example/questions/{}", queue)));
+        assert!(!registry.to_ics().contains("URL:"));
+    }
+}
@@ -0,0 +1,125 @@
+//! Where the registry gets the current time from.
+//!
+//! Every timestamp the registry records, and every query that defaults to
+//! "now", reads the registry's clock. It is the system clock unless replaced
+//! with `Registry::set_clock`, which tests use to control time with a
+//! `FixedClock` or `SteppingClock`.
+
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use crate::Registry;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+pub(crate) fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// Always reads the time it was last set to. Clones share the time, so a
+/// test can keep one to move the clock of a registry it gave another.
+#[derive(Clone, Debug)]
+pub struct FixedClock {
+    now: Arc<Mutex<DateTime<Utc>>>
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> FixedClock {
+        FixedClock { now: Arc::new(Mutex::new(now)) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().expect("clock lock poisoned") = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().expect("clock lock poisoned") += by;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().expect("clock lock poisoned")
+    }
+}
+
+/// Moves forward by `step` every time it is read, starting at `start`, so
+/// consecutive timestamps are distinct and evenly spaced. Clones share the
+/// time.
+#[derive(Clone, Debug)]
+pub struct SteppingClock {
+    next: Arc<Mutex<DateTime<Utc>>>,
+    step: Duration
+}
+
+impl SteppingClock {
+    pub fn new(start: DateTime<Utc>, step: Duration) -> SteppingClock {
+        SteppingClock { next: Arc::new(Mutex::new(start)), step }
+    }
+}
+
+impl Clock for SteppingClock {
+    fn now(&self) -> DateTime<Utc> {
+        let mut next = self.next.lock().expect("clock lock poisoned");
+        let now = *next;
+        *next += self.step;
+        now
+    }
+}
+
+impl Registry {
+    /// Replaces the registry's clock. It isn't persisted; a loaded registry
+    /// uses the system clock.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// The current time by the registry's clock.
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{Duration, TimeZone, Utc};
+    use crate::*;
+    use crate::clock::*;
+
+    #[test]
+    fn test_registry_times_follow_its_clock() {
+        let luke = Actor::new("luke");
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        let clock = FixedClock::new(start);
+        let mut registry = Registry::new();
+        registry.set_clock(clock.clone());
+        let identifier = registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        assert_eq!(registry.audit_log().entries()[0].at, start);
+
+        clock.advance(Duration::days(10));
+        assert_eq!(registry.stale_questions(Duration::days(7))[0].identifier, identifier);
+        registry.add_comment(identifier.clone(), "GitHub Actions?".to_string(), None, &luke).unwrap();
+        assert!(registry.stale_questions(Duration::days(7)).is_empty());
+        assert_eq!(registry.get_question(identifier).unwrap().history().last().unwrap().at, start + Duration::days(10));
+    }
+
+    #[test]
+    fn test_stepping_clock_advances_on_every_read() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        let clock = SteppingClock::new(start, Duration::minutes(1));
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.clone().now(), start + Duration::minutes(1));
+        assert_eq!(clock.now(), start + Duration::minutes(2));
+    }
+}
@@ -0,0 +1,253 @@
+//! Stable codes for errors, for programs to branch on.
+//!
+//! Every error variant has an `ErrorCode`, such as `DECIS-TAG-001`
+//! (`TagAlreadyExists`). Variants that only wrap another error, like
+//! `UpdateQuestionError::Question`, take the wrapped error's code. Codes are
+//! never renumbered or reused: new ones are added at the end of their area.
+//! An `ErrorReport` holds what an HTTP or RPC layer needs to answer with.
+
+use serde::Serialize;
+use crate::{AddQuestionError, AddTagErrors, BulkTagError, GetQuestionError, ReorderOptionsError, SetDecisionError,
+    SetOptionOutcomeError, SetQuestionDecisionError, UpdateQuestionError};
+use crate::messages::{Catalog, Localized};
+
+macro_rules! error_codes {
+    ($($name:ident => $code:literal, $status:literal;)+) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum ErrorCode {
+            $($name),+
+        }
+
+        impl ErrorCode {
+            pub const ALL: &'static [ErrorCode] = &[$(ErrorCode::$name),+];
+
+            /// The code itself, such as `DECIS-TAG-001`.
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name => $code),+
+                }
+            }
+
+            /// The variant's name, such as `TagAlreadyExists`.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(ErrorCode::$name => stringify!($name)),+
+                }
+            }
+
+            /// The HTTP status an API should answer with.
+            pub fn http_status(&self) -> u16 {
+                match self {
+                    $(ErrorCode::$name => $status),+
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    Io => "DECIS-IO-001", 500;
+    Encoding => "DECIS-IO-002", 500;
+    InvalidQuestionId => "DECIS-QUESTION-001", 400;
+    QuestionNotFound => "DECIS-QUESTION-002", 404;
+    QuestionAlreadyExists => "DECIS-QUESTION-003", 409;
+    QuestionUsesUnknownTags => "DECIS-QUESTION-004", 422;
+    QuestionRejected => "DECIS-QUESTION-005", 422;
+    VersionConflict => "DECIS-QUESTION-006", 409;
+    DuplicateExternalId => "DECIS-QUESTION-007", 409;
+    DecisionAlreadyExists => "DECIS-DECISION-001", 409;
+    QuestionNotOpen => "DECIS-DECISION-002", 409;
+    DecisionRejected => "DECIS-DECISION-003", 422;
+    UnknownOption => "DECIS-OPTION-001", 422;
+    NotAPermutation => "DECIS-OPTION-002", 422;
+    InvalidDistribution => "DECIS-OPTION-003", 422;
+    TagAlreadyExists => "DECIS-TAG-001", 409;
+    TagNotFound => "DECIS-TAG-002", 404;
+    UnknownTags => "DECIS-TAG-003", 422;
+    InvalidTag => "DECIS-TAG-004", 422;
+    TagCollisions => "DECIS-TAG-005", 409;
+    ZeroWeight => "DECIS-ARGUMENT-001", 422;
+    InvalidCommentId => "DECIS-COMMENT-001", 400;
+    CommentNotFound => "DECIS-COMMENT-002", 404;
+    NotCommentAuthor => "DECIS-COMMENT-003", 403;
+    ContextNotFound => "DECIS-CONTEXT-001", 404;
+    ContextAlreadyRetracted => "DECIS-CONTEXT-002", 409;
+    CriterionAlreadyExists => "DECIS-CRITERION-001", 409;
+    InvalidScale => "DECIS-CRITERION-002", 422;
+    UnknownCriterion => "DECIS-CRITERION-003", 422;
+    ScoreOutOfScale => "DECIS-CRITERION-004", 422;
+    InvalidEstimate => "DECIS-ESTIMATE-001", 422;
+    InvalidTransition => "DECIS-STATUS-001", 409;
+    UseSetDecision => "DECIS-STATUS-002", 422;
+    UseDeferQuestion => "DECIS-STATUS-003", 422;
+    UseCancelQuestion => "DECIS-STATUS-004", 422;
+    UseReopenQuestion => "DECIS-STATUS-005", 422;
+    ReasonRequired => "DECIS-STATUS-006", 422;
+    NoOptions => "DECIS-PICK-001", 422;
+    InvalidWeights => "DECIS-PICK-002", 422;
+    InvalidProbabilities => "DECIS-BRANCH-001", 422;
+    UnknownFollowUp => "DECIS-BRANCH-002", 422;
+    FollowUpCycle => "DECIS-BRANCH-003", 422;
+    InvalidCursor => "DECIS-PAGE-001", 400;
+    ZeroPageLimit => "DECIS-PAGE-002", 400;
+    NewerFormat => "DECIS-LOAD-001", 500;
+    InvalidStreamLine => "DECIS-STREAM-001", 400;
+    UnsupportedPolicyFormat => "DECIS-POLICY-001", 400;
+    InvalidBackup => "DECIS-BACKUP-001", 400;
+    BackupChecksumMismatch => "DECIS-BACKUP-002", 400;
+    InvalidEncryptedFile => "DECIS-CRYPTO-001", 400;
+    InvalidKeyFile => "DECIS-CRYPTO-002", 400;
+    KeyDerivationFailed => "DECIS-CRYPTO-003", 500;
+    DecryptionFailed => "DECIS-CRYPTO-004", 403;
+    StorageUnavailable => "DECIS-STORAGE-001", 503;
+    StorageFailed => "DECIS-STORAGE-002", 500;
+    StorageConflict => "DECIS-STORAGE-003", 409;
+    RemoteHttpError => "DECIS-REMOTE-001", 502;
+    RemoteUnreachable => "DECIS-REMOTE-002", 502;
+    RemoteInvalidResponse => "DECIS-REMOTE-003", 502;
+}
+
+pub trait Coded {
+    fn code(&self) -> ErrorCode;
+}
+
+/// An error as an API answers with it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ErrorReport {
+    pub code: &'static str,
+    pub name: &'static str,
+    #[serde(skip)]
+    pub status: u16,
+    /// In the catalog's language, for people; programs use `code`.
+    pub message: String
+}
+
+impl ErrorReport {
+    pub fn new<E: Coded + Localized>(error: &E, catalog: &dyn Catalog) -> ErrorReport {
+        let code = error.code();
+        ErrorReport { code: code.as_str(), name: code.name(), status: code.http_status(), message: error.localize(catalog) }
+    }
+}
+
+impl Coded for std::io::Error {
+    fn code(&self) -> ErrorCode {
+        ErrorCode::Io
+    }
+}
+
+impl Coded for GetQuestionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            GetQuestionError::InvalidUUID => ErrorCode::InvalidQuestionId,
+            GetQuestionError::DoesNotExist => ErrorCode::QuestionNotFound
+        }
+    }
+}
+
+impl Coded for SetDecisionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SetDecisionError::AlreadyExists => ErrorCode::DecisionAlreadyExists,
+            SetDecisionError::NotOpen(_) => ErrorCode::QuestionNotOpen
+        }
+    }
+}
+
+impl Coded for AddTagErrors {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AddTagErrors::AlreadyExists => ErrorCode::TagAlreadyExists,
+            AddTagErrors::Invalid(_) => ErrorCode::InvalidTag
+        }
+    }
+}
+
+impl Coded for AddQuestionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AddQuestionError::AlreadyExists => ErrorCode::QuestionAlreadyExists,
+            AddQuestionError::UsesNonExistentTags(_) => ErrorCode::QuestionUsesUnknownTags,
+            AddQuestionError::Rejected(_) => ErrorCode::QuestionRejected,
+            AddQuestionError::DuplicateExternalId { .. } => ErrorCode::DuplicateExternalId
+        }
+    }
+}
+
+impl Coded for UpdateQuestionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            UpdateQuestionError::Question(error) => error.code(),
+            UpdateQuestionError::Conflict(_) => ErrorCode::VersionConflict
+        }
+    }
+}
+
+impl Coded for SetQuestionDecisionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SetQuestionDecisionError::Question(error) => error.code(),
+            SetQuestionDecisionError::Conflict(_) => ErrorCode::VersionConflict,
+            SetQuestionDecisionError::Decision(error) => error.code(),
+            SetQuestionDecisionError::Rejected(_) => ErrorCode::DecisionRejected
+        }
+    }
+}
+
+impl Coded for ReorderOptionsError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ReorderOptionsError::Question(error) => error.code(),
+            ReorderOptionsError::Conflict(_) => ErrorCode::VersionConflict,
+            ReorderOptionsError::UnknownOption(_) => ErrorCode::UnknownOption,
+            ReorderOptionsError::NotAPermutation => ErrorCode::NotAPermutation
+        }
+    }
+}
+
+impl Coded for SetOptionOutcomeError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SetOptionOutcomeError::Question(error) => error.code(),
+            SetOptionOutcomeError::Conflict(_) => ErrorCode::VersionConflict,
+            SetOptionOutcomeError::Outcome(error) => error.code()
+        }
+    }
+}
+
+impl Coded for BulkTagError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            BulkTagError::UsesNonExistentTags(_) => ErrorCode::UnknownTags,
+            BulkTagError::Question(_, error) => error.code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::codes::*;
+    use crate::messages::English;
+
+    #[test]
+    fn test_codes_are_unique() {
+        let codes: HashSet<&str> = ErrorCode::ALL.iter().map(ErrorCode::as_str).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        assert_eq!((ErrorCode::TagAlreadyExists.as_str(), ErrorCode::TagAlreadyExists.name()), ("DECIS-TAG-001", "TagAlreadyExists"));
+    }
+
+    #[test]
+    fn test_reports_carry_the_wrapped_code() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let error = registry.set_question_priority(identifier, priority::Priority::High, 5, &luke).unwrap_err();
+        let report = ErrorReport::new(&error, &English);
+        assert_eq!((report.code, report.status), ("DECIS-QUESTION-006", 409));
+        assert_eq!(serde_json::to_value(&report).unwrap()["name"], "VersionConflict");
+
+        let missing = Registry::new().set_question_priority("0e4c3a5e-0000-4000-8000-000000000000".to_string(), priority::Priority::High, 0, &luke);
+        assert_eq!(missing.unwrap_err().code(), ErrorCode::QuestionNotFound);
+    }
+}
@@ -0,0 +1,161 @@
+//! Threaded discussion on questions.
+//!
+//! Comments don't change what is being decided, so adding or editing one
+//! doesn't bump the question's version or take an expected version.
+
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{Actor, GetQuestionError, Question, Registry};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Revision {
+    pub body: String,
+    /// When this body was replaced by the next one.
+    pub replaced_at: DateTime<Utc>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Comment {
+    pub identifier: Uuid,
+    pub author: Actor,
+    pub body: String,
+    pub posted_at: DateTime<Utc>,
+    pub reply_to: Option<Uuid>,
+    /// Earlier bodies, oldest first.
+    pub history: Vec<Revision>
+}
+
+#[derive(Debug)]
+pub enum CommentError {
+    Question(GetQuestionError),
+    InvalidCommentUUID,
+    /// The comment (or the one being replied to) isn't on this question.
+    UnknownComment,
+    /// Only a comment's author may edit it.
+    NotAuthor
+}
+
+impl Localized for CommentError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            CommentError::Question(error) => error.localize(catalog),
+            CommentError::InvalidCommentUUID => messages::text(catalog, "comment.invalid-uuid", &[]),
+            CommentError::UnknownComment => messages::text(catalog, "comment.unknown", &[]),
+            CommentError::NotAuthor => messages::text(catalog, "comment.not-author", &[])
+        }
+    }
+}
+
+impl Coded for CommentError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            CommentError::Question(error) => error.code(),
+            CommentError::InvalidCommentUUID => ErrorCode::InvalidCommentId,
+            CommentError::UnknownComment => ErrorCode::CommentNotFound,
+            CommentError::NotAuthor => ErrorCode::NotCommentAuthor
+        }
+    }
+}
+
+messages::display_localized!(CommentError);
+
+impl Question {
+    /// Every comment in the order it was posted.
+    pub fn get_comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// Direct replies to a comment, in the order they were posted.
+    pub fn replies(&self, comment: &Uuid) -> Vec<&Comment> {
+        self.comments.iter().filter(|candidate| candidate.reply_to.as_ref() == Some(comment)).collect()
+    }
+}
+
+fn parse_comment_id(identifier: &str) -> Result<Uuid, CommentError> {
+    Uuid::from_str(identifier).map_err(|_| CommentError::InvalidCommentUUID)
+}
+
+impl Registry {
+    /// Posts a comment by the acting user, optionally as a reply, and returns
+    /// the comment's identifier.
+    pub fn add_comment(&mut self, identifier: String, body: String, reply_to: Option<String>, actor: &Actor) -> Result<String, CommentError> {
+        let reply_to = reply_to.map(|parent| parse_comment_id(&parent)).transpose()?;
+        let now = self.now();
+        let comment_id = self.next_id();
+        let question = self.get_question_mut(identifier).map_err(CommentError::Question)?;
+        if let Some(parent) = reply_to {
+            if !question.comments.iter().any(|comment| comment.identifier == parent) {
+                return Result::Err(CommentError::UnknownComment);
+            }
+        }
+        let uuid = question.identifier;
+        let comment = Comment {
+            identifier: comment_id,
+            author: actor.clone(),
+            body,
+            posted_at: now,
+            reply_to,
+            history: Vec::new()
+        };
+        let comment_id = comment.identifier.to_string();
+        question.comments.push(comment);
+        self.record_change(actor, uuid, AuditAction::CommentAdded(comment_id.clone()));
+        Result::Ok(comment_id)
+    }
+
+    /// Replaces the body of one of the acting user's comments, keeping the
+    /// previous body in its history.
+    pub fn edit_comment(&mut self, identifier: String, comment: String, body: String, actor: &Actor) -> Result<(), CommentError> {
+        let comment_id = parse_comment_id(&comment)?;
+        let now = self.now();
+        let question = self.get_question_mut(identifier).map_err(CommentError::Question)?;
+        let uuid = question.identifier;
+        let comment = question.comments.iter_mut()
+            .find(|candidate| candidate.identifier == comment_id)
+            .ok_or(CommentError::UnknownComment)?;
+        if &comment.author != actor {
+            return Result::Err(CommentError::NotAuthor);
+        }
+        let previous = std::mem::replace(&mut comment.body, body);
+        comment.history.push(Revision { body: previous, replaced_at: now });
+        self.record_change(actor, uuid, AuditAction::CommentEdited(comment_id.to_string()));
+        Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use uuid::Uuid;
+    use crate::*;
+    use crate::comments::*;
+
+    #[test]
+    fn test_threaded_comments_keep_edit_history() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(Question::new("Tabs or spaces?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let first = registry.add_comment(identifier.clone(), "Spaces, obviously".to_string(), None, &luke).unwrap();
+        registry.add_comment(identifier.clone(), "Why?".to_string(), Some(first.clone()), &ana).unwrap();
+        registry.edit_comment(identifier.clone(), first.clone(), "Spaces".to_string(), &luke).unwrap();
+
+        let question = registry.get_question(identifier.clone()).unwrap();
+        let first_id = Uuid::parse_str(&first).unwrap();
+        assert_eq!(question.get_comments()[0].body, "Spaces");
+        assert_eq!(question.get_comments()[0].history[0].body, "Spaces, obviously");
+        assert_eq!(question.replies(&first_id)[0].author, ana);
+        assert_eq!(question.version(), 0);
+        assert!(registry.serialize_json().contains("Why?"));
+
+        match registry.edit_comment(identifier, first, "Tabs".to_string(), &ana) {
+            Err(CommentError::NotAuthor) => (),
+            _ => panic!("Only the author should be able to edit a comment")
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Context items: the facts a question is decided on, with where each came
+//! from, who added it and when.
+
+use std::collections::HashSet;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::{Actor, GetQuestionError, Question, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContextItem {
+    pub text: String,
+    /// Where the fact came from, e.g. a URL or "call with vendor X".
+    pub source: Option<String>,
+    /// `None` only for context loaded from formats that didn't record it.
+    pub added_by: Option<Actor>,
+    pub added_at: DateTime<Utc>,
+    /// Set once the fact no longer holds. The item is kept so the record of
+    /// what a decision was based on stays intact.
+    #[serde(default)]
+    pub retraction: Option<Retraction>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetractionKind {
+    /// True when it was added, but things have since changed.
+    Outdated,
+    /// Was never right.
+    Retracted
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Retraction {
+    pub kind: RetractionKind,
+    pub note: String,
+    pub by: Actor,
+    pub at: DateTime<Utc>
+}
+
+#[derive(Debug)]
+pub enum RetractContextError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    UnknownContext,
+    AlreadyRetracted
+}
+
+impl Localized for RetractContextError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            RetractContextError::Question(error) => error.localize(catalog),
+            RetractContextError::Conflict(question) => messages::conflict(catalog, question),
+            RetractContextError::UnknownContext => messages::text(catalog, "context.unknown", &[]),
+            RetractContextError::AlreadyRetracted => messages::text(catalog, "context.already-retracted", &[])
+        }
+    }
+}
+
+impl Coded for RetractContextError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            RetractContextError::Question(error) => error.code(),
+            RetractContextError::Conflict(_) => ErrorCode::VersionConflict,
+            RetractContextError::UnknownContext => ErrorCode::ContextNotFound,
+            RetractContextError::AlreadyRetracted => ErrorCode::ContextAlreadyRetracted
+        }
+    }
+}
+
+messages::display_localized!(RetractContextError);
+
+impl From<UpdateQuestionError> for RetractContextError {
+    fn from(error: UpdateQuestionError) -> RetractContextError {
+        match error {
+            UpdateQuestionError::Question(error) => RetractContextError::Question(error),
+            UpdateQuestionError::Conflict(current) => RetractContextError::Conflict(current)
+        }
+    }
+}
+
+impl ContextItem {
+    /// Stamped by the system clock. Registries use their own clock for the
+    /// context they add, including context given to `Question::new`.
+    pub fn new(text: String, source: Option<String>, added_by: Option<Actor>) -> ContextItem {
+        ContextItem { text, source, added_by, added_at: Utc::now(), retraction: None }
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.retraction.is_none()
+    }
+
+    /// The text as Markdown, struck through with the note appended once
+    /// retracted, e.g. `~~Plan costs $10~~ *(outdated: price went up)*`.
+    pub fn to_markdown(&self, catalog: &dyn Catalog) -> String {
+        match &self.retraction {
+            None => self.text.clone(),
+            Some(retraction) => {
+                let kind = match retraction.kind {
+                    RetractionKind::Outdated => messages::text(catalog, "context.outdated", &[]),
+                    RetractionKind::Retracted => messages::text(catalog, "context.retracted", &[])
+                };
+                format!("~~{}~~ *({}: {})*", self.text, kind, retraction.note)
+            }
+        }
+    }
+}
+
+/// Format version 2 and earlier stored context as a set of strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredItem {
+    Text(String),
+    Item(ContextItem)
+}
+
+pub(crate) fn deserialize_items<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ContextItem>, D::Error> {
+    let stored: Vec<StoredItem> = Vec::deserialize(deserializer)?;
+    let mut items = Vec::with_capacity(stored.len());
+    for item in stored {
+        match item {
+            StoredItem::Text(text) => items.push(ContextItem { text, source: None, added_by: None, added_at: DateTime::<Utc>::MIN_UTC, retraction: None }),
+            StoredItem::Item(item) => items.push(item)
+        }
+    }
+    Result::Ok(items)
+}
+
+/// Sets of strings have no order, so they become items sorted by text.
+pub(crate) fn items_from(texts: HashSet<String>) -> Vec<ContextItem> {
+    let mut texts: Vec<String> = texts.into_iter().collect();
+    texts.sort();
+    texts.into_iter().map(|text| ContextItem::new(text, None, None)).collect()
+}
+
+impl Question {
+    /// Adds an item unless one with the same text is already present.
+    /// Returns whether it was added.
+    pub fn add_context_item(&mut self, item: ContextItem) -> bool {
+        if self.context.iter().any(|existing| existing.text == item.text) {
+            return false;
+        }
+        self.context.push(item);
+        true
+    }
+
+    /// Every context item in the order it was added.
+    pub fn context_items(&self) -> &[ContextItem] {
+        &self.context
+    }
+}
+
+impl Registry {
+    /// Adds a context item, attributed to the acting user, and returns the
+    /// question's new version. Adding text the question already has changes
+    /// nothing.
+    pub fn add_question_context_item(&mut self, identifier: String, text: String, source: Option<String>, expected_version: u64, actor: &Actor) -> Result<u64, UpdateQuestionError> {
+        let item = ContextItem { added_at: self.now(), ..ContextItem::new(text.clone(), source, Some(actor.clone())) };
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        if !question.add_context_item(item) {
+            return Result::Ok(question.version);
+        }
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::ContextAdded(text));
+        Result::Ok(version)
+    }
+
+    /// Marks the context item with the given text as outdated or retracted
+    /// and returns the question's new version.
+    pub fn retract_context(&mut self, identifier: String, text: &str, kind: RetractionKind, note: String, expected_version: u64, actor: &Actor) -> Result<u64, RetractContextError> {
+        let now = self.now();
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        let item = question.context.iter_mut()
+            .find(|item| item.text == text)
+            .ok_or(RetractContextError::UnknownContext)?;
+        if item.retraction.is_some() {
+            return Result::Err(RetractContextError::AlreadyRetracted);
+        }
+        item.retraction = Some(Retraction { kind, note, by: actor.clone(), at: now });
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::ContextRetracted(text.to_string()));
+        Result::Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{TimeZone, Utc};
+    use crate::*;
+    use crate::clock::FixedClock;
+
+    #[test]
+    fn test_context_items_keep_order_and_attribution() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        registry.set_clock(FixedClock::new(start));
+        let mut context: HashSet<String> = HashSet::new();
+        context.insert("Budget is fixed".to_string());
+        let identifier = registry.add_question(Question::new("Which vendor?".to_string(), HashSet::new(), context, HashSet::new()), &luke).unwrap();
+        registry.add_question_context_item(identifier.clone(), "Vendor X dropped the plan".to_string(), Some("Sales call".to_string()), 0, &Actor::new("ana")).unwrap();
+        registry.add_question_context(identifier.clone(), ["Legal wants a DPA".to_string()].iter().cloned().collect(), 1, &luke).unwrap();
+
+        let question = registry.get_question(identifier).unwrap();
+        let items = question.context_items();
+        assert_eq!(items[0].text, "Budget is fixed");
+        assert_eq!(items[0].added_by, Some(luke));
+        assert_eq!(items[1].source.as_deref(), Some("Sales call"));
+        assert_eq!(items[1].added_by, Some(Actor::new("ana")));
+        assert!(items.iter().all(|item| item.added_at == start));
+    }
+
+    #[test]
+    fn test_retracted_context_is_kept_and_struck_through() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(Question::new("Which plan?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question_context_item(identifier.clone(), "Plan B costs $10".to_string(), None, 0, &luke).unwrap();
+        registry.retract_context(identifier.clone(), "Plan B costs $10", context::RetractionKind::Outdated, "Price went up".to_string(), 1, &luke).unwrap();
+
+        let question = registry.get_question(identifier.clone()).unwrap();
+        assert!(!question.context_items()[0].is_current());
+        assert_eq!(question.context_items()[0].to_markdown(registry.catalog()), "~~Plan B costs $10~~ *(outdated: Price went up)*");
+        match registry.retract_context(identifier, "Plan B costs $10", context::RetractionKind::Retracted, "Again".to_string(), 2, &luke) {
+            Err(context::RetractContextError::AlreadyRetracted) => (),
+            _ => panic!("An item should only be retracted once")
+        }
+    }
+
+    #[test]
+    fn test_plain_string_context_still_loads() {
+        let json = r#"{"format_version": 2, "tags": [], "questions": {
+            "5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80": {
+                "identifier": "5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80", "content": "Which queue?", "tags": [],
+                "context": ["Needs ordering"], "options": [], "decision": null, "version": 3
+            }
+        }}"#;
+        let registry = Registry::deserialize_json(json).unwrap();
+        let question = registry.get_question("5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80".to_string()).unwrap();
+        assert_eq!(question.context_items()[0].text, "Needs ordering");
+        assert!(question.context_items()[0].added_by.is_none());
+    }
+}
@@ -0,0 +1,299 @@
+//! Multi-criteria evaluation.
+//!
+//! Criteria are registered once per registry. Evaluators then score a
+//! question's options against them, and the scores are kept on the question.
+//! Rankings normalise every score onto 0..=1 (1 being best, whatever the
+//! criterion's direction) before aggregating.
+
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::{Actor, GetQuestionError, Question, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    HigherIsBetter,
+    LowerIsBetter
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Criterion {
+    pub name: String,
+    pub description: String,
+    /// Scores must lie within `min..=max`.
+    pub min: f64,
+    pub max: f64,
+    pub direction: Direction
+}
+
+impl Criterion {
+    pub fn new(name: &str, description: &str, min: f64, max: f64, direction: Direction) -> Criterion {
+        Criterion { name: name.to_string(), description: description.to_string(), min, max, direction }
+    }
+
+    /// Maps a score onto 0..=1 with 1 the best end of the scale.
+    fn normalise(&self, score: f64) -> f64 {
+        let fraction = (score - self.min) / (self.max - self.min);
+        match self.direction {
+            Direction::HigherIsBetter => fraction,
+            Direction::LowerIsBetter => 1.0 - fraction
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AddCriterionError {
+    AlreadyExists,
+    /// `min` must be below `max` and both finite.
+    InvalidScale
+}
+
+impl Localized for AddCriterionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            AddCriterionError::AlreadyExists => messages::text(catalog, "criterion.already-exists", &[]),
+            AddCriterionError::InvalidScale => messages::text(catalog, "criterion.invalid-scale", &[])
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ScoreOptionError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    UnknownOption(String),
+    UnknownCriterion(String),
+    OutOfScale
+}
+
+impl Localized for ScoreOptionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            ScoreOptionError::Question(error) => error.localize(catalog),
+            ScoreOptionError::Conflict(question) => messages::conflict(catalog, question),
+            ScoreOptionError::UnknownOption(option) => messages::text(catalog, "option.unknown", &[("option", option.clone())]),
+            ScoreOptionError::UnknownCriterion(criterion) => messages::text(catalog, "criterion.unknown", &[("criterion", criterion.clone())]),
+            ScoreOptionError::OutOfScale => messages::text(catalog, "score.out-of-scale", &[])
+        }
+    }
+}
+
+impl Coded for AddCriterionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            AddCriterionError::AlreadyExists => ErrorCode::CriterionAlreadyExists,
+            AddCriterionError::InvalidScale => ErrorCode::InvalidScale
+        }
+    }
+}
+
+impl Coded for ScoreOptionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ScoreOptionError::Question(error) => error.code(),
+            ScoreOptionError::Conflict(_) => ErrorCode::VersionConflict,
+            ScoreOptionError::UnknownOption(_) => ErrorCode::UnknownOption,
+            ScoreOptionError::UnknownCriterion(_) => ErrorCode::UnknownCriterion,
+            ScoreOptionError::OutOfScale => ErrorCode::ScoreOutOfScale
+        }
+    }
+}
+
+messages::display_localized!(AddCriterionError, ScoreOptionError);
+
+impl From<UpdateQuestionError> for ScoreOptionError {
+    fn from(error: UpdateQuestionError) -> ScoreOptionError {
+        match error {
+            UpdateQuestionError::Question(error) => ScoreOptionError::Question(error),
+            UpdateQuestionError::Conflict(current) => ScoreOptionError::Conflict(current)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Aggregation {
+    /// The mean of every criterion's mean score.
+    Mean,
+    /// The median of every criterion's median score.
+    Median,
+    /// A weighted mean of every criterion's mean score. Criteria without a
+    /// positive, finite weight don't count.
+    Weighted(BTreeMap<String, f64>)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Ranking {
+    pub option: String,
+    /// Between 0 and 1, higher is better.
+    pub score: f64,
+    /// How many criteria the option was scored against.
+    pub criteria: usize
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+impl Question {
+    /// Criterion -> evaluator -> score for one option.
+    pub fn get_scores(&self, option: &str) -> BTreeMap<String, BTreeMap<Actor, f64>> {
+        self.scores.get(option).cloned().unwrap_or_default()
+    }
+}
+
+impl Registry {
+    pub fn add_criterion(&mut self, criterion: Criterion, actor: &Actor) -> Result<(), AddCriterionError> {
+        if !(criterion.min.is_finite() && criterion.max.is_finite() && criterion.min < criterion.max) {
+            return Result::Err(AddCriterionError::InvalidScale);
+        }
+        if self.criteria.contains_key(&criterion.name) {
+            return Result::Err(AddCriterionError::AlreadyExists);
+        }
+        self.audit_log.record(self.now(), actor, None, AuditAction::CriterionAdded(criterion.name.clone()));
+        self.criteria.insert(criterion.name.clone(), criterion);
+        Result::Ok(())
+    }
+
+    pub fn criteria(&self) -> Vec<&Criterion> {
+        self.criteria.values().collect()
+    }
+
+    /// Records the acting evaluator's score for an option against a criterion,
+    /// replacing their earlier score, and returns the question's new version.
+    pub fn score_option(&mut self, identifier: String, option: &str, criterion: &str, score: f64, expected_version: u64, actor: &Actor) -> Result<u64, ScoreOptionError> {
+        let scale = self.criteria.get(criterion).ok_or_else(|| ScoreOptionError::UnknownCriterion(criterion.to_string()))?;
+        if !(scale.min..=scale.max).contains(&score) {
+            return Result::Err(ScoreOptionError::OutOfScale);
+        }
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        if !question.has_option(option) {
+            return Result::Err(ScoreOptionError::UnknownOption(option.to_string()));
+        }
+        let uuid = question.identifier;
+        question.scores
+            .entry(option.to_string()).or_default()
+            .entry(criterion.to_string()).or_default()
+            .insert(actor.clone(), score);
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::CriterionScored { option: option.to_string(), criterion: criterion.to_string() });
+        Result::Ok(version)
+    }
+
+    /// The question's scored options, best first. Options nobody has scored
+    /// (or, for `Weighted`, scored only on unweighted criteria) are left out.
+    pub fn rank_options(&self, identifier: String, aggregation: &Aggregation) -> Result<Vec<Ranking>, GetQuestionError> {
+        let question = self.get_question(identifier)?;
+        let mut rankings = Vec::new();
+        for (option, by_criterion) in &question.scores {
+            // Per criterion, the evaluators' normalised scores combined.
+            let combined: Vec<(&String, f64)> = by_criterion.iter()
+                .filter_map(|(name, by_evaluator)| {
+                    let criterion = self.criteria.get(name)?;
+                    let normalised: Vec<f64> = by_evaluator.values().map(|score| criterion.normalise(*score)).collect();
+                    let value = match aggregation {
+                        Aggregation::Median => median(&normalised),
+                        _ => mean(&normalised)
+                    };
+                    Some((name, value))
+                })
+                .collect();
+            let score = match aggregation {
+                Aggregation::Mean if !combined.is_empty() => Some(mean(&combined.iter().map(|(_, value)| *value).collect::<Vec<f64>>())),
+                Aggregation::Median if !combined.is_empty() => Some(median(&combined.iter().map(|(_, value)| *value).collect::<Vec<f64>>())),
+                Aggregation::Weighted(weights) => {
+                    let weighted: Vec<(f64, f64)> = combined.iter()
+                        .filter_map(|(name, value)| weights.get(*name).filter(|weight| weight.is_finite() && **weight > 0.0).map(|weight| (*weight, *value)))
+                        .collect();
+                    let total: f64 = weighted.iter().map(|(weight, _)| weight).sum();
+                    if total > 0.0 {
+                        Some(weighted.iter().map(|(weight, value)| weight * value).sum::<f64>() / total)
+                    } else {
+                        None
+                    }
+                }
+                _ => None
+            };
+            if let Some(score) = score {
+                rankings.push(Ranking { option: option.clone(), score, criteria: combined.len() });
+            }
+        }
+        rankings.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.option.cmp(&b.option)));
+        Result::Ok(rankings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+    use crate::*;
+    use crate::criteria::*;
+
+    fn registry_with_scores() -> (Registry, String) {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        registry.add_criterion(Criterion::new("speed", "Requests per second", 1.0, 5.0, Direction::HigherIsBetter), &luke).unwrap();
+        registry.add_criterion(Criterion::new("cost", "Monthly bill", 1.0, 5.0, Direction::LowerIsBetter), &luke).unwrap();
+        let options: HashSet<String> = ["Nginx", "Caddy"].iter().map(|option| option.to_string()).collect();
+        let identifier = registry.add_question(Question::new("Which proxy?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+
+        let mut version = 0;
+        for (option, criterion, score, evaluator) in [("Nginx", "speed", 5.0, &luke), ("Nginx", "speed", 3.0, &ana), ("Nginx", "cost", 5.0, &luke),
+                                                      ("Caddy", "speed", 3.0, &luke), ("Caddy", "cost", 1.0, &ana)] {
+            version = registry.score_option(identifier.clone(), option, criterion, score, version, evaluator).unwrap();
+        }
+        (registry, identifier)
+    }
+
+    #[test]
+    fn test_rankings_depend_on_aggregation() {
+        let (registry, identifier) = registry_with_scores();
+        // Nginx: speed 0.75, cost 0. Caddy: speed 0.5, cost 1.
+        let mean = registry.rank_options(identifier.clone(), &Aggregation::Mean).unwrap();
+        assert_eq!(mean[0].option, "Caddy");
+        assert_eq!(mean[0].score, 0.75);
+        assert_eq!(mean[1].score, 0.375);
+
+        let mut weights: BTreeMap<String, f64> = BTreeMap::new();
+        weights.insert("speed".to_string(), 1.0);
+        let speed_only = registry.rank_options(identifier.clone(), &Aggregation::Weighted(weights.clone())).unwrap();
+        assert_eq!(speed_only[0].option, "Nginx");
+        // An infinite weight is ignored rather than turning scores into NaN.
+        weights.insert("cost".to_string(), f64::INFINITY);
+        assert_eq!(registry.rank_options(identifier.clone(), &Aggregation::Weighted(weights)).unwrap(), speed_only);
+        assert_eq!(registry.get_question(identifier).unwrap().get_scores("Nginx")["speed"].len(), 2);
+    }
+
+    #[test]
+    fn test_scores_are_validated() {
+        let (mut registry, identifier) = registry_with_scores();
+        let luke = Actor::new("luke");
+        let version = registry.get_question(identifier.clone()).unwrap().version();
+        match registry.score_option(identifier.clone(), "Nginx", "speed", 9.0, version, &luke) {
+            Err(ScoreOptionError::OutOfScale) => (),
+            _ => panic!("Scores outside the scale should be rejected")
+        }
+        match registry.score_option(identifier, "Nginx", "beauty", 3.0, version, &luke) {
+            Err(ScoreOptionError::UnknownCriterion(_)) => (),
+            _ => panic!("Unregistered criteria should be rejected")
+        }
+        match registry.add_criterion(Criterion::new("speed", "Again", 0.0, 1.0, Direction::HigherIsBetter), &luke) {
+            Err(AddCriterionError::AlreadyExists) => (),
+            _ => panic!("Criteria names should be unique")
+        }
+    }
+}
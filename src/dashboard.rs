@@ -0,0 +1,120 @@
+//! The counts a landing page shows, gathered in one pass.
+
+use std::collections::{BTreeMap, HashMap};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use serde::Serialize;
+use crate::Registry;
+use crate::status::QuestionStatus;
+
+/// How far ahead `Dashboard::upcoming_reviews` looks.
+pub const REVIEW_WINDOW_DAYS: i64 = 30;
+
+/// How many tags `Dashboard::top_tags` lists.
+pub const TOP_TAGS: usize = 10;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Dashboard {
+    /// Open questions, including deferred ones that are due back.
+    pub open: usize,
+    pub decided: usize,
+    /// Every status, including those with no questions.
+    pub by_status: BTreeMap<QuestionStatus, usize>,
+    /// The most used tags with their question counts, most used first.
+    pub top_tags: Vec<(String, usize)>,
+    /// Decisions set in the current calendar month that still stand.
+    pub decided_this_month: usize,
+    /// Decisions expiring and deferred questions due back within the
+    /// review window.
+    pub upcoming_reviews: usize,
+    /// Expired decisions and deferred questions past their wake-up date.
+    pub overdue: usize
+}
+
+impl Registry {
+    pub fn dashboard(&self) -> Dashboard {
+        self.dashboard_at(self.now())
+    }
+
+    /// `dashboard` as of `now`.
+    pub fn dashboard_at(&self, now: DateTime<Utc>) -> Dashboard {
+        let today = now.date_naive();
+        let window = now + Duration::days(REVIEW_WINDOW_DAYS);
+        let mut dashboard = Dashboard::default();
+        for status in &[QuestionStatus::Open, QuestionStatus::Decided, QuestionStatus::Deferred, QuestionStatus::Cancelled] {
+            dashboard.by_status.insert(*status, 0);
+        }
+        let mut tag_counts: HashMap<&String, usize> = HashMap::new();
+        for question in self.questions.values() {
+            let status = question.status();
+            *dashboard.by_status.entry(status).or_insert(0) += 1;
+            for tag in &question.tags {
+                *tag_counts.entry(tag).or_insert(0) += 1;
+            }
+            match status {
+                QuestionStatus::Open => dashboard.open += 1,
+                QuestionStatus::Decided => {
+                    dashboard.decided += 1;
+                    if self.decided_at(question).is_some_and(|at| at.year() == now.year() && at.month() == now.month()) {
+                        dashboard.decided_this_month += 1;
+                    }
+                    match question.decision.as_ref().and_then(|decision| decision.expires_at) {
+                        Some(expires_at) if expires_at <= now => dashboard.overdue += 1,
+                        Some(expires_at) if expires_at <= window => dashboard.upcoming_reviews += 1,
+                        _ => ()
+                    }
+                }
+                QuestionStatus::Deferred => match question.deferral.as_ref().and_then(|deferral| deferral.until) {
+                    Some(until) if until <= today => {
+                        dashboard.open += 1;
+                        dashboard.overdue += 1;
+                    }
+                    Some(until) if until <= window.date_naive() => dashboard.upcoming_reviews += 1,
+                    _ => ()
+                },
+                QuestionStatus::Cancelled => ()
+            }
+        }
+        let mut top_tags: Vec<(String, usize)> = tag_counts.into_iter().map(|(tag, count)| (tag.clone(), count)).collect();
+        top_tags.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        top_tags.truncate(TOP_TAGS);
+        dashboard.top_tags = top_tags;
+        dashboard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{Duration, Utc};
+    use crate::*;
+    use crate::status::QuestionStatus;
+
+    #[test]
+    fn test_dashboard_counts() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        registry.add_tag(&"billing".to_string(), &luke).unwrap();
+        let infra: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let both: HashSet<String> = ["infra".to_string(), "billing".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let now = Utc::now();
+        let decided = registry.add_question(Question::new("Use Terraform?".to_string(), infra.clone(), HashSet::new(), options), &luke).unwrap();
+        let deferred = registry.add_question(Question::new("Move regions?".to_string(), both, HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question(Question::new("Which CI?".to_string(), infra, HashSet::new(), HashSet::new()), &luke).unwrap();
+        let decision = Decision::new("Yes".to_string(), "Already in use".to_string(), HashSet::new()).with_expiry(now + Duration::days(10));
+        registry.set_question_decision(decided, decision, 0, &luke).unwrap();
+        registry.defer_question(deferred, "After the migration".to_string(), Some((now + Duration::days(40)).date_naive()), 0, &luke).unwrap();
+
+        let dashboard = registry.dashboard_at(now);
+        assert_eq!((dashboard.open, dashboard.decided), (1, 1));
+        assert_eq!(dashboard.by_status[&QuestionStatus::Deferred], 1);
+        assert_eq!(dashboard.by_status[&QuestionStatus::Cancelled], 0);
+        assert_eq!(dashboard.top_tags, vec![("infra".to_string(), 3), ("billing".to_string(), 1)]);
+        assert_eq!(dashboard.decided_this_month, 1);
+        assert_eq!((dashboard.upcoming_reviews, dashboard.overdue), (1, 0));
+
+        let later = registry.dashboard_at(now + Duration::days(41));
+        assert_eq!((later.open, later.overdue, later.upcoming_reviews), (2, 2, 0));
+    }
+}
@@ -0,0 +1,208 @@
+//! Encrypted persistence for registries (feature `encryption`).
+//!
+//! Files are laid out as `MAGIC | salt | nonce | ciphertext`, where the
+//! ciphertext is the CBOR encoded registry sealed with AES-256-GCM. Passphrases
+//! are stretched with Argon2id using the stored salt; key files hold the raw
+//! 32 byte key.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::aead::rand_core::RngCore;
+use argon2::Argon2;
+use crate::Registry;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+use crate::migrations::{self, LoadError, Versioned};
+
+const MAGIC: &[u8; 8] = b"DECISAE1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+pub enum EncryptionKey {
+    Passphrase(String),
+    KeyFile(PathBuf)
+}
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    Io(std::io::Error),
+    Serialization(serde_cbor::Error),
+    Load(LoadError),
+    /// The file is not an encrypted registry, or is truncated.
+    InvalidFormat,
+    /// A key file did not contain exactly 32 bytes.
+    InvalidKeyFile,
+    KeyDerivation,
+    /// The ciphertext failed authentication: wrong key or tampered file.
+    Authentication
+}
+
+impl Localized for EncryptionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            EncryptionError::Io(error) => messages::io(catalog, error),
+            EncryptionError::Serialization(error) => messages::encoding(catalog, error),
+            EncryptionError::Load(error) => error.localize(catalog),
+            EncryptionError::InvalidFormat => messages::text(catalog, "encryption.invalid-format", &[]),
+            EncryptionError::InvalidKeyFile => messages::text(catalog, "encryption.invalid-key-file", &[]),
+            EncryptionError::KeyDerivation => messages::text(catalog, "encryption.key-derivation", &[]),
+            EncryptionError::Authentication => messages::text(catalog, "encryption.authentication", &[])
+        }
+    }
+}
+
+impl Coded for EncryptionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            EncryptionError::Io(_) => ErrorCode::Io,
+            EncryptionError::Serialization(_) => ErrorCode::Encoding,
+            EncryptionError::Load(error) => error.code(),
+            EncryptionError::InvalidFormat => ErrorCode::InvalidEncryptedFile,
+            EncryptionError::InvalidKeyFile => ErrorCode::InvalidKeyFile,
+            EncryptionError::KeyDerivation => ErrorCode::KeyDerivationFailed,
+            EncryptionError::Authentication => ErrorCode::DecryptionFailed
+        }
+    }
+}
+
+messages::display_localized!(EncryptionError);
+
+impl From<std::io::Error> for EncryptionError {
+    fn from(error: std::io::Error) -> EncryptionError {
+        EncryptionError::Io(error)
+    }
+}
+
+impl From<serde_cbor::Error> for EncryptionError {
+    fn from(error: serde_cbor::Error) -> EncryptionError {
+        EncryptionError::Serialization(error)
+    }
+}
+
+impl From<LoadError> for EncryptionError {
+    fn from(error: LoadError) -> EncryptionError {
+        EncryptionError::Load(error)
+    }
+}
+
+impl EncryptionKey {
+    fn derive(&self, salt: &[u8]) -> Result<[u8; KEY_LEN], EncryptionError> {
+        let mut key = [0u8; KEY_LEN];
+        match self {
+            EncryptionKey::Passphrase(passphrase) => {
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                    .map_err(|_| EncryptionError::KeyDerivation)?;
+            }
+            EncryptionKey::KeyFile(path) => {
+                let contents = fs::read(path)?;
+                if contents.len() != KEY_LEN {
+                    return Result::Err(EncryptionError::InvalidKeyFile);
+                }
+                key.copy_from_slice(&contents);
+            }
+        }
+        Result::Ok(key)
+    }
+}
+
+impl Registry {
+    pub fn save_encrypted<P: AsRef<Path>>(&self, path: P, key: &EncryptionKey) -> Result<(), EncryptionError> {
+        let plaintext = serde_cbor::to_vec(&Versioned::new(self))?;
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.derive(&salt)?));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| EncryptionError::Authentication)?;
+
+        let mut contents = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+        contents.extend_from_slice(MAGIC);
+        contents.extend_from_slice(&salt);
+        contents.extend_from_slice(&nonce);
+        contents.extend_from_slice(&ciphertext);
+        fs::write(path, contents)?;
+        Result::Ok(())
+    }
+
+    pub fn load_encrypted<P: AsRef<Path>>(path: P, key: &EncryptionKey) -> Result<Registry, EncryptionError> {
+        let contents = fs::read(path)?;
+        if contents.len() < MAGIC.len() + SALT_LEN + NONCE_LEN || !contents.starts_with(MAGIC) {
+            return Result::Err(EncryptionError::InvalidFormat);
+        }
+        let (salt, rest) = contents[MAGIC.len()..].split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key.derive(salt)?));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| EncryptionError::Authentication)?;
+        Result::Ok(migrations::from_cbor(&plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::encryption::*;
+
+    fn registry_with_question() -> Registry {
+        let mut registry = Registry::new();
+        registry.add_tag(&"Vendors".to_string(), &Actor::new("luke")).unwrap();
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("Vendors".to_string());
+        let mut context: HashSet<String> = HashSet::new();
+        context.insert("Vendor X quoted $40k/year".to_string());
+        registry.add_question(Question::new("Which vendor?".to_string(), tags, context, HashSet::new()), &Actor::new("luke")).unwrap();
+        registry
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("decis_test_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_round_trip_with_passphrase() {
+        let path = temp_path("encrypted_round_trip");
+        let key = EncryptionKey::Passphrase("correct horse".to_string());
+        registry_with_question().save_encrypted(&path, &key).unwrap();
+
+        assert!(!std::fs::read(&path).unwrap().windows(6).any(|window| window == b"Vendor"));
+        let loaded = Registry::load_encrypted(&path, &key).unwrap();
+        assert!(loaded.serialize_json().contains("Vendor X quoted $40k/year"));
+    }
+
+    #[test]
+    fn test_wrong_passphrase_fails_authentication() {
+        let path = temp_path("encrypted_wrong_key");
+        registry_with_question().save_encrypted(&path, &EncryptionKey::Passphrase("right".to_string())).unwrap();
+
+        match Registry::load_encrypted(&path, &EncryptionKey::Passphrase("wrong".to_string())) {
+            Err(EncryptionError::Authentication) => (),
+            _ => panic!("Loading with the wrong passphrase should fail authentication")
+        }
+    }
+
+    #[test]
+    fn test_tampered_file_fails_authentication() {
+        let path = temp_path("encrypted_tampered");
+        let key_path = temp_path("encrypted_tampered_key");
+        std::fs::write(&key_path, [7u8; 32]).unwrap();
+        let key = EncryptionKey::KeyFile(key_path);
+        registry_with_question().save_encrypted(&path, &key).unwrap();
+
+        let mut contents = std::fs::read(&path).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 0x01;
+        std::fs::write(&path, contents).unwrap();
+
+        match Registry::load_encrypted(&path, &key) {
+            Err(EncryptionError::Authentication) => (),
+            _ => panic!("A tampered file should fail authentication")
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Structured cost, effort and risk estimates on options, so they can be
+//! compared and sorted instead of living in free text.
+
+use std::cmp::Ordering;
+use serde::{Serialize, Deserialize};
+use crate::{Actor, GetQuestionError, Question, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cost {
+    pub amount: f64,
+    /// ISO 4217 code, e.g. `EUR`. Costs in different currencies don't compare.
+    pub currency: String
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Effort {
+    Hours(f64),
+    /// Working days of eight hours.
+    Days(f64),
+    /// Working weeks of five days.
+    Weeks(f64)
+}
+
+impl Effort {
+    pub fn as_hours(&self) -> f64 {
+        match *self {
+            Effort::Hours(hours) => hours,
+            Effort::Days(days) => days * 8.0,
+            Effort::Weeks(weeks) => weeks * 40.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Risk {
+    Low,
+    Medium,
+    High
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Estimate {
+    pub cost: Option<Cost>,
+    pub effort: Option<Effort>,
+    pub risk: Option<Risk>
+}
+
+impl Estimate {
+    fn is_valid(&self) -> bool {
+        let cost = self.cost.as_ref().is_none_or(|cost| cost.amount.is_finite() && cost.amount >= 0.0 && !cost.currency.is_empty());
+        let effort = self.effort.is_none_or(|effort| effort.as_hours().is_finite() && effort.as_hours() >= 0.0);
+        cost && effort
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptionOrder {
+    /// Cheapest first, grouped by currency.
+    Cost,
+    /// Least effort first.
+    Effort,
+    /// Lowest risk first.
+    Risk
+}
+
+#[derive(Debug)]
+pub enum SetEstimateError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    UnknownOption(String),
+    /// Amounts and efforts must be finite and non-negative, and costs need a currency.
+    InvalidEstimate
+}
+
+impl Localized for SetEstimateError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            SetEstimateError::Question(error) => error.localize(catalog),
+            SetEstimateError::Conflict(question) => messages::conflict(catalog, question),
+            SetEstimateError::UnknownOption(option) => messages::text(catalog, "option.unknown", &[("option", option.clone())]),
+            SetEstimateError::InvalidEstimate => messages::text(catalog, "estimate.invalid", &[])
+        }
+    }
+}
+
+impl Coded for SetEstimateError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SetEstimateError::Question(error) => error.code(),
+            SetEstimateError::Conflict(_) => ErrorCode::VersionConflict,
+            SetEstimateError::UnknownOption(_) => ErrorCode::UnknownOption,
+            SetEstimateError::InvalidEstimate => ErrorCode::InvalidEstimate
+        }
+    }
+}
+
+messages::display_localized!(SetEstimateError);
+
+impl From<UpdateQuestionError> for SetEstimateError {
+    fn from(error: UpdateQuestionError) -> SetEstimateError {
+        match error {
+            UpdateQuestionError::Question(error) => SetEstimateError::Question(error),
+            UpdateQuestionError::Conflict(current) => SetEstimateError::Conflict(current)
+        }
+    }
+}
+
+impl Question {
+    pub fn get_estimate(&self, option: &str) -> Option<&Estimate> {
+        self.estimates.get(option)
+    }
+
+    /// All options in `order`. Options without the estimate sort last, and
+    /// ties keep the question's option order.
+    pub fn sorted_options_by(&self, order: OptionOrder) -> Vec<String> {
+        let mut options: Vec<&String> = self.options.iter().collect();
+        let estimate = |option: &String| self.estimates.get(option.as_str());
+        options.sort_by(|a, b| match order {
+            OptionOrder::Cost => compare_missing_last(
+                estimate(a).and_then(|estimate| estimate.cost.as_ref()),
+                estimate(b).and_then(|estimate| estimate.cost.as_ref()),
+                |a, b| a.currency.cmp(&b.currency).then(a.amount.partial_cmp(&b.amount).unwrap_or(Ordering::Equal))),
+            OptionOrder::Effort => compare_missing_last(
+                estimate(a).and_then(|estimate| estimate.effort),
+                estimate(b).and_then(|estimate| estimate.effort),
+                |a, b| a.as_hours().partial_cmp(&b.as_hours()).unwrap_or(Ordering::Equal)),
+            OptionOrder::Risk => compare_missing_last(
+                estimate(a).and_then(|estimate| estimate.risk),
+                estimate(b).and_then(|estimate| estimate.risk),
+                |a, b| a.cmp(b))
+        });
+        options.into_iter().cloned().collect()
+    }
+
+    /// A Markdown table of every option's estimates, in option order.
+    pub fn comparison_table(&self) -> String {
+        let mut lines = vec!["| Option | Cost | Effort | Risk |".to_string(), "| --- | --- | --- | --- |".to_string()];
+        for option in &self.options {
+            let estimate = self.estimates.get(option.as_str()).cloned().unwrap_or_default();
+            let cost = estimate.cost.map(|cost| format!("{} {}", cost.amount, cost.currency)).unwrap_or_default();
+            let effort = estimate.effort.map(|effort| match effort {
+                Effort::Hours(hours) => format!("{} h", hours),
+                Effort::Days(days) => format!("{} d", days),
+                Effort::Weeks(weeks) => format!("{} w", weeks)
+            }).unwrap_or_default();
+            let risk = estimate.risk.map(|risk| format!("{:?}", risk)).unwrap_or_default();
+            lines.push(format!("| {} | {} | {} | {} |", option, cost, effort, risk));
+        }
+        lines.join("\n")
+    }
+}
+
+fn compare_missing_last<T, F: Fn(&T, &T) -> Ordering>(a: Option<T>, b: Option<T>, compare: F) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare(&a, &b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal
+    }
+}
+
+impl Registry {
+    /// Replaces the estimate for one of the question's options and returns
+    /// the question's new version.
+    pub fn set_option_estimate(&mut self, identifier: String, option: &str, estimate: Estimate, expected_version: u64, actor: &Actor) -> Result<u64, SetEstimateError> {
+        if !estimate.is_valid() {
+            return Result::Err(SetEstimateError::InvalidEstimate);
+        }
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        if !question.has_option(option) {
+            return Result::Err(SetEstimateError::UnknownOption(option.to_string()));
+        }
+        let uuid = question.identifier;
+        question.estimates.insert(option.to_string(), estimate);
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::EstimateSet(option.to_string()));
+        Result::Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::estimate::*;
+
+    fn estimate(amount: f64, effort: Effort, risk: Risk) -> Estimate {
+        Estimate { cost: Some(Cost { amount, currency: "EUR".to_string() }), effort: Some(effort), risk: Some(risk) }
+    }
+
+    #[test]
+    fn test_options_sort_by_estimates() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Build", "Buy", "Wait"].iter().map(|option| option.to_string()).collect();
+        let identifier = registry.add_question(Question::new("Build or buy?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        registry.set_option_estimate(identifier.clone(), "Build", estimate(20000.0, Effort::Weeks(6.0), Risk::High), 0, &luke).unwrap();
+        registry.set_option_estimate(identifier.clone(), "Buy", estimate(5000.0, Effort::Days(3.0), Risk::Medium), 1, &luke).unwrap();
+
+        let question = registry.get_question(identifier.clone()).unwrap();
+        assert_eq!(question.sorted_options_by(OptionOrder::Cost), vec!["Buy", "Build", "Wait"]);
+        assert_eq!(question.sorted_options_by(OptionOrder::Risk), vec!["Buy", "Build", "Wait"]);
+        assert!(question.comparison_table().contains("| Build | 20000 EUR | 6 w | High |"));
+        assert!(question.comparison_table().contains("| Wait |  |  |  |"));
+
+        match registry.set_option_estimate(identifier, "Rent", Estimate::default(), 2, &luke) {
+            Err(SetEstimateError::UnknownOption(_)) => (),
+            _ => panic!("Estimates for unknown options should be rejected")
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! Pushing questions to external systems.
+//!
+//! Each question remembers its identifier in every system it was exported
+//! to, so exporting again updates what is there instead of adding copies.
+//!
+//! Enable the `notion` feature for [`Registry::export_to_notion`], which
+//! writes questions into a Notion database.
+
+use crate::{Actor, GetQuestionError, Question, Registry};
+use crate::audit::AuditAction;
+
+#[cfg(feature = "notion")]
+pub use self::notion::{page_properties, ExportError, NotionConfig, NotionExport, PropertyMapping, NOTION_SYSTEM};
+
+impl Question {
+    /// The question's identifier in `system`, once exported there.
+    pub fn external_id(&self, system: &str) -> Option<&str> {
+        self.external_ids.get(system).map(String::as_str)
+    }
+}
+
+impl Registry {
+    /// Records where an exporter put the question, returning its version.
+    /// Like a comment, this touches nothing users edit, so it takes no
+    /// expected version and leaves the version alone; the change is still in
+    /// the question's history. Setting the identifier it already has changes
+    /// nothing.
+    pub fn set_external_id(&mut self, identifier: String, system: &str, id: String, actor: &Actor) -> Result<u64, GetQuestionError> {
+        let question = self.get_question_mut(identifier)?;
+        if question.external_ids.get(system) == Some(&id) {
+            return Result::Ok(question.version);
+        }
+        let uuid = question.identifier;
+        question.external_ids.insert(system.to_string(), id);
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::ExternalIdSet(system.to_string()));
+        Result::Ok(version)
+    }
+}
+
+#[cfg(feature = "notion")]
+mod notion {
+    use serde_json::{json, Map, Value};
+    use uuid::Uuid;
+    use crate::{Actor, Question, Registry};
+    use crate::codes::{Coded, ErrorCode};
+    use crate::messages::{self, Catalog, Localized};
+
+    const NOTION_API: &str = "https://api.notion.com/v1";
+    const NOTION_VERSION: &str = "2022-06-28";
+    /// Notion's limit on one piece of rich text, in characters.
+    const MAX_TEXT: usize = 2000;
+    /// The system name page IDs are kept under, see `Question::external_id`.
+    pub const NOTION_SYSTEM: &str = "notion";
+
+    #[derive(Debug)]
+    pub enum ExportError {
+        Http(u16),
+        Transport(String),
+        InvalidResponse(String)
+    }
+
+    impl Localized for ExportError {
+        fn localize(&self, catalog: &dyn Catalog) -> String {
+            match self {
+                ExportError::Http(status) => messages::text(catalog, "remote.http", &[("status", status.to_string())]),
+                ExportError::Transport(error) => messages::text(catalog, "remote.transport", &[("error", error.clone())]),
+                ExportError::InvalidResponse(error) => messages::text(catalog, "remote.invalid-response", &[("error", error.clone())])
+            }
+        }
+    }
+
+    impl Coded for ExportError {
+        fn code(&self) -> ErrorCode {
+            match self {
+                ExportError::Http(_) => ErrorCode::RemoteHttpError,
+                ExportError::Transport(_) => ErrorCode::RemoteUnreachable,
+                ExportError::InvalidResponse(_) => ErrorCode::RemoteInvalidResponse
+            }
+        }
+    }
+
+    messages::display_localized!(ExportError);
+
+    impl From<ureq::Error> for ExportError {
+        fn from(error: ureq::Error) -> ExportError {
+            match error {
+                ureq::Error::Status(code, _) => ExportError::Http(code),
+                ureq::Error::Transport(transport) => ExportError::Transport(transport.to_string())
+            }
+        }
+    }
+
+    /// Which database property each part of a question goes to. `None`
+    /// leaves that part out. The properties must already exist in the
+    /// database with the right types: title, multi-select for tags, select
+    /// for status, and text for the rest.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct PropertyMapping {
+        pub title: String,
+        pub tags: Option<String>,
+        pub status: Option<String>,
+        pub decision: Option<String>,
+        pub rationale: Option<String>,
+        pub owner: Option<String>,
+        /// Current context items, one per line.
+        pub context: Option<String>
+    }
+
+    impl Default for PropertyMapping {
+        fn default() -> PropertyMapping {
+            PropertyMapping {
+                title: "Name".to_string(),
+                tags: Some("Tags".to_string()),
+                status: Some("Status".to_string()),
+                decision: Some("Decision".to_string()),
+                rationale: Some("Rationale".to_string()),
+                owner: None,
+                context: None
+            }
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct NotionConfig {
+        /// An integration token with access to the database.
+        pub token: String,
+        pub database_id: String,
+        pub mapping: PropertyMapping
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct NotionExport {
+        pub created: usize,
+        pub updated: usize
+    }
+
+    fn text(content: &str) -> Value {
+        let content: String = content.chars().take(MAX_TEXT).collect();
+        json!([{ "type": "text", "text": { "content": content } }])
+    }
+
+    /// The Notion properties for `question` under `mapping`.
+    pub fn page_properties(question: &Question, mapping: &PropertyMapping) -> Value {
+        let mut properties = Map::new();
+        properties.insert(mapping.title.clone(), json!({ "title": text(&question.content) }));
+        if let Some(name) = &mapping.tags {
+            // Commas separate options in Notion, so they can't appear in one.
+            let mut tags: Vec<String> = question.tags.iter().map(|tag| tag.replace(',', " ")).collect();
+            tags.sort();
+            let options: Vec<Value> = tags.into_iter().map(|tag| json!({ "name": tag })).collect();
+            properties.insert(name.clone(), json!({ "multi_select": options }));
+        }
+        if let Some(name) = &mapping.status {
+            properties.insert(name.clone(), json!({ "select": { "name": format!("{:?}", question.status()) } }));
+        }
+        let decision = question.decision.as_ref();
+        if let Some(name) = &mapping.decision {
+            properties.insert(name.clone(), json!({ "rich_text": text(decision.map_or("", |decision| decision.choice.as_str())) }));
+        }
+        if let Some(name) = &mapping.rationale {
+            properties.insert(name.clone(), json!({ "rich_text": text(decision.map_or("", |decision| decision.rationale.as_str())) }));
+        }
+        if let Some(name) = &mapping.owner {
+            properties.insert(name.clone(), json!({ "rich_text": text(question.owner.as_ref().map_or("", |owner| owner.id())) }));
+        }
+        if let Some(name) = &mapping.context {
+            let context: Vec<&str> = question.context.iter().filter(|item| item.is_current()).map(|item| item.text.as_str()).collect();
+            properties.insert(name.clone(), json!({ "rich_text": text(&context.join("\n")) }));
+        }
+        Value::Object(properties)
+    }
+
+    fn request(method: &str, url: &str, token: &str) -> ureq::Request {
+        ureq::request(method, url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .set("Notion-Version", NOTION_VERSION)
+            .set("User-Agent", "decis")
+    }
+
+    impl Registry {
+        /// Writes every question to the configured database: a new page for
+        /// questions not exported yet, an update of its page for the rest.
+        /// New page IDs are kept on the questions. Stops at the first
+        /// failed request; pages written before it keep their IDs.
+        pub fn export_to_notion(&mut self, config: &NotionConfig, actor: &Actor) -> Result<NotionExport, ExportError> {
+            let mut export = NotionExport::default();
+            let mut questions: Vec<(Uuid, Option<String>, Value)> = self.questions.values()
+                .map(|question| (
+                    question.identifier,
+                    question.external_id(NOTION_SYSTEM).map(str::to_string),
+                    page_properties(question, &config.mapping)
+                ))
+                .collect();
+            questions.sort_by_key(|(uuid, _, _)| *uuid);
+            for (uuid, page, properties) in questions {
+                match page {
+                    Some(page) => {
+                        request("PATCH", &format!("{}/pages/{}", NOTION_API, page), &config.token)
+                            .send_json(json!({ "properties": properties }))?;
+                        export.updated += 1;
+                    }
+                    None => {
+                        let created: Value = request("POST", &format!("{}/pages", NOTION_API), &config.token)
+                            .send_json(json!({ "parent": { "database_id": config.database_id }, "properties": properties }))?
+                            .into_json()
+                            .map_err(|error| ExportError::InvalidResponse(error.to_string()))?;
+                        let page = created["id"].as_str()
+                            .ok_or_else(|| ExportError::InvalidResponse("page without an id".to_string()))?;
+                        self.set_external_id(uuid.to_string(), NOTION_SYSTEM, page.to_string(), actor)
+                            .expect("the question was just read from the registry");
+                        export.created += 1;
+                    }
+                }
+            }
+            Result::Ok(export)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::HashSet;
+        use crate::{Actor, Decision, Question};
+        use super::*;
+
+        #[test]
+        fn test_page_properties_follow_the_mapping() {
+            let tags: HashSet<String> = ["infra, ops".to_string()].iter().cloned().collect();
+            let options: HashSet<String> = ["Kafka".to_string()].iter().cloned().collect();
+            let mut question = Question::new("Which queue?".to_string(), tags, HashSet::new(), options);
+            question.set_decision(Decision::new("Kafka".to_string(), "Throughput".to_string(), HashSet::new())).unwrap();
+            question.owner = Some(Actor::new("luke"));
+
+            let mapping = PropertyMapping { title: "Question".to_string(), rationale: None, owner: Some("Owner".to_string()), ..PropertyMapping::default() };
+            let properties = page_properties(&question, &mapping);
+            assert_eq!(properties["Question"]["title"][0]["text"]["content"], "Which queue?");
+            assert_eq!(properties["Tags"]["multi_select"][0]["name"], "infra  ops");
+            assert_eq!(properties["Status"]["select"]["name"], "Decided");
+            assert_eq!(properties["Decision"]["rich_text"][0]["text"]["content"], "Kafka");
+            assert_eq!(properties["Owner"]["rich_text"][0]["text"]["content"], "luke");
+            assert!(properties.get("Rationale").is_none());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+
+    #[test]
+    fn test_external_ids_are_recorded_once() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        assert_eq!(registry.set_external_id(identifier.clone(), "notion", "page-1".to_string(), &luke).unwrap(), 0);
+        assert_eq!(registry.set_external_id(identifier.clone(), "notion", "page-1".to_string(), &luke).unwrap(), 0);
+
+        let question = registry.question(&identifier).unwrap();
+        assert_eq!(question.external_id("notion"), Some("page-1"));
+        assert_eq!((question.version, question.history().len()), (0, 2));
+    }
+
+    #[test]
+    fn test_questions_already_imported_are_duplicates() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let imported = |issue: &str| {
+            let mut question = Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new());
+            question.external_ids.insert("github".to_string(), issue.to_string());
+            question
+        };
+        let existing = registry.add_question(imported("a/b#1"), &luke).unwrap();
+
+        let dry_run = registry.validate_questions(vec![imported("a/b#1"), imported("a/b#2")], &luke);
+        match dry_run.failures().as_slice() {
+            [(0, AddQuestionError::DuplicateExternalId { existing: found, .. })] => assert_eq!(found, &existing),
+            failures => panic!("Only the issue imported before should be a duplicate, got {:?}", failures)
+        }
+        assert_eq!(registry.list_questions().len(), 1);
+    }
+}
@@ -0,0 +1,67 @@
+//! Each question's own record of the changes made to it.
+//!
+//! Every change the registry records in the audit log for a question is
+//! also kept on the question, so its history travels with it through
+//! exports and storage and survives audit compaction.
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{Actor, Question, Registry};
+use crate::audit::AuditAction;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub at: DateTime<Utc>,
+    pub actor: Actor,
+    pub action: AuditAction
+}
+
+impl Question {
+    /// Changes to this question, oldest first. Questions saved before
+    /// histories were kept start with their first later change.
+    pub fn history(&self) -> &[Change] {
+        &self.history
+    }
+}
+
+impl Registry {
+    /// Records `action` in the audit log and in the question's history.
+    pub(crate) fn record_change(&mut self, actor: &Actor, question: Uuid, action: AuditAction) {
+        self.audit_log.record(self.now(), actor, Some(question), action);
+        let entry = self.audit_log.entries().last().expect("just recorded");
+        if let Some(question) = self.questions.get_mut(&question) {
+            question.history.push(Change { at: entry.at, actor: entry.actor.clone(), action: entry.action.clone() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::audit::AuditAction;
+
+    #[test]
+    fn test_history_lists_the_questions_changes() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let identifier = registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let other = registry.add_question(Question::new("Which CD?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.edit_question_content(identifier.clone(), "Which CI service?".to_string(), 0, &luke).unwrap();
+        registry.bulk_tag(QuestionSelector::Ids(vec![identifier.clone()]), &["infra".to_string()], &[], &luke).unwrap();
+        registry.defer_question(identifier.clone(), "After the audit".to_string(), None, 2, &luke).unwrap();
+        registry.edit_question_content(other, "Which CD service?".to_string(), 0, &luke).unwrap();
+
+        let question = registry.get_question(identifier).unwrap();
+        let actions: Vec<&AuditAction> = question.history().iter().map(|change| &change.action).collect();
+        assert_eq!(actions, vec![
+            &AuditAction::QuestionAdded,
+            &AuditAction::ContentEdited("Which CI?".to_string()),
+            &AuditAction::QuestionTagged("infra".to_string()),
+            &AuditAction::StatusChanged(status::QuestionStatus::Deferred)
+        ]);
+        assert!(question.history().iter().all(|change| change.actor == luke));
+    }
+}
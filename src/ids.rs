@@ -0,0 +1,116 @@
+//! Where the registry gets identifiers for new questions and comments.
+//!
+//! Identifiers are random by default. `Registry::set_id_generator` with a
+//! `SeededIds` makes them repeat from run to run, for snapshot tests and
+//! for replaying an import into a fresh registry.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::{Builder, Uuid, Variant, Version};
+use crate::{Question, Registry};
+
+pub trait IdGenerator: Send + Sync {
+    fn next_id(&self) -> Uuid;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {
+    fn next_id(&self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+pub(crate) fn random_ids() -> Arc<dyn IdGenerator> {
+    Arc::new(RandomIds)
+}
+
+/// The same sequence of random-looking version 4 identifiers for the same
+/// seed. Clones share the sequence.
+#[derive(Clone, Debug)]
+pub struct SeededIds {
+    seed: u64,
+    issued: Arc<AtomicU64>
+}
+
+impl SeededIds {
+    pub fn new(seed: u64) -> SeededIds {
+        SeededIds { seed, issued: Arc::new(AtomicU64::new(0)) }
+    }
+}
+
+/// SplitMix64's output function.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+impl IdGenerator for SeededIds {
+    fn next_id(&self) -> Uuid {
+        let issued = self.issued.fetch_add(1, Ordering::Relaxed);
+        let state = self.seed.wrapping_add(issued.wrapping_mul(2).wrapping_mul(0x9e3779b97f4a7c15));
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&mix(state).to_be_bytes());
+        bytes[8..].copy_from_slice(&mix(state.wrapping_add(0x9e3779b97f4a7c15)).to_be_bytes());
+        Builder::from_bytes(bytes).set_variant(Variant::RFC4122).set_version(Version::Random).build()
+    }
+}
+
+impl Question {
+    /// Gives the question its identifier up front, instead of having the
+    /// registry assign one when it is added.
+    pub fn with_id(mut self, identifier: Uuid) -> Question {
+        self.identifier = identifier;
+        self
+    }
+}
+
+impl Registry {
+    /// Replaces the registry's identifier generator. It isn't persisted; a
+    /// loaded registry goes back to random identifiers.
+    pub fn set_id_generator<G: IdGenerator + 'static>(&mut self, ids: G) {
+        self.ids = Arc::new(ids);
+    }
+
+    pub(crate) fn next_id(&self) -> Uuid {
+        self.ids.next_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::ids::*;
+
+    #[test]
+    fn test_seeded_ids_repeat() {
+        let first: Vec<uuid::Uuid> = (0..3).map(|_| SeededIds::new(7)).map(|ids| ids.next_id()).collect();
+        assert!(first.iter().all(|id| *id == first[0]));
+        let ids = SeededIds::new(7);
+        let sequence: HashSet<uuid::Uuid> = (0..100).map(|_| ids.next_id()).collect();
+        assert_eq!(sequence.len(), 100);
+        assert_eq!(first[0].get_version(), Some(uuid::Version::Random));
+        assert_ne!(SeededIds::new(8).next_id(), first[0]);
+    }
+
+    #[test]
+    fn test_registry_assigns_ids_from_its_generator() {
+        let luke = Actor::new("luke");
+        let add = |content: &str| -> (String, String) {
+            let mut registry = Registry::new();
+            registry.set_id_generator(SeededIds::new(42));
+            let identifier = registry.add_question(Question::new(content.to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+            let comment = registry.add_comment(identifier.clone(), "Any".to_string(), None, &luke).unwrap();
+            (identifier, comment)
+        };
+        assert_eq!(add("Which CI?"), add("Which CD?"));
+
+        let chosen = uuid::Uuid::new_v4();
+        let mut registry = Registry::new();
+        registry.set_id_generator(SeededIds::new(42));
+        assert_eq!(registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()).with_id(chosen), &luke).unwrap(), chosen.to_string());
+    }
+}
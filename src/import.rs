@@ -0,0 +1,235 @@
+//! Backfilling questions from external systems.
+//!
+//! Enable the `github` feature for [`from_github`], which turns labelled GitHub
+//! issues into questions.
+
+#[cfg(feature = "github")]
+pub use self::github::{from_github, question_from_issue, Comment, ImportError, Issue, Label, User, GITHUB_SYSTEM};
+
+#[cfg(feature = "github")]
+mod github {
+    use std::collections::HashSet;
+    use serde::Deserialize;
+    use crate::{Actor, Decision, Question};
+    use crate::codes::{Coded, ErrorCode};
+    use crate::messages::{self, Catalog, Localized};
+
+    const GITHUB_API: &str = "https://api.github.com";
+    const PAGE_SIZE: usize = 100;
+    /// The system issue references are kept under, see `Question::external_id`.
+    pub const GITHUB_SYSTEM: &str = "github";
+
+    #[derive(Debug)]
+    pub enum ImportError {
+        Http(u16),
+        Transport(String),
+        InvalidResponse(String)
+    }
+
+    impl Localized for ImportError {
+        fn localize(&self, catalog: &dyn Catalog) -> String {
+            match self {
+                ImportError::Http(status) => messages::text(catalog, "remote.http", &[("status", status.to_string())]),
+                ImportError::Transport(error) => messages::text(catalog, "remote.transport", &[("error", error.clone())]),
+                ImportError::InvalidResponse(error) => messages::text(catalog, "remote.invalid-response", &[("error", error.clone())])
+            }
+        }
+    }
+
+    impl Coded for ImportError {
+        fn code(&self) -> ErrorCode {
+            match self {
+                ImportError::Http(_) => ErrorCode::RemoteHttpError,
+                ImportError::Transport(_) => ErrorCode::RemoteUnreachable,
+                ImportError::InvalidResponse(_) => ErrorCode::RemoteInvalidResponse
+            }
+        }
+    }
+
+    messages::display_localized!(ImportError);
+
+    impl From<ureq::Error> for ImportError {
+        fn from(error: ureq::Error) -> ImportError {
+            match error {
+                ureq::Error::Status(code, _) => ImportError::Http(code),
+                ureq::Error::Transport(transport) => ImportError::Transport(transport.to_string())
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct User {
+        pub login: String
+    }
+
+    #[derive(Deserialize)]
+    pub struct Label {
+        pub name: String
+    }
+
+    #[derive(Deserialize)]
+    pub struct Issue {
+        pub number: u64,
+        pub title: String,
+        pub body: Option<String>,
+        pub state: String,
+        pub labels: Vec<Label>,
+        pub comments: u64,
+        pub comments_url: String,
+        /// Present when GitHub hands us a pull request through the issues endpoint.
+        pub pull_request: Option<serde_json::Value>
+    }
+
+    #[derive(Deserialize)]
+    pub struct Comment {
+        pub body: Option<String>,
+        pub user: Option<User>
+    }
+
+    /// Pulls every issue in `repo` (`owner/name`) labelled `label_filter` and maps
+    /// it to a question: title to content, labels to tags, body to context and,
+    /// for closed issues, the last comment to the decision.
+    ///
+    /// Labels become tags on the returned questions but are not registered, so
+    /// add any missing ones to the registry before calling `add_question`.
+    /// Each question keeps its issue as `owner/name#number` under
+    /// `GITHUB_SYSTEM`, so adding an issue imported before is rejected as a
+    /// duplicate. `Registry::validate_questions` shows what adding them would do.
+    /// A `GITHUB_TOKEN` environment variable is used for authentication when set.
+    pub fn from_github(repo: &str, label_filter: &str) -> Result<Vec<Question>, ImportError> {
+        let token = std::env::var("GITHUB_TOKEN").ok();
+        let mut questions = Vec::new();
+        let mut page = 1;
+        loop {
+            let url = format!("{}/repos/{}/issues", GITHUB_API, repo);
+            let issues: Vec<Issue> = get(&url, token.as_deref())
+                .query("labels", label_filter)
+                .query("state", "all")
+                .query("per_page", &PAGE_SIZE.to_string())
+                .query("page", &page.to_string())
+                .call()?
+                .into_json()
+                .map_err(|error| ImportError::InvalidResponse(error.to_string()))?;
+            let fetched = issues.len();
+
+            for issue in issues.into_iter().filter(|issue| issue.pull_request.is_none()) {
+                let closing_comment = if issue.state == "closed" && issue.comments > 0 {
+                    last_comment(&issue, token.as_deref())?
+                } else {
+                    None
+                };
+                let mut question = question_from_issue(&issue, closing_comment.as_ref());
+                question.external_ids.insert(GITHUB_SYSTEM.to_string(), format!("{}#{}", repo, issue.number));
+                questions.push(question);
+            }
+
+            if fetched < PAGE_SIZE {
+                return Result::Ok(questions);
+            }
+            page += 1;
+        }
+    }
+
+    /// Maps a single issue (and the comment that closed it, if any) to a question.
+    pub fn question_from_issue(issue: &Issue, closing_comment: Option<&Comment>) -> Question {
+        let tags: HashSet<String> = issue.labels.iter().map(|label| label.name.clone()).collect();
+        let mut context: HashSet<String> = HashSet::new();
+        if let Some(body) = non_empty(issue.body.as_deref()) {
+            context.insert(body.to_string());
+        }
+        let mut question = Question::new(issue.title.clone(), tags, context, HashSet::new());
+
+        if let Some(comment) = closing_comment {
+            if let Some(body) = non_empty(comment.body.as_deref()) {
+                let choice = body.lines().next().unwrap_or_default().trim().to_string();
+                let mut decision_makers: HashSet<Actor> = HashSet::new();
+                if let Some(user) = &comment.user {
+                    decision_makers.insert(Actor::new(&user.login));
+                }
+                question.add_option(choice.clone());
+                // A freshly built question has no decision yet, so this cannot fail.
+                let _ = question.set_decision(Decision::new(choice, body.to_string(), decision_makers));
+            }
+        }
+        question
+    }
+
+    fn last_comment(issue: &Issue, token: Option<&str>) -> Result<Option<Comment>, ImportError> {
+        // Comments are returned oldest first; jump straight to the page holding the last one.
+        let last_page = (issue.comments as usize).div_ceil(PAGE_SIZE);
+        let comments: Vec<Comment> = get(&issue.comments_url, token)
+            .query("per_page", &PAGE_SIZE.to_string())
+            .query("page", &last_page.to_string())
+            .call()?
+            .into_json()
+            .map_err(|error| ImportError::InvalidResponse(error.to_string()))?;
+        Result::Ok(comments.into_iter().last())
+    }
+
+    fn get(url: &str, token: Option<&str>) -> ureq::Request {
+        let request = ureq::get(url)
+            .set("Accept", "application/vnd.github+json")
+            .set("User-Agent", "decis");
+        match token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request
+        }
+    }
+
+    fn non_empty(text: Option<&str>) -> Option<&str> {
+        text.map(str::trim).filter(|text| !text.is_empty())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn issue(json: &str) -> Issue {
+            serde_json::from_str(json).unwrap()
+        }
+
+        #[test]
+        fn test_open_issue_maps_to_undecided_question() {
+            let issue = issue(r#"{
+                "number": 1,
+                "title": "Which queue should we use?",
+                "body": "We need at-least-once delivery.",
+                "state": "open",
+                "labels": [{"name": "decision"}, {"name": "infra"}],
+                "comments": 0,
+                "comments_url": "https://api.github.com/repos/a/b/issues/1/comments",
+                "pull_request": null
+            }"#);
+            let question = question_from_issue(&issue, None);
+
+            assert!(question.get_tags().contains("decision"));
+            assert!(question.get_tags().contains("infra"));
+            assert!(question.get_context().contains("We need at-least-once delivery."));
+            assert!(question.get_decision().is_none());
+        }
+
+        #[test]
+        fn test_closing_comment_becomes_decision() {
+            let issue = issue(r#"{
+                "number": 1,
+                "title": "Which queue should we use?",
+                "body": null,
+                "state": "closed",
+                "labels": [],
+                "comments": 3,
+                "comments_url": "https://api.github.com/repos/a/b/issues/1/comments"
+            }"#);
+            let comment: Comment = serde_json::from_str(r#"{
+                "body": "RabbitMQ\nThe team already operates it.",
+                "user": {"login": "ada"}
+            }"#).unwrap();
+            let question = question_from_issue(&issue, Some(&comment));
+
+            assert!(question.get_context().is_empty());
+            assert!(question.get_options().contains("RabbitMQ"));
+            let decision = question.get_decision().unwrap();
+            assert_eq!(decision.choice, "RabbitMQ");
+            assert!(decision.decision_makers.contains(&Actor::new("ada")));
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Lookup tables kept alongside the registry's questions, so the common
+//! queries don't scan every question.
+//!
+//! An index is built on first use and then kept up to date by the methods
+//! that change what it covers. It isn't persisted; a loaded registry builds
+//! its indices again when first queried.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use uuid::Uuid;
+use crate::{GetQuestionError, Question, Registry};
+
+#[derive(Clone, Default)]
+pub(crate) struct Indices {
+    by_tag: OnceLock<HashMap<String, HashSet<Uuid>>>
+}
+
+impl Registry {
+    fn tag_index(&self) -> &HashMap<String, HashSet<Uuid>> {
+        self.indices.by_tag.get_or_init(|| {
+            let mut by_tag: HashMap<String, HashSet<Uuid>> = HashMap::new();
+            for question in self.questions.values() {
+                for tag in &question.tags {
+                    by_tag.entry(tag.clone()).or_default().insert(question.identifier);
+                }
+            }
+            by_tag
+        })
+    }
+
+    /// The questions carrying exactly `tag`.
+    pub(crate) fn indexed_questions_with_tag(&self, tag: &str) -> Vec<&Question> {
+        self.tag_index().get(tag)
+            .map(|identifiers| identifiers.iter().filter_map(|identifier| self.questions.get(identifier)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Records in the indices that `question` now carries `tag`.
+    pub(crate) fn index_tagged(&mut self, question: Uuid, tag: &str) {
+        if let Some(by_tag) = self.indices.by_tag.get_mut() {
+            by_tag.entry(tag.to_string()).or_default().insert(question);
+        }
+    }
+
+    /// Records in the indices that `question` no longer carries `tag`.
+    pub(crate) fn index_untagged(&mut self, question: Uuid, tag: &str) {
+        if let Some(identifiers) = self.indices.by_tag.get_mut().and_then(|by_tag| by_tag.get_mut(tag)) {
+            identifiers.remove(&question);
+        }
+    }
+
+    /// Like `get_question`, without copying the question.
+    pub fn question(&self, identifier: &str) -> Result<&Question, GetQuestionError> {
+        let uuid = Uuid::from_str(identifier).map_err(|_| GetQuestionError::InvalidUUID)?;
+        self.questions.get(&uuid).ok_or(GetQuestionError::DoesNotExist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+
+    #[test]
+    fn test_tag_index_follows_changes() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        registry.add_tag(&"ops".to_string(), &luke).unwrap();
+        let infra: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let first = registry.add_question(Question::new("Which CI?".to_string(), infra.clone(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        assert_eq!(registry.list_questions_with_tag("infra").len(), 1);
+
+        // Changes after the index is built are applied to it.
+        registry.add_question(Question::new("Which CD?".to_string(), infra, HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.bulk_tag(QuestionSelector::Ids(vec![first.clone()]), &["ops".to_string()], &["infra".to_string()], &luke).unwrap();
+        assert_eq!(registry.list_questions_with_tag("infra").len(), 1);
+        assert_eq!(registry.list_questions_with_tag("ops")[0].identifier, first);
+        registry.rename_tag("ops", "operations", &luke).unwrap();
+        assert!(registry.list_questions_with_tag("ops").is_empty());
+        assert_eq!(registry.list_questions_with_tag("operations").len(), 1);
+
+        let reloaded = Registry::deserialize_json(&registry.serialize_json()).unwrap();
+        assert_eq!(reloaded.list_questions_with_tag("operations").len(), 1);
+        assert_eq!(reloaded.question(&first).unwrap().version(), registry.question(&first).unwrap().version());
+    }
+}
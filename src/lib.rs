@@ -1,136 +1,677 @@
-use std::collections::{HashSet, HashMap};
+use std::collections::{BTreeMap, HashSet, HashMap};
+use std::collections::hash_map::Entry;
 use uuid::Uuid;
 use std::str::FromStr;
 use serde::{Serialize, Deserialize};
-use std::io::{Cursor, Read};
-use std::fs::File;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use crate::audit::{AuditAction, AuditLog};
+use crate::clock::Clock;
+use crate::ids::IdGenerator;
+use crate::index::Indices;
+use crate::messages::Catalog;
+use crate::migrations::{LoadError, UnknownFields, Versioned};
+use crate::simulate::{Distribution, OutcomeError};
+use crate::tree::Branch;
+use crate::estimate::Estimate;
+use crate::criteria::Criterion;
+use crate::arguments::ProsCons;
+use crate::comments::Comment;
+use crate::attachments::Attachment;
+use crate::context::ContextItem;
+use crate::history::Change;
+use crate::priority::Priority;
+use crate::settings::Settings;
+use crate::status::{Cancellation, Deferral, PastDecision, QuestionStatus};
+use crate::validation::Validator;
+use std::sync::Arc;
 
-#[derive(Clone, Serialize)]
+pub mod activity;
+pub mod arguments;
+pub mod attachments;
+pub mod audit;
+pub mod calendar;
+pub mod clock;
+pub mod codes;
+pub mod comments;
+pub mod context;
+pub mod criteria;
+pub mod estimate;
+pub mod export;
+pub mod history;
+pub mod ids;
+pub mod import;
+pub mod index;
+pub mod merge;
+pub mod messages;
+pub mod migrations;
+pub mod owner;
+pub mod page;
+pub mod persistent;
+pub mod policy;
+pub mod priority;
+pub mod privacy;
+pub mod query;
+pub mod search;
+pub mod settings;
+pub mod simulate;
+pub mod snippet;
+pub mod storage;
+pub mod status;
+pub mod stream;
+pub mod subset;
+pub mod suggest;
+pub mod sync;
+pub mod tags;
+pub mod tiebreak;
+pub mod tree;
+pub mod validation;
+pub mod vault;
+#[cfg(feature = "analytics")]
+pub mod analytics;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+#[cfg(feature = "backup")]
+pub mod backup;
+#[cfg(feature = "analytics")]
+pub mod dashboard;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "markdown")]
+pub mod render;
+#[cfg(feature = "test-util")]
+pub mod testing;
+
+/// A person (or system) performing changes against the registry.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Actor {
+    id: String
+}
+
+impl Actor {
+    pub fn new(id: &str) -> Actor {
+        Actor { id: id.to_string() }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Decision {
     choice: String,
     rationale: String,
-    decision_makers: HashSet<String>,
+    decision_makers: HashSet<Actor>,
+    /// When a temporary decision stops standing and needs revisiting.
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl Decision {
+    pub fn new(choice: String, rationale: String, decision_makers: HashSet<Actor>) -> Decision {
+        Decision {
+            choice,
+            rationale,
+            decision_makers,
+            expires_at: None
+        }
+    }
+
+    /// The same decision, standing only until `expires_at`.
+    pub fn with_expiry(mut self, expires_at: DateTime<Utc>) -> Decision {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    pub fn get_expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn get_choice(&self) -> &str {
+        &self.choice
+    }
+
+    pub fn get_rationale(&self) -> &str {
+        &self.rationale
+    }
+
+    pub fn get_decision_makers(&self) -> &HashSet<Actor> {
+        &self.decision_makers
+    }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Question {
     identifier: Uuid,
     content: String,
     tags: HashSet<String>,
-    context: HashSet<String>,
-    options: HashSet<String>,
-    decision: Option<Decision>
+    #[serde(deserialize_with = "context::deserialize_items")]
+    context: Vec<ContextItem>,
+    /// In the order they were added (or last arranged with `Registry::reorder_options`).
+    options: Vec<String>,
+    decision: Option<Decision>,
+    /// `Decided` is never stored here; see `Question::status`.
+    #[serde(default)]
+    status: QuestionStatus,
+    #[serde(default)]
+    deferral: Option<Deferral>,
+    #[serde(default)]
+    cancellation: Option<Cancellation>,
+    #[serde(default)]
+    decision_history: Vec<PastDecision>,
+    #[serde(default)]
+    owner: Option<Actor>,
+    #[serde(default)]
+    priority: Priority,
+    #[serde(default)]
+    history: Vec<Change>,
+    /// Estimated outcomes per option and metric, see `simulate`.
+    #[serde(default)]
+    outcomes: BTreeMap<String, BTreeMap<String, Distribution>>,
+    /// Where each option leads, see `tree`.
+    #[serde(default)]
+    branches: BTreeMap<String, Vec<Branch>>,
+    #[serde(default)]
+    estimates: BTreeMap<String, Estimate>,
+    /// Option -> criterion -> evaluator -> score, see `criteria`.
+    #[serde(default)]
+    scores: BTreeMap<String, BTreeMap<String, BTreeMap<Actor, f64>>>,
+    #[serde(default)]
+    arguments: BTreeMap<String, ProsCons>,
+    #[serde(default)]
+    comments: Vec<Comment>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// The question's identifier in other systems it was exported to, by
+    /// system, see `export`.
+    #[serde(default)]
+    external_ids: BTreeMap<String, String>,
+    /// Bumped on every change made through the registry, see `UpdateQuestionError::Conflict`.
+    #[serde(default)]
+    version: u64,
+    #[serde(flatten)]
+    unknown_fields: UnknownFields
 }
 
+/// The lightweight view of a question returned by listings. Fetch the full
+/// question, with its context, options and decision, via `Registry::get_question`.
+#[derive(Clone, Debug, Serialize)]
+pub struct QuestionSummary {
+    pub identifier: String,
+    pub content: String,
+    pub tags: HashSet<String>,
+    pub decided: bool,
+    pub status: QuestionStatus,
+    /// The decision has passed its expiry and is due to be revisited.
+    pub expired: bool,
+    pub owner: Option<Actor>,
+    pub priority: Priority,
+    pub version: u64
+}
+
+#[derive(Debug)]
 pub enum SetDecisionError {
-    AlreadyExists
+    AlreadyExists,
+    /// Only open questions can be decided.
+    NotOpen(QuestionStatus)
 }
 
 impl Question {
-    fn new(content: String, tags: HashSet<String>, context: HashSet<String>, options: HashSet<String>) -> Question {
+    /// The question gets its identifier from the registry it is added to,
+    /// unless given one with `Question::with_id`.
+    pub fn new(content: String, tags: HashSet<String>, context: HashSet<String>, options: HashSet<String>) -> Question {
         Question {
-            identifier: Uuid::new_v4(),
+            identifier: Uuid::nil(),
             content,
             tags,
-            context,
-            options,
-            decision: None
+            context: context::items_from(context),
+            options: {
+                // A set has no order of its own to preserve.
+                let mut options: Vec<String> = options.into_iter().collect();
+                options.sort();
+                options
+            },
+            decision: None,
+            status: QuestionStatus::Open,
+            deferral: None,
+            cancellation: None,
+            decision_history: Vec::new(),
+            owner: None,
+            priority: Priority::Normal,
+            history: Vec::new(),
+            outcomes: BTreeMap::new(),
+            branches: BTreeMap::new(),
+            estimates: BTreeMap::new(),
+            scores: BTreeMap::new(),
+            arguments: BTreeMap::new(),
+            comments: Vec::new(),
+            attachments: Vec::new(),
+            external_ids: BTreeMap::new(),
+            version: 0,
+            unknown_fields: UnknownFields::new()
         }
     }
 
-    fn add_context(&mut self, context_item: String){
-        self.context.insert(context_item);
+    pub fn add_context(&mut self, context_item: String){
+        self.add_context_item(ContextItem::new(context_item, None, None));
+    }
+
+    /// The context texts; see `context_items` for their order and provenance.
+    pub fn get_context(&self) -> HashSet<String>{
+        self.context.iter().map(|item| item.text.clone()).collect()
     }
 
-    fn get_context(&self) -> HashSet<String>{
-        self.context.clone()
+    /// Appends the option unless it is already present.
+    pub fn add_option(&mut self, option: String){
+        if !self.has_option(&option) {
+            self.options.push(option);
+        }
     }
 
-    fn add_option(&mut self, option: String){
-        self.options.insert(option);
+    /// The options as a set; see `ordered_options` for their order.
+    pub fn get_options(&self) -> HashSet<String>{
+        self.options.iter().cloned().collect()
     }
 
-    fn get_options(&self) -> HashSet<String>{
-        self.options.clone()
+    pub fn ordered_options(&self) -> &[String] {
+        &self.options
     }
 
-    fn set_decision(&mut self, decision: Decision) -> Result<(), SetDecisionError>{
+    pub fn has_option(&self, option: &str) -> bool {
+        self.options.iter().any(|existing| existing == option)
+    }
+
+    pub fn set_decision(&mut self, decision: Decision) -> Result<(), SetDecisionError>{
         match self.decision {
+            None if self.status != QuestionStatus::Open => Result::Err(SetDecisionError::NotOpen(self.status)),
             None => {
                 self.decision = Some(decision);
                 Result::Ok(())
             }
-            Some(_) => return Result::Err(SetDecisionError::AlreadyExists)
+            Some(_) => Result::Err(SetDecisionError::AlreadyExists)
         }
     }
 
-    fn get_decision(&self) -> Option<Decision> {
+    pub fn get_decision(&self) -> Option<Decision> {
         self.decision.clone()
     }
+
+    pub fn get_tags(&self) -> HashSet<String> {
+        self.tags.clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// With `expired` as of `now`, which registries take from their clock.
+    pub(crate) fn summary_at(&self, now: DateTime<Utc>) -> QuestionSummary {
+        QuestionSummary {
+            identifier: self.identifier.to_string(),
+            content: self.content.clone(),
+            tags: self.tags.clone(),
+            decided: self.decision.is_some(),
+            status: self.status(),
+            expired: self.decision.as_ref().is_some_and(|decision| decision.is_expired(now)),
+            owner: self.owner.clone(),
+            priority: self.priority,
+            version: self.version
+        }
+    }
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Registry {
     tags: HashSet<String>,
     questions: HashMap<Uuid, Question>,
+    #[serde(default)]
+    audit_log: AuditLog,
+    #[serde(default)]
+    criteria: BTreeMap<String, Criterion>,
+    #[serde(default)]
+    settings: Settings,
+    #[serde(skip)]
+    validators: Vec<Arc<dyn Validator>>,
+    #[serde(skip, default = "clock::system_clock")]
+    clock: Arc<dyn Clock>,
+    #[serde(skip, default = "ids::random_ids")]
+    ids: Arc<dyn IdGenerator>,
+    #[serde(skip, default = "messages::english")]
+    catalog: Arc<dyn Catalog>,
+    #[serde(skip)]
+    indices: Indices,
+    #[serde(flatten)]
+    unknown_fields: UnknownFields,
 }
 
 #[derive(Debug)]
 pub enum AddTagErrors {
-    AlreadyExists
+    AlreadyExists,
+    Invalid(Vec<tags::TagRuleViolation>)
 }
 #[derive(Debug)]
 pub enum AddQuestionError {
     AlreadyExists,
-    UsesNonExistentTags(Vec<String>)
+    UsesNonExistentTags(Vec<String>),
+    /// Messages from the registry's validators.
+    Rejected(Vec<String>),
+    /// Another question already has the same identifier in `system`, so
+    /// this one was most likely imported before.
+    DuplicateExternalId { system: String, id: String, existing: String }
 }
 #[derive(Debug)]
 pub enum GetQuestionError {
     InvalidUUID,
     DoesNotExist
 }
+#[derive(Debug)]
+pub enum UpdateQuestionError {
+    Question(GetQuestionError),
+    /// The question changed since the caller read it; holds its current state.
+    Conflict(Box<Question>)
+}
+#[derive(Debug)]
+pub enum SetQuestionDecisionError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    Decision(SetDecisionError),
+    /// Messages from the registry's validators.
+    Rejected(Vec<String>)
+}
+
+#[derive(Debug)]
+pub enum ReorderOptionsError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    UnknownOption(String),
+    /// The new order must list every existing option exactly once.
+    NotAPermutation
+}
+
+#[derive(Debug)]
+pub enum SetOptionOutcomeError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    Outcome(OutcomeError)
+}
+
+#[derive(Debug)]
+pub enum BulkTagError {
+    UsesNonExistentTags(Vec<String>),
+    Question(String, GetQuestionError)
+}
+
+/// Which questions a bulk operation applies to.
+pub enum QuestionSelector<'a> {
+    Ids(Vec<String>),
+    Matching(&'a dyn Fn(&Question) -> bool)
+}
+
+/// Per-question outcomes of `Registry::add_questions`, in submission order.
+#[derive(Debug)]
+pub struct BulkResult {
+    pub outcomes: Vec<Result<String, AddQuestionError>>
+}
+
+impl BulkResult {
+    /// Identifiers of the questions that were inserted.
+    pub fn inserted(&self) -> Vec<&String> {
+        self.outcomes.iter().filter_map(|outcome| outcome.as_ref().ok()).collect()
+    }
+
+    /// Position in the submitted batch and reason for each rejected question.
+    pub fn failures(&self) -> Vec<(usize, &AddQuestionError)> {
+        self.outcomes.iter()
+            .enumerate()
+            .filter_map(|(index, outcome)| outcome.as_ref().err().map(|error| (index, error)))
+            .collect()
+    }
+
+    pub fn all_inserted(&self) -> bool {
+        self.outcomes.iter().all(Result::is_ok)
+    }
+}
+
+impl From<UpdateQuestionError> for SetQuestionDecisionError {
+    fn from(error: UpdateQuestionError) -> SetQuestionDecisionError {
+        match error {
+            UpdateQuestionError::Question(error) => SetQuestionDecisionError::Question(error),
+            UpdateQuestionError::Conflict(current) => SetQuestionDecisionError::Conflict(current)
+        }
+    }
+}
+
+impl From<UpdateQuestionError> for ReorderOptionsError {
+    fn from(error: UpdateQuestionError) -> ReorderOptionsError {
+        match error {
+            UpdateQuestionError::Question(error) => ReorderOptionsError::Question(error),
+            UpdateQuestionError::Conflict(current) => ReorderOptionsError::Conflict(current)
+        }
+    }
+}
+
+impl From<UpdateQuestionError> for SetOptionOutcomeError {
+    fn from(error: UpdateQuestionError) -> SetOptionOutcomeError {
+        match error {
+            UpdateQuestionError::Question(error) => SetOptionOutcomeError::Question(error),
+            UpdateQuestionError::Conflict(current) => SetOptionOutcomeError::Conflict(current)
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
 
 impl Registry {
     pub fn new() -> Registry {
         Registry {
             tags: Default::default(),
-            questions: Default::default()
+            questions: Default::default(),
+            audit_log: AuditLog::new(),
+            criteria: BTreeMap::new(),
+            settings: Settings::default(),
+            validators: Vec::new(),
+            clock: clock::system_clock(),
+            ids: ids::random_ids(),
+            catalog: messages::english(),
+            indices: Indices::default(),
+            unknown_fields: UnknownFields::new()
         }
     }
 
-    pub fn add_tag(&mut self, tag: &String) -> Result<bool, AddTagErrors> {
-        return if self.tags.contains(tag) {
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit_log
+    }
+
+    /// Shrinks the persisted registry by moving audit entries older than
+    /// `before` out of it, into `archive` when one is given.
+    pub fn compact(&mut self, before: DateTime<Utc>, archive: Option<&Path>) -> std::io::Result<usize> {
+        self.audit_log.compact(before, archive)
+    }
+
+    /// Runs `operations` against the registry, keeping every change if it
+    /// returns `Ok` and rolling all of them back (audit entries included) if it
+    /// returns `Err`. The registry is snapshotted up front, so keep transactions
+    /// to batches where that copy is acceptable.
+    pub fn transaction<T, E, F>(&mut self, operations: F) -> Result<T, E>
+        where F: FnOnce(&mut Registry) -> Result<T, E> {
+        let snapshot = self.clone();
+        let result = operations(self);
+        if result.is_err() {
+            *self = snapshot;
+        }
+        result
+    }
+
+    /// Tags are compared under the registry's `TagNormalization` and must
+    /// follow its `TagRules`.
+    #[allow(clippy::ptr_arg)] // Kept as `&String` for existing callers.
+    pub fn add_tag(&mut self, tag: &String, actor: &Actor) -> Result<bool, AddTagErrors> {
+        if self.resolve_tag(tag).is_some() {
             Result::Err(AddTagErrors::AlreadyExists)
         } else {
+            let tag = self.settings.tag_normalization.clean(tag);
+            self.settings.tag_rules.check(&tag).map_err(AddTagErrors::Invalid)?;
             self.tags.insert(tag.clone());
+            self.audit_log.record(self.now(), actor, None, AuditAction::TagAdded(tag));
             Result::Ok(true)
         }
     }
 
     pub fn get_tags(&self) -> HashSet<String> {
-        return self.tags.clone()
+        self.tags.clone()
+    }
+
+    /// The question's tags must exist; they are stored in the registry's spelling.
+    pub fn add_question(&mut self, mut question: Question, actor: &Actor) -> Result<String, AddQuestionError> {
+        question.tags = self.resolve_tags(&question.tags).map_err(AddQuestionError::UsesNonExistentTags)?;
+        self.insert_question(question, actor)
     }
 
-    pub fn add_question(&mut self, question: Question) -> Result<String, AddQuestionError> {
-        let tag_diff: HashSet<_>= question.tags.difference(&self.tags).collect();
-        if !tag_diff.is_empty() {
-            let mut response : Vec<String> = Vec::new();
-            for tag in tag_diff.into_iter().enumerate() {
-                response.push(String::from(tag.1));
+    /// Adds a batch of questions, inserting every valid one. Tags are checked
+    /// against the registry once for the whole batch rather than per question.
+    pub fn add_questions(&mut self, questions: Vec<Question>, actor: &Actor) -> BulkResult {
+        let mut resolved: HashMap<String, Option<String>> = HashMap::new();
+        for tag in questions.iter().flat_map(|question| question.tags.iter()) {
+            if !resolved.contains_key(tag) {
+                resolved.insert(tag.clone(), self.resolve_tag(tag).cloned());
+            }
+        }
+        let outcomes = questions.into_iter().map(|mut question| {
+            let missing: Vec<String> = question.tags.iter().filter(|tag| resolved[*tag].is_none()).cloned().collect();
+            if missing.is_empty() {
+                question.tags = question.tags.iter().filter_map(|tag| resolved[tag].clone()).collect();
+                self.insert_question(question, actor)
+            } else {
+                Result::Err(AddQuestionError::UsesNonExistentTags(missing))
             }
+        }).collect();
+        BulkResult { outcomes }
+    }
 
-            return Result::Err(AddQuestionError::UsesNonExistentTags(response));
+    /// The outcomes `add_questions` would have, without changing the
+    /// registry. The identifiers in them are only examples.
+    pub fn validate_questions(&self, questions: Vec<Question>, actor: &Actor) -> BulkResult {
+        self.scratch().add_questions(questions, actor)
+    }
+
+    /// A copy to try changes on. It has its own identifiers and a stopped
+    /// clock, so trying changes leaves this registry's sequences where they
+    /// were.
+    pub(crate) fn scratch(&self) -> Registry {
+        let mut scratch = self.clone();
+        scratch.set_clock(clock::FixedClock::new(Utc::now()));
+        scratch.set_id_generator(ids::RandomIds);
+        scratch
+    }
+
+    /// Adds and removes tags on every selected question, returning how many
+    /// questions actually changed. Nothing is modified unless all the selected
+    /// identifiers resolve and every tag in `add` exists.
+    pub fn bulk_tag(&mut self, selector: QuestionSelector, add: &[String], remove: &[String], actor: &Actor) -> Result<usize, BulkTagError> {
+        let unknown_tags: Vec<String> = add.iter().filter(|tag| self.resolve_tag(tag).is_none()).cloned().collect();
+        if !unknown_tags.is_empty() {
+            return Result::Err(BulkTagError::UsesNonExistentTags(unknown_tags));
         }
-        return if self.questions.contains_key(&question.identifier){
-            Result::Err(AddQuestionError::AlreadyExists)
-        } else {
-            let identifier = question.identifier.to_string();
-            self.questions.insert(question.identifier, question);
-            Result::Ok(identifier)
+        let add: Vec<String> = add.iter().filter_map(|tag| self.resolve_tag(tag).cloned()).collect();
+        let remove: Vec<String> = remove.iter().filter_map(|tag| self.resolve_tag(tag).cloned()).collect();
+        let selected: Vec<Uuid> = match selector {
+            QuestionSelector::Ids(identifiers) => {
+                let mut uuids = Vec::new();
+                for identifier in identifiers {
+                    let uuid = Uuid::from_str(&identifier)
+                        .map_err(|_| BulkTagError::Question(identifier.clone(), GetQuestionError::InvalidUUID))?;
+                    if !self.questions.contains_key(&uuid) {
+                        return Result::Err(BulkTagError::Question(identifier, GetQuestionError::DoesNotExist));
+                    }
+                    uuids.push(uuid);
+                }
+                uuids
+            }
+            QuestionSelector::Matching(predicate) => self.questions.values()
+                .filter(|question| predicate(question))
+                .map(|question| question.identifier)
+                .collect()
+        };
+
+        let mut modified = 0;
+        for uuid in selected {
+            let question = self.questions.get_mut(&uuid).expect("selected questions exist");
+            let mut actions = Vec::new();
+            for tag in &add {
+                if question.tags.insert(tag.clone()) {
+                    actions.push(AuditAction::QuestionTagged(tag.clone()));
+                }
+            }
+            for tag in &remove {
+                if question.tags.remove(tag) {
+                    actions.push(AuditAction::QuestionUntagged(tag.clone()));
+                }
+            }
+            if !actions.is_empty() {
+                question.version += 1;
+                modified += 1;
+                for action in actions {
+                    match &action {
+                        AuditAction::QuestionTagged(tag) => self.index_tagged(uuid, tag),
+                        AuditAction::QuestionUntagged(tag) => self.index_untagged(uuid, tag),
+                        _ => ()
+                    }
+                    self.record_change(actor, uuid, action);
+                }
+            }
         }
+        Result::Ok(modified)
     }
 
-    fn get_question(&self, identifier: String) -> Result<Question, GetQuestionError> {
+    fn insert_question(&mut self, mut question: Question, actor: &Actor) -> Result<String, AddQuestionError> {
+        if question.identifier.is_nil() {
+            question.identifier = self.next_id();
+        }
+        self.check_question(&question).map_err(AddQuestionError::Rejected)?;
+        for (system, id) in &question.external_ids {
+            let existing = self.questions.values()
+                .find(|other| other.identifier != question.identifier && other.external_ids.get(system) == Some(id));
+            if let Some(existing) = existing {
+                return Result::Err(AddQuestionError::DuplicateExternalId {
+                    system: system.clone(),
+                    id: id.clone(),
+                    existing: existing.identifier.to_string()
+                });
+            }
+        }
+        let now = self.now();
+        match self.questions.entry(question.identifier) {
+            Entry::Occupied(_) => Result::Err(AddQuestionError::AlreadyExists),
+            Entry::Vacant(entry) => {
+                let uuid = question.identifier;
+
+                // Context given to `Question::new` is credited to whoever adds the question, now.
+                question.context.iter_mut()
+                    .filter(|item| item.added_by.is_none())
+                    .for_each(|item| {
+                        item.added_by = Some(actor.clone());
+                        item.added_at = now;
+                    });
+                let tags: Vec<String> = question.tags.iter().cloned().collect();
+                entry.insert(question);
+                tags.iter().for_each(|tag| self.index_tagged(uuid, tag));
+                self.record_change(actor, uuid, AuditAction::QuestionAdded);
+                Result::Ok(uuid.to_string())
+            }
+        }
+    }
+
+    pub fn get_question(&self, identifier: String) -> Result<Question, GetQuestionError> {
         match Uuid::from_str(&identifier) {
             Ok(uuid) => {
                 match self.questions.get(&uuid) {
@@ -142,39 +683,164 @@ impl Registry {
         }
     }
 
-    pub fn add_question_context(&self, identifier: String, new_contexts: HashSet<String>){
-        match self.get_question(identifier) {
-            Ok(mut question) => {
-                new_contexts.iter().for_each(|context| question.add_context(context.to_string()))
-            },
-            Err(_) => ()
+    /// Summaries of every question except those deferred and not yet due.
+    pub fn list_questions(&self) -> Vec<QuestionSummary> {
+        let now = self.now();
+        let today = now.date_naive();
+        self.questions.values()
+            .filter(|question| question.is_listed_by_default(today))
+            .map(|question| question.summary_at(now))
+            .collect()
+    }
+
+    fn get_question_mut(&mut self, identifier: String) -> Result<&mut Question, GetQuestionError> {
+        match Uuid::from_str(&identifier) {
+            Ok(uuid) => self.questions.get_mut(&uuid).ok_or(GetQuestionError::DoesNotExist),
+            _ => Result::Err(GetQuestionError::InvalidUUID)
         }
     }
 
-    pub fn add_question_option(&self, identifier: String, new_options: HashSet<String>){
-        match self.get_question(identifier) {
-            Ok(mut question) => {
-                new_options.iter().for_each(|context| question.add_option(context.to_string()))
-            },
-            Err(_) => ()
+    /// Looks up a question that is about to be modified, rejecting the change
+    /// if it is no longer at `expected_version`.
+    fn get_question_for_update(&mut self, identifier: String, expected_version: u64) -> Result<&mut Question, UpdateQuestionError> {
+        let question = self.get_question_mut(identifier).map_err(UpdateQuestionError::Question)?;
+        if question.version != expected_version {
+            return Result::Err(UpdateQuestionError::Conflict(Box::new(question.clone())));
         }
+        Result::Ok(question)
     }
 
-    pub fn set_question_decision(&self, identifier: String, decision: Decision){
-        match self.get_question(identifier) {
-            Ok(mut question) => {
-                question.set_decision(decision);
-            },
-            Err(_) => ()
+    /// Replaces the question's text and returns its new version. The
+    /// previous text is kept in the audit trail.
+    pub fn edit_question_content(&mut self, identifier: String, content: String, expected_version: u64, actor: &Actor) -> Result<u64, UpdateQuestionError> {
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        let previous = std::mem::replace(&mut question.content, content);
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::ContentEdited(previous));
+        Result::Ok(version)
+    }
+
+    /// Returns the question's new version. Context it already has is skipped,
+    /// and if nothing is left the question is unchanged.
+    pub fn add_question_context(&mut self, identifier: String, new_contexts: HashSet<String>, expected_version: u64, actor: &Actor) -> Result<u64, UpdateQuestionError> {
+        let now = self.now();
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        let mut new_contexts: Vec<String> = new_contexts.into_iter().collect();
+        new_contexts.sort();
+        new_contexts.retain(|context| {
+            question.add_context_item(ContextItem { added_at: now, ..ContextItem::new(context.clone(), None, Some(actor.clone())) })
+        });
+        if new_contexts.is_empty() {
+            return Result::Ok(question.version);
+        }
+        question.version += 1;
+        let version = question.version;
+        for context in new_contexts {
+            self.record_change(actor, uuid, AuditAction::ContextAdded(context));
+        }
+        Result::Ok(version)
+    }
+
+    /// Returns the question's new version. Options it already has are
+    /// skipped, and if nothing is left the question is unchanged.
+    pub fn add_question_option(&mut self, identifier: String, new_options: HashSet<String>, expected_version: u64, actor: &Actor) -> Result<u64, UpdateQuestionError> {
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        let mut new_options: Vec<String> = new_options.into_iter().collect();
+        new_options.sort();
+        new_options.retain(|option| !question.has_option(option));
+        if new_options.is_empty() {
+            return Result::Ok(question.version);
+        }
+        new_options.iter().for_each(|option| question.add_option(option.clone()));
+        question.version += 1;
+        let version = question.version;
+        for option in new_options {
+            self.record_change(actor, uuid, AuditAction::OptionAdded(option));
         }
+        Result::Ok(version)
+    }
+
+    /// Rearranges the question's options into `order`, which must name every
+    /// option exactly once, and returns the question's new version.
+    pub fn reorder_options(&mut self, identifier: String, order: Vec<String>, expected_version: u64, actor: &Actor) -> Result<u64, ReorderOptionsError> {
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let mut current = question.options.clone();
+        let mut requested = order.clone();
+        current.sort();
+        requested.sort();
+        if current != requested {
+            return Result::Err(ReorderOptionsError::NotAPermutation);
+        }
+        let uuid = question.identifier;
+        question.options = order;
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::OptionsReordered);
+        Result::Ok(version)
+    }
+
+    /// Moves one option to `position` (clamped to the end), keeping the
+    /// others in order, and returns the question's new version.
+    pub fn move_option(&mut self, identifier: String, option: &str, position: usize, expected_version: u64, actor: &Actor) -> Result<u64, ReorderOptionsError> {
+        let question = self.get_question_for_update(identifier.clone(), expected_version)?;
+        let mut order = question.options.clone();
+        let index = order.iter().position(|existing| existing == option)
+            .ok_or_else(|| ReorderOptionsError::UnknownOption(option.to_string()))?;
+        let moved = order.remove(index);
+        order.insert(position.min(order.len()), moved);
+        self.reorder_options(identifier, order, expected_version, actor)
+    }
+
+    /// Records `decision` on the question and returns its new version. The
+    /// acting user is always counted among the decision makers.
+    pub fn set_question_decision(&mut self, identifier: String, mut decision: Decision, expected_version: u64, actor: &Actor) -> Result<u64, SetQuestionDecisionError> {
+        decision.decision_makers.insert(actor.clone());
+        let current = self.get_question_for_update(identifier, expected_version)?.identifier;
+        self.check_decision(&self.questions[&current], &decision).map_err(SetQuestionDecisionError::Rejected)?;
+        let question = self.questions.get_mut(&current).unwrap();
+        let uuid = question.identifier;
+        let choice = decision.choice.clone();
+        question.set_decision(decision).map_err(SetQuestionDecisionError::Decision)?;
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::DecisionSet(choice));
+        Result::Ok(version)
     }
 
-    fn serialize_cbor(&self, path: &str){
-        serde_cbor::to_writer(File::create(path).unwrap(), self);
+    /// Sets the estimated distribution of `metric` for one of the question's
+    /// options and returns the question's new version.
+    pub fn set_option_outcome(&mut self, identifier: String, option: &str, metric: &str, distribution: Distribution, expected_version: u64, actor: &Actor) -> Result<u64, SetOptionOutcomeError> {
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        question.set_outcome(option, metric, distribution).map_err(SetOptionOutcomeError::Outcome)?;
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::OutcomeSet(option.to_string()));
+        Result::Ok(version)
+    }
+
+    #[cfg(feature = "cbor")]
+    pub fn serialize_cbor(&self, path: &str) -> serde_cbor::Result<()> {
+        serde_cbor::to_writer(std::fs::File::create(path)?, &Versioned::new(self))
     }
 
     pub fn serialize_json(&self) -> String{
-        serde_json::to_string(self).unwrap()
+        serde_json::to_string(&Versioned::new(self)).unwrap()
+    }
+
+    /// Loads a registry written by `serialize_cbor`, upgrading older formats.
+    #[cfg(feature = "cbor")]
+    pub fn deserialize_cbor(path: &str) -> Result<Registry, LoadError> {
+        migrations::from_cbor(&std::fs::read(path)?)
+    }
+
+    /// Loads a registry written by `serialize_json`, upgrading older formats.
+    pub fn deserialize_json(json: &str) -> Result<Registry, LoadError> {
+        migrations::from_json(json)
     }
 
 }
@@ -188,9 +854,13 @@ mod tests {
     const TAG_C : &str = "AriesThing";
     const TAG_D : &str = "AdasEndeavor";
 
-    fn add_some_default_tags(registry: &mut Registry) -> () {
+    fn luke() -> Actor {
+        Actor::new("luke")
+    }
+
+    fn add_some_default_tags(registry: &mut Registry) {
         [TAG_A, TAG_B, TAG_C, TAG_D].iter().for_each(|x| {
-            registry.add_tag(&String::from(*x));
+            registry.add_tag(&String::from(*x), &luke()).unwrap();
         });
     }
 
@@ -198,7 +868,7 @@ mod tests {
     fn test_add_tag() {
         let mut registry = Registry::new();
         let tag_value = "Something".to_string();
-        registry.add_tag(&tag_value);
+        registry.add_tag(&tag_value, &luke()).unwrap();
         assert!(registry.get_tags().contains(&tag_value));
     }
 
@@ -206,9 +876,9 @@ mod tests {
     fn test_add_existing_tag_fails() {
         let mut registry = Registry::new();
         let tag_value = "Something".to_string();
-        registry.add_tag(&tag_value);
+        registry.add_tag(&tag_value, &luke()).unwrap();
 
-        assert!(registry.add_tag(&tag_value).is_err(), "This should have failed due to tag already existing")
+        assert!(registry.add_tag(&tag_value, &luke()).is_err(), "This should have failed due to tag already existing")
     }
 
     #[test]
@@ -221,10 +891,27 @@ mod tests {
                                      question_tags,
                                      HashSet::new(),
                                      HashSet::new());
-        let identifier = registry.add_question(question)?;
+        let identifier = registry.add_question(question, &luke())?;
+        assert!(registry.get_question(identifier).is_ok());
         Result::Ok(())
     }
 
+    #[test]
+    fn test_list_questions_returns_summaries(){
+        let mut registry = Registry::new();
+        add_some_default_tags(&mut registry);
+        let mut context : HashSet<String> = HashSet::new();
+        context.insert("A long discussion".to_string());
+        let question = Question::new("Listed?".to_string(), HashSet::new(), context, HashSet::new());
+        let identifier = registry.add_question(question, &luke()).unwrap();
+
+        let summaries = registry.list_questions();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].identifier, identifier);
+        assert_eq!(summaries[0].content, "Listed?");
+        assert!(!summaries[0].decided);
+    }
+
     #[test]
     fn test_question_with_nonexistent_tag_wont_work(){
         let fake_project_name = String::from("ThisIsn'tOneOfMyProjects!");
@@ -236,7 +923,7 @@ mod tests {
                                      question_tags,
                                      HashSet::new(),
                                      HashSet::new());
-        match registry.add_question(question) {
+        match registry.add_question(question, &luke()) {
             Ok(_) => panic!("This should have never worked!"),
             Err(AddQuestionError::UsesNonExistentTags(tags)) => assert!(tags.contains(&fake_project_name)),
             _ => panic!("Got an add question error we did not expect")
@@ -253,7 +940,19 @@ mod tests {
                                      question_tags,
                                      HashSet::new(),
                                     HashSet::new());
-        let identifier = registry.add_question(question);
+        let identifier = registry.add_question(question, &luke()).unwrap();
+        let mut new_contexts : HashSet<String> = HashSet::new();
+        new_contexts.insert("Luke tends to undercount".to_string());
+        registry.add_question_context(identifier.clone(), new_contexts.clone(), 0, &luke()).unwrap();
+        assert!(registry.get_question(identifier.clone()).unwrap().get_context().contains("Luke tends to undercount"));
+
+        // Adding what is already there changes nothing.
+        assert_eq!(registry.add_question_context(identifier.clone(), new_contexts, 1, &luke()).unwrap(), 1);
+        assert_eq!(registry.add_question_context_item(identifier.clone(), "Luke tends to undercount".to_string(), None, 1, &luke()).unwrap(), 1);
+        let options: HashSet<String> = ["Ten".to_string()].iter().cloned().collect();
+        registry.add_question_option(identifier.clone(), options.clone(), 1, &luke()).unwrap();
+        assert_eq!(registry.add_question_option(identifier.clone(), options, 2, &luke()).unwrap(), 2);
+        assert_eq!(registry.get_question(identifier).unwrap().history().len(), 3);
     }
 
     #[test]
@@ -266,11 +965,14 @@ mod tests {
                                      question_tags,
                                      HashSet::new(),
                                      HashSet::new());
-        registry.add_question(question);
-        assert_eq!(registry.serialize_json(), "");
+        registry.add_question(question, &luke()).unwrap();
+        let json = registry.serialize_json();
+        assert!(json.contains("How many tests will luke end up writing?"));
+        assert!(json.contains(TAG_A));
     }
 
     #[test]
+    #[cfg(feature = "cbor")]
     fn test_cbor_serialization(){
         let mut registry = Registry::new();
         add_some_default_tags(&mut registry);
@@ -280,8 +982,194 @@ mod tests {
                                      question_tags,
                                      HashSet::new(),
                                      HashSet::new());
-        registry.add_question(question);
-        registry.serialize_cbor("something.txt")
+        registry.add_question(question, &luke()).unwrap();
+        let path = std::env::temp_dir().join("decis_test_cbor_serialization.cbor");
+        registry.serialize_cbor(path.to_str().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_mutations_are_attributed_to_actor(){
+        let mut registry = Registry::new();
+        let ada = Actor::new("ada");
+        registry.add_tag(&TAG_D.to_string(), &ada).unwrap();
+        let question = Question::new("Which compiler should we target?".to_string(),
+                                     HashSet::new(),
+                                     HashSet::new(),
+                                     HashSet::new());
+        let identifier = registry.add_question(question, &luke()).unwrap();
+        let mut options : HashSet<String> = HashSet::new();
+        options.insert("rustc".to_string());
+        registry.add_question_option(identifier.clone(), options, 0, &ada).unwrap();
+
+        let actions : Vec<&AuditAction> = registry.audit_log().entries_by(&ada).map(|entry| &entry.action).collect();
+        assert_eq!(actions, vec![&AuditAction::TagAdded(TAG_D.to_string()), &AuditAction::OptionAdded("rustc".to_string())]);
+        assert_eq!(registry.audit_log().entries().len(), 3);
+    }
+
+    #[test]
+    fn test_decision_includes_acting_decision_maker(){
+        let mut registry = Registry::new();
+        let question = Question::new("Which compiler should we target?".to_string(),
+                                     HashSet::new(),
+                                     HashSet::new(),
+                                     HashSet::new());
+        let identifier = registry.add_question(question, &luke()).unwrap();
+        let mut decision_makers : HashSet<Actor> = HashSet::new();
+        decision_makers.insert(Actor::new("ada"));
+        let decision = Decision::new("rustc".to_string(), "It's the only one we know".to_string(), decision_makers);
+        registry.set_question_decision(identifier.clone(), decision, 0, &luke()).unwrap();
+
+        let decision = registry.get_question(identifier).unwrap().get_decision().unwrap();
+        assert!(decision.decision_makers.contains(&luke()));
+        assert!(decision.decision_makers.contains(&Actor::new("ada")));
+    }
+
+    #[test]
+    fn test_bulk_add_reports_per_question_outcomes(){
+        let mut registry = Registry::new();
+        add_some_default_tags(&mut registry);
+        let mut known_tags : HashSet<String> = HashSet::new();
+        known_tags.insert(TAG_A.to_string());
+        let mut unknown_tags : HashSet<String> = HashSet::new();
+        unknown_tags.insert("NotAProject".to_string());
+        let questions = vec![
+            Question::new("First".to_string(), known_tags.clone(), HashSet::new(), HashSet::new()),
+            Question::new("Second".to_string(), unknown_tags, HashSet::new(), HashSet::new()),
+            Question::new("Third".to_string(), known_tags, HashSet::new(), HashSet::new()),
+        ];
+
+        registry.set_id_generator(ids::SeededIds::new(7));
+        let dry_run = registry.validate_questions(questions.clone(), &luke());
+        assert_eq!(dry_run.inserted().len(), 2);
+        assert!(registry.list_questions().is_empty());
+        // The dry run didn't use up the registry's identifiers.
+        let first = ids::IdGenerator::next_id(&ids::SeededIds::new(7)).to_string();
+
+        let result = registry.add_questions(questions, &luke());
+        assert!(!result.all_inserted());
+        assert_eq!(result.inserted().len(), 2);
+        match result.failures().as_slice() {
+            [(1, AddQuestionError::UsesNonExistentTags(tags))] => assert_eq!(tags, &vec!["NotAProject".to_string()]),
+            _ => panic!("Only the second question should have been rejected")
+        }
+        assert_eq!(result.inserted()[0], &first);
+        for identifier in result.inserted() {
+            assert!(registry.get_question(identifier.clone()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_bulk_tag_matching_questions(){
+        let mut registry = Registry::new();
+        add_some_default_tags(&mut registry);
+        let mut a_tags : HashSet<String> = HashSet::new();
+        a_tags.insert(TAG_A.to_string());
+        registry.add_question(Question::new("First".to_string(), a_tags.clone(), HashSet::new(), HashSet::new()), &luke()).unwrap();
+        registry.add_question(Question::new("Second".to_string(), a_tags, HashSet::new(), HashSet::new()), &luke()).unwrap();
+        let untouched = registry.add_question(Question::new("Third".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke()).unwrap();
+
+        let has_tag_a = |question: &Question| question.tags.contains(TAG_A);
+        let modified = registry.bulk_tag(QuestionSelector::Matching(&has_tag_a),
+                                         &[TAG_B.to_string()],
+                                         &[TAG_A.to_string()],
+                                         &luke()).unwrap();
+        assert_eq!(modified, 2);
+        assert!(registry.get_question(untouched).unwrap().get_tags().is_empty());
+        assert_eq!(registry.questions.values().filter(|question| question.tags.contains(TAG_B)).count(), 2);
+        assert!(registry.questions.values().all(|question| !question.tags.contains(TAG_A)));
+    }
+
+    #[test]
+    fn test_bulk_tag_rejects_unknown_ids_without_changes(){
+        let mut registry = Registry::new();
+        add_some_default_tags(&mut registry);
+        let identifier = registry.add_question(Question::new("First".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke()).unwrap();
+        let missing = Uuid::new_v4().to_string();
+
+        match registry.bulk_tag(QuestionSelector::Ids(vec![identifier.clone(), missing.clone()]), &[TAG_C.to_string()], &[], &luke()) {
+            Err(BulkTagError::Question(id, GetQuestionError::DoesNotExist)) => assert_eq!(id, missing),
+            _ => panic!("An unknown identifier should fail the whole operation")
+        }
+        assert!(registry.get_question(identifier).unwrap().get_tags().is_empty());
+    }
+
+    #[test]
+    fn test_failed_transaction_rolls_back_everything(){
+        let mut registry = Registry::new();
+        let result : Result<(), String> = registry.transaction(|tx| {
+            tx.add_tag(&TAG_A.to_string(), &luke()).map_err(|error| format!("{:?}", error))?;
+            let mut question_tags : HashSet<String> = HashSet::new();
+            question_tags.insert(TAG_A.to_string());
+            let valid = Question::new("Kept?".to_string(), question_tags, HashSet::new(), HashSet::new());
+            tx.add_question(valid, &luke()).map_err(|error| format!("{:?}", error))?;
+            let mut bad_tags : HashSet<String> = HashSet::new();
+            bad_tags.insert(TAG_B.to_string());
+            let invalid = Question::new("Rejected".to_string(), bad_tags, HashSet::new(), HashSet::new());
+            tx.add_question(invalid, &luke()).map_err(|error| format!("{:?}", error))?;
+            Result::Ok(())
+        });
+
+        assert!(result.is_err());
+        assert!(registry.get_tags().is_empty());
+        assert!(!registry.serialize_json().contains("Kept?"));
+        assert!(registry.audit_log().entries().is_empty());
+    }
+
+    #[test]
+    fn test_successful_transaction_commits(){
+        let mut registry = Registry::new();
+        let result : Result<String, AddQuestionError> = registry.transaction(|tx| {
+            add_some_default_tags(tx);
+            let mut question_tags : HashSet<String> = HashSet::new();
+            question_tags.insert(TAG_C.to_string());
+            tx.add_question(Question::new("Kept?".to_string(), question_tags, HashSet::new(), HashSet::new()), &luke())
+        });
+
+        assert!(registry.get_question(result.unwrap()).is_ok());
+        assert_eq!(registry.get_tags().len(), 4);
+    }
+
+    #[test]
+    fn test_options_keep_and_change_their_order(){
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(Question::new("Which editor?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke()).unwrap();
+        for (version, option) in ["Vim", "Emacs", "Helix"].iter().enumerate() {
+            let mut options: HashSet<String> = HashSet::new();
+            options.insert(option.to_string());
+            registry.add_question_option(identifier.clone(), options, version as u64, &luke()).unwrap();
+        }
+        assert_eq!(registry.get_question(identifier.clone()).unwrap().ordered_options(), ["Vim", "Emacs", "Helix"]);
+
+        registry.move_option(identifier.clone(), "Helix", 0, 3, &luke()).unwrap();
+        assert_eq!(registry.get_question(identifier.clone()).unwrap().ordered_options(), ["Helix", "Vim", "Emacs"]);
+        match registry.reorder_options(identifier, vec!["Vim".to_string()], 4, &luke()) {
+            Err(ReorderOptionsError::NotAPermutation) => (),
+            _ => panic!("A reorder that drops options should be rejected")
+        }
+    }
+
+    #[test]
+    fn test_stale_version_is_rejected_with_current_state(){
+        let mut registry = Registry::new();
+        let question = Question::new("Tabs or spaces?".to_string(),
+                                     HashSet::new(),
+                                     HashSet::new(),
+                                     HashSet::new());
+        let identifier = registry.add_question(question, &luke()).unwrap();
+        let mut options : HashSet<String> = HashSet::new();
+        options.insert("Tabs".to_string());
+        assert_eq!(registry.add_question_option(identifier.clone(), options.clone(), 0, &luke()).unwrap(), 1);
+
+        let mut other_options : HashSet<String> = HashSet::new();
+        other_options.insert("Spaces".to_string());
+        match registry.add_question_option(identifier.clone(), other_options, 0, &Actor::new("ada")) {
+            Err(UpdateQuestionError::Conflict(current)) => {
+                assert_eq!(current.version(), 1);
+                assert!(current.get_options().contains("Tabs"));
+            },
+            _ => panic!("Editing from a stale version should conflict")
+        }
+        assert!(!registry.get_question(identifier).unwrap().get_options().contains("Spaces"));
     }
 
 }
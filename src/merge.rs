@@ -0,0 +1,476 @@
+//! Merging copies of a question edited on different replicas, without a
+//! server to order the edits.
+//!
+//! Every change a question goes through is kept in its history, and the
+//! history is what makes the merge work. The merged history is the union of
+//! both. Each field then takes the value from the side whose last change to
+//! it the other side has already seen. When neither has seen the other's
+//! change, the edits are concurrent:
+//!
+//! - Content, owner, priority, status and option order are last-writer-wins
+//!   registers. The later change wins, with the actor's name breaking ties.
+//! - Tags form a set that is decided per tag in the same way. A tag nobody
+//!   changed is kept if either side has it.
+//! - Options, context, comments and attachments are grow-only sets, merged
+//!   by union. A context retraction on either side sticks.
+//! - Outcomes, branches, estimates and scores are maps, merged key by key.
+//!   A key is taken from the side whose last change to that key wins, so
+//!   edits to different options, metrics, criteria or evaluators all stay.
+//!   Arguments are grow-only sets per option.
+//! - Concurrent edits to one comment keep the most edited copy, with the
+//!   latest revision and then the body breaking ties.
+//! - Concurrent decisions that differ are never settled silently. The merged
+//!   question is left undecided, and the two decisions come back as a
+//!   `DecisionConflict` for someone to settle by deciding again.
+//!
+//! Merging is symmetric and idempotent, so replicas converge whichever way
+//! round they merge. The one exception is a `DecisionConflict`: its `local`
+//! and `remote` sides depend on which replica merged.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use crate::{Actor, Decision, Question, Registry};
+use crate::arguments::{Argument, ProsCons};
+use crate::audit::AuditAction;
+use crate::history::Change;
+
+#[derive(Clone, Debug)]
+pub struct DecisionConflict {
+    pub identifier: String,
+    pub local: Decision,
+    pub remote: Decision
+}
+
+#[derive(Clone, Debug)]
+pub struct Merged {
+    pub question: Question,
+    pub conflict: Option<DecisionConflict>
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MergeReport {
+    /// Questions only the other registry had.
+    pub added: usize,
+    /// Questions both registries had, in different states.
+    pub merged: usize,
+    pub conflicts: Vec<DecisionConflict>
+}
+
+/// Whose value a field takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Local,
+    Remote
+}
+
+/// How one side's last change compares to the other's, for ordering
+/// concurrent changes.
+fn later(local: &Change, remote: &Change) -> Ordering {
+    local.at.cmp(&remote.at)
+        .then_with(|| local.actor.id().cmp(remote.actor.id()))
+        .then_with(|| format!("{:?}", local.action).cmp(&format!("{:?}", remote.action)))
+}
+
+/// Picks the changes made through `relevant` actions.
+fn acting<F: Fn(&AuditAction) -> bool>(relevant: F) -> impl Fn(&Change) -> bool {
+    move |change| relevant(&change.action)
+}
+
+/// `local` and `remote` merged key by key. Keys with the same value on both
+/// sides are kept; for the rest, `resolve` gets both values and returns the
+/// merged one, or `None` to leave the key out.
+fn merge_keys<K: Clone + Ord, V: Clone + PartialEq, F: FnMut(&K, Option<&V>, Option<&V>) -> Option<V>>(
+    local: &BTreeMap<K, V>, remote: &BTreeMap<K, V>, mut resolve: F
+) -> BTreeMap<K, V> {
+    let mut merged = BTreeMap::new();
+    for key in local.keys().chain(remote.keys()) {
+        if merged.contains_key(key) {
+            continue;
+        }
+        let value = match (local.get(key), remote.get(key)) {
+            (Some(local), Some(remote)) if local == remote => Some(local.clone()),
+            (local, remote) => resolve(key, local, remote)
+        };
+        if let Some(value) = value {
+            merged.insert(key.clone(), value);
+        }
+    }
+    merged
+}
+
+/// Both sides' arguments, each once, oldest first.
+fn union_arguments(local: &[Argument], remote: &[Argument]) -> Vec<Argument> {
+    let mut arguments = local.to_vec();
+    arguments.extend(remote.iter().filter(|argument| !local.contains(argument)).cloned());
+    arguments.sort_by(|a, b| a.added_at.cmp(&b.added_at)
+        .then_with(|| a.author.id().cmp(b.author.id()))
+        .then_with(|| a.text.cmp(&b.text))
+        .then_with(|| a.weight.cmp(&b.weight)));
+    arguments
+}
+
+impl Question {
+    fn last_change<F: Fn(&Change) -> bool>(&self, relevant: &F) -> Option<&Change> {
+        self.history.iter().rev().find(|change| relevant(change))
+    }
+
+    /// Which side's value to keep for the field `relevant` picks the changes
+    /// of, or `None` if the last changes were concurrent.
+    fn causal_winner<F: Fn(&Change) -> bool>(&self, other: &Question, relevant: F) -> Option<Side> {
+        match (self.last_change(&relevant), other.last_change(&relevant)) {
+            (None, None) => Some(Side::Local),
+            (Some(_), None) => Some(Side::Local),
+            (None, Some(_)) => Some(Side::Remote),
+            (Some(local), Some(remote)) if local == remote => Some(Side::Local),
+            (Some(local), Some(remote)) => {
+                if other.history.contains(local) {
+                    Some(Side::Remote)
+                } else if self.history.contains(remote) {
+                    Some(Side::Local)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Like `causal_winner`, with concurrent changes going to the later one.
+    fn winner<F: Fn(&Change) -> bool>(&self, other: &Question, relevant: F) -> Side {
+        match self.causal_winner(other, &relevant) {
+            Some(side) => side,
+            None => {
+                let local = self.last_change(&relevant).expect("concurrent changes exist on both sides");
+                let remote = other.last_change(&relevant).expect("concurrent changes exist on both sides");
+                if later(local, remote).is_ge() { Side::Local } else { Side::Remote }
+            }
+        }
+    }
+
+    /// The value of the side whose last `relevant` change wins, or whichever
+    /// value there is when neither side has such a change.
+    fn pick_value<V: Clone, F: Fn(&Change) -> bool>(&self, other: &Question, local: Option<&V>, remote: Option<&V>, relevant: F) -> Option<V> {
+        if self.last_change(&relevant).is_none() && other.last_change(&relevant).is_none() {
+            return local.or(remote).cloned();
+        }
+        match self.winner(other, relevant) {
+            Side::Local => local.cloned(),
+            Side::Remote => remote.cloned()
+        }
+    }
+
+    /// Like `pick_value`, but a value only one side has is always kept:
+    /// these maps are never removed from.
+    fn keep_value<V: Clone, F: Fn(&Change) -> bool>(&self, other: &Question, local: Option<&V>, remote: Option<&V>, relevant: F) -> Option<V> {
+        match (local, remote) {
+            (Some(_), Some(_)) => self.pick_value(other, local, remote, relevant),
+            _ => local.or(remote).cloned()
+        }
+    }
+
+    /// The two copies merged, plus the decisions to settle if both sides
+    /// decided differently.
+    pub fn merge(&self, other: &Question) -> Merged {
+        let pick = |side: Side| if side == Side::Local { self } else { other };
+        let order = |side: Side| if side == Side::Local { (self, other) } else { (other, self) };
+        let mut merged = self.clone();
+
+        let mut history: Vec<Change> = self.history.clone();
+        history.extend(other.history.iter().filter(|change| !self.history.contains(change)).cloned());
+        // Fully ordered, so both sides end up with the same history.
+        history.sort_by(later);
+        merged.history = history;
+
+        merged.content = pick(self.winner(other, acting(|action| matches!(action, AuditAction::ContentEdited(_))))).content.clone();
+        merged.owner = pick(self.winner(other, acting(|action| matches!(action, AuditAction::OwnerChanged(_))))).owner.clone();
+        merged.priority = pick(self.winner(other, acting(|action| matches!(action, AuditAction::PrioritySet(_))))).priority;
+
+        merged.tags = self.tags.union(&other.tags)
+            .filter(|tag| {
+                let relevant = |action: &AuditAction| matches!(action, AuditAction::QuestionTagged(name) | AuditAction::QuestionUntagged(name) if name == *tag);
+                match (self.last_change(&acting(relevant)), other.last_change(&acting(relevant))) {
+                    (None, None) => true,
+                    _ => pick(self.winner(other, acting(relevant))).tags.contains(*tag)
+                }
+            })
+            .cloned()
+            .collect();
+
+        let (first, second) = order(self.winner(other, acting(|action| matches!(action, AuditAction::OptionsReordered))));
+        merged.options = first.options.clone();
+        merged.options.extend(second.options.iter().filter(|option| !first.options.contains(option)).cloned());
+
+        for item in &other.context {
+            match merged.context.iter_mut().find(|existing| existing.text == item.text && existing.added_at == item.added_at) {
+                Some(existing) => {
+                    // The earlier of two retractions, so both sides keep the same one.
+                    let earlier = match (&existing.retraction, &item.retraction) {
+                        (Some(local), Some(remote)) => (remote.at, remote.by.id(), &remote.note) < (local.at, local.by.id(), &local.note),
+                        (None, Some(_)) => true,
+                        _ => false
+                    };
+                    if earlier {
+                        existing.retraction = item.retraction.clone();
+                    }
+                }
+                None => merged.context.push(item.clone())
+            }
+        }
+        merged.context.sort_by(|a, b| a.added_at.cmp(&b.added_at).then_with(|| a.text.cmp(&b.text)));
+        for comment in &other.comments {
+            match merged.comments.iter_mut().find(|existing| existing.identifier == comment.identifier) {
+                Some(existing) => {
+                    let edits = |comment: &crate::comments::Comment| {
+                        (comment.history.len(), comment.history.last().map(|revision| revision.replaced_at), comment.body.clone())
+                    };
+                    if edits(comment) > edits(existing) {
+                        *existing = comment.clone();
+                    }
+                }
+                None => merged.comments.push(comment.clone())
+            }
+        }
+        merged.comments.sort_by_key(|comment| (comment.posted_at, comment.identifier));
+        merged.attachments.extend(other.attachments.iter().filter(|attachment| !self.attachments.contains(attachment)).cloned());
+        merged.attachments.sort_by(|a, b| a.added_at.cmp(&b.added_at)
+            .then_with(|| a.added_by.id().cmp(b.added_by.id()))
+            .then_with(|| format!("{:?}", a.reference).cmp(&format!("{:?}", b.reference))));
+
+        merged.outcomes = merge_keys(&self.outcomes, &other.outcomes, |option, local, remote| match (local, remote) {
+            (Some(local), Some(remote)) => Some(merge_keys(local, remote, |_, local, remote| {
+                self.keep_value(other, local, remote, acting(|action| matches!(action, AuditAction::OutcomeSet(changed) if changed == option)))
+            })),
+            _ => local.or(remote).cloned()
+        });
+        merged.branches = merge_keys(&self.branches, &other.branches, |option, local, remote| {
+            self.pick_value(other, local, remote, acting(|action| matches!(action, AuditAction::BranchesSet(changed) if changed == option)))
+        });
+        merged.estimates = merge_keys(&self.estimates, &other.estimates, |option, local, remote| {
+            self.keep_value(other, local, remote, acting(|action| matches!(action, AuditAction::EstimateSet(changed) if changed == option)))
+        });
+        merged.scores = merge_keys(&self.scores, &other.scores, |option, local, remote| match (local, remote) {
+            (Some(local), Some(remote)) => Some(merge_keys(local, remote, |criterion, local, remote| match (local, remote) {
+                (Some(local), Some(remote)) => Some(merge_keys(local, remote, |evaluator, local, remote| {
+                    // Older histories only recorded the option scored.
+                    self.keep_value(other, local, remote, |change: &Change| &change.actor == evaluator && match &change.action {
+                        AuditAction::CriterionScored { option: scored, criterion: on } => scored == option && on == criterion,
+                        AuditAction::OptionScored(scored) => scored == option,
+                        _ => false
+                    })
+                })),
+                _ => local.or(remote).cloned()
+            })),
+            _ => local.or(remote).cloned()
+        });
+        merged.arguments = merge_keys(&self.arguments, &other.arguments, |_, local, remote| match (local, remote) {
+            (Some(local), Some(remote)) => Some(ProsCons {
+                pros: union_arguments(&local.pros, &remote.pros),
+                cons: union_arguments(&local.cons, &remote.cons)
+            }),
+            _ => local.or(remote).cloned()
+        });
+
+        let deciding = |action: &AuditAction| matches!(action, AuditAction::DecisionSet(_) | AuditAction::QuestionReopened(_) | AuditAction::StatusChanged(_));
+        let mut conflict = None;
+        let deciding = acting(deciding);
+        let side = match self.causal_winner(other, &deciding) {
+            Some(side) => side,
+            None => match (&self.decision, &other.decision) {
+                (Some(local), Some(remote)) if local.choice != remote.choice || local.rationale != remote.rationale => {
+                    conflict = Some(DecisionConflict { identifier: self.identifier.to_string(), local: local.clone(), remote: remote.clone() });
+                    self.winner(other, &deciding)
+                }
+                _ => self.winner(other, &deciding)
+            }
+        };
+        let decided = pick(side);
+        merged.decision = if conflict.is_some() { None } else { decided.decision.clone() };
+        merged.decision_history = decided.decision_history.clone();
+        merged.status = decided.status;
+        merged.deferral = decided.deferral.clone();
+        merged.cancellation = decided.cancellation.clone();
+
+        merged.external_ids = merge_keys(&self.external_ids, &other.external_ids, |system, local, remote| {
+            self.keep_value(other, local, remote, acting(|action| matches!(action, AuditAction::ExternalIdSet(changed) if changed == system)))
+        });
+        // Fields from newer versions have no history here, so the greater value wins.
+        merged.unknown_fields = merge_keys(&self.unknown_fields, &other.unknown_fields, |_, local, remote| {
+            local.into_iter().chain(remote).max_by_key(|value| value.to_string()).cloned()
+        });
+        merged.version = if self.version == other.version && self.history == other.history {
+            self.version
+        } else {
+            self.version.max(other.version) + 1
+        };
+        Merged { question: merged, conflict }
+    }
+}
+
+impl Registry {
+    /// Merges `other` into this registry: its tags and the questions only it
+    /// has are added, and questions both have are merged with
+    /// `Question::merge`. Merging the other way round as well leaves both
+    /// registries with the same questions.
+    pub fn merge(&mut self, other: &Registry, actor: &Actor) -> MergeReport {
+        let mut report = MergeReport::default();
+        let mut tags: Vec<&String> = other.tags.iter().collect();
+        tags.sort();
+        for tag in tags {
+            self.adopt_tag(tag, actor);
+        }
+        let mut questions: Vec<&Question> = other.questions.values().collect();
+        questions.sort_by_key(|question| question.identifier);
+        for question in questions {
+            let merged = match self.questions.get(&question.identifier) {
+                None => {
+                    report.added += 1;
+                    question.clone()
+                }
+                Some(local) if local.version == question.version && local.history == question.history => continue,
+                Some(local) => {
+                    let merged = local.merge(question);
+                    report.merged += 1;
+                    report.conflicts.extend(merged.conflict);
+                    merged.question
+                }
+            };
+            self.adopt_question(merged, actor);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::clock::FixedClock;
+
+    fn replicas() -> (Registry, Registry, String, FixedClock) {
+        let luke = Actor::new("luke");
+        let clock = FixedClock::new(chrono::Utc::now());
+        let mut laptop = Registry::new();
+        laptop.set_clock(clock.clone());
+        laptop.add_tag(&"infra".to_string(), &luke).unwrap();
+        laptop.add_tag(&"backend".to_string(), &luke).unwrap();
+        let tags: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Kafka".to_string()].iter().cloned().collect();
+        let identifier = laptop.add_question(Question::new("Which queue?".to_string(), tags, HashSet::new(), options), &luke).unwrap();
+        let mut desktop = laptop.clone();
+        desktop.set_clock(clock.clone());
+        (laptop, desktop, identifier, clock)
+    }
+
+    #[test]
+    fn test_concurrent_edits_merge_the_same_both_ways() {
+        let (mut laptop, mut desktop, identifier, clock) = replicas();
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        clock.advance(chrono::Duration::minutes(1));
+        laptop.edit_question_content(identifier.clone(), "Which message queue?".to_string(), 0, &luke).unwrap();
+        laptop.add_question_option(identifier.clone(), ["SQS".to_string()].iter().cloned().collect(), 1, &luke).unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        desktop.edit_question_content(identifier.clone(), "Which job queue?".to_string(), 0, &ana).unwrap();
+        desktop.bulk_tag(QuestionSelector::Ids(vec![identifier.clone()]), &["backend".to_string()], &["infra".to_string()], &ana).unwrap();
+        desktop.add_question_option(identifier.clone(), ["NATS".to_string()].iter().cloned().collect(), 2, &ana).unwrap();
+
+        let local = laptop.question(&identifier).unwrap();
+        let remote = desktop.question(&identifier).unwrap();
+        let one_way = local.merge(remote).question;
+        let other_way = remote.merge(local).question;
+        assert_eq!(one_way.content, "Which job queue?");
+        assert_eq!(one_way.tags, ["backend".to_string()].iter().cloned().collect());
+        assert_eq!(one_way.options.len(), 3);
+        assert_eq!(one_way.history.len(), 7);
+        assert_eq!(serde_json::to_value(&one_way.history).unwrap(), serde_json::to_value(&other_way.history).unwrap());
+        assert_eq!((&other_way.content, &other_way.tags, other_way.version), (&one_way.content, &one_way.tags, one_way.version));
+        // Merging again changes nothing.
+        assert_eq!(one_way.merge(&other_way).question.content, one_way.content);
+
+        let report = laptop.merge(&desktop, &luke);
+        assert_eq!((report.added, report.merged), (0, 1));
+        assert!(report.conflicts.is_empty());
+        assert_eq!(laptop.list_questions_with_tag("backend").len(), 1);
+        assert!(laptop.list_questions_with_tag("infra").is_empty());
+    }
+
+    /// The question as JSON, with its tags in a fixed order.
+    fn normalized(question: &Question) -> serde_json::Value {
+        let mut value = serde_json::to_value(question).unwrap();
+        let mut tags: Vec<String> = question.tags.iter().cloned().collect();
+        tags.sort();
+        value["tags"] = serde_json::to_value(tags).unwrap();
+        value
+    }
+
+    #[test]
+    fn test_concurrent_edits_to_one_option_are_all_kept() {
+        let (mut laptop, mut desktop, identifier, clock) = replicas();
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        for registry in [&mut laptop, &mut desktop] {
+            for name in ["speed", "cost"] {
+                registry.add_criterion(criteria::Criterion::new(name, "", 0.0, 5.0, criteria::Direction::HigherIsBetter), &luke).unwrap();
+            }
+        }
+        clock.advance(chrono::Duration::minutes(1));
+        laptop.add_argument(identifier.clone(), "Kafka", arguments::Stance::Pro, "Replays".to_string(), 2, 0, &luke).unwrap();
+        laptop.score_option(identifier.clone(), "Kafka", "speed", 3.0, 1, &luke).unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        desktop.add_argument(identifier.clone(), "Kafka", arguments::Stance::Con, "Operations".to_string(), 1, 0, &ana).unwrap();
+        desktop.score_option(identifier.clone(), "Kafka", "speed", 4.0, 1, &ana).unwrap();
+        desktop.score_option(identifier.clone(), "Kafka", "cost", 2.0, 2, &ana).unwrap();
+
+        let local = laptop.question(&identifier).unwrap();
+        let remote = desktop.question(&identifier).unwrap();
+        let merged = local.merge(remote).question;
+        let pros_cons = &merged.arguments["Kafka"];
+        assert_eq!((pros_cons.pros.len(), pros_cons.cons.len()), (1, 1));
+        let scores = merged.get_scores("Kafka");
+        assert_eq!(scores["speed"][&luke], 3.0);
+        assert_eq!(scores["speed"][&ana], 4.0);
+        assert_eq!(scores["cost"][&ana], 2.0);
+        assert_eq!(normalized(&merged), normalized(&remote.merge(local).question));
+    }
+
+    #[test]
+    fn test_concurrent_comment_and_id_edits_converge() {
+        let (mut laptop, mut desktop, identifier, clock) = replicas();
+        let luke = Actor::new("luke");
+        let comment = laptop.add_comment(identifier.clone(), "Kafka, probably".to_string(), None, &luke).unwrap();
+        desktop.merge(&laptop, &luke);
+        clock.advance(chrono::Duration::minutes(1));
+        laptop.edit_comment(identifier.clone(), comment.clone(), "Kafka, for replays".to_string(), &luke).unwrap();
+        laptop.set_external_id(identifier.clone(), "notion", "page-1".to_string(), &luke).unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        desktop.edit_comment(identifier.clone(), comment, "Kafka, for throughput".to_string(), &luke).unwrap();
+        desktop.set_external_id(identifier.clone(), "notion", "page-2".to_string(), &luke).unwrap();
+
+        let local = laptop.question(&identifier).unwrap();
+        let remote = desktop.question(&identifier).unwrap();
+        let one_way = local.merge(remote).question;
+        assert_eq!(normalized(&one_way), normalized(&remote.merge(local).question));
+        assert_eq!(one_way.comments[0].body, "Kafka, for throughput");
+        assert_eq!(one_way.external_id("notion"), Some("page-2"));
+    }
+
+    #[test]
+    fn test_concurrent_decisions_are_surfaced() {
+        let (mut laptop, mut desktop, identifier, clock) = replicas();
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        clock.advance(chrono::Duration::minutes(1));
+        laptop.set_question_decision(identifier.clone(), Decision::new("Kafka".to_string(), "Throughput".to_string(), HashSet::new()), 0, &luke).unwrap();
+        desktop.set_question_decision(identifier.clone(), Decision::new("SQS".to_string(), "Managed".to_string(), HashSet::new()), 0, &ana).unwrap();
+
+        let report = laptop.merge(&desktop, &luke);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].remote.choice, "SQS");
+        assert!(laptop.question(&identifier).unwrap().decision.is_none());
+
+        // A decision made after seeing the other one is not a conflict.
+        let version = laptop.question(&identifier).unwrap().version;
+        laptop.set_question_decision(identifier.clone(), Decision::new("SQS".to_string(), "Managed, and ana agreed".to_string(), HashSet::new()), version, &luke).unwrap();
+        assert!(desktop.merge(&laptop, &ana).conflicts.is_empty());
+        assert_eq!(desktop.question(&identifier).unwrap().decision.as_ref().unwrap().rationale, "Managed, and ana agreed");
+    }
+}
@@ -0,0 +1,403 @@
+//! The text the library shows to people, and its translations.
+//!
+//! Every error's message, and the prose in generated notes and feeds, is
+//! looked up by key in a `Catalog`. Templates name their arguments in
+//! braces, such as `{option}`. The built-in `English` catalog has every key.
+//! `Translations` hold an embedding application's own templates, and any key
+//! they leave out falls back to English. `Catalogs` picks one by locale.
+//!
+//! Errors implement `Localized`, and their `Display` uses English. The
+//! registry's catalog, set with `Registry::set_catalog`, is the one its
+//! generated text uses.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::{AddQuestionError, AddTagErrors, BulkTagError, GetQuestionError, Question, Registry, ReorderOptionsError,
+    SetDecisionError, SetOptionOutcomeError, SetQuestionDecisionError, UpdateQuestionError};
+
+/// Every key the library uses, with its English template.
+const ENGLISH: &[(&str, &str)] = &[
+    ("error.io", "Reading or writing failed: {error}"),
+    ("error.encoding", "The data could not be encoded or decoded: {error}"),
+    ("question.invalid-uuid", "The question identifier is not a valid UUID."),
+    ("question.does-not-exist", "No question has that identifier."),
+    ("question.already-exists", "A question with that identifier already exists."),
+    ("question.uses-unknown-tags", "The question uses tags that don't exist: {tags}."),
+    ("question.rejected", "The question was rejected: {reasons}"),
+    ("question.duplicate-external-id", "Question {existing} is already {id} in {system}."),
+    ("question.conflict", "The question changed since it was read, and is now at version {version}."),
+    ("decision.already-exists", "The question already has a decision."),
+    ("decision.not-open", "Only open questions can be decided, and this one is {status}."),
+    ("decision.rejected", "The decision was rejected: {reasons}"),
+    ("option.unknown", "The question has no option \"{option}\"."),
+    ("options.not-a-permutation", "The new order must list every option exactly once."),
+    ("outcome.invalid-distribution", "The outcome's probabilities or values are invalid."),
+    ("simulate.summary", "Simulated {iterations} iterations (seed {seed})."),
+    ("simulate.metric", "{option} / {metric}: p10 {p10}, p50 {p50}, p90 {p90} (mean {mean})"),
+    ("tag.already-exists", "That tag already exists."),
+    ("tag.does-not-exist", "That tag doesn't exist."),
+    ("tag.unknown", "These tags don't exist: {tags}."),
+    ("tag.invalid", "The tag breaks the registry's tag rules: {violations}."),
+    ("tag.empty", "it is empty"),
+    ("tag.too-long", "it is {length} characters long, more than {max_length}"),
+    ("tag.disallowed-characters", "it contains {characters}"),
+    ("tag.pattern-mismatch", "it doesn't match the pattern \"{pattern}\""),
+    ("tag.reserved-prefix", "it starts with the reserved \"{prefix}\""),
+    ("tag.collisions", "These tags would become the same tag: {collisions}."),
+    ("bulk-tag.question", "Question {identifier}: {error}"),
+    ("argument.zero-weight", "An argument needs a weight above zero."),
+    ("comment.invalid-uuid", "The comment identifier is not a valid UUID."),
+    ("comment.unknown", "The comment isn't on this question."),
+    ("comment.not-author", "Only a comment's author may edit it."),
+    ("context.unknown", "The question has no such context item."),
+    ("context.already-retracted", "That context item is already retracted."),
+    ("context.outdated", "outdated"),
+    ("context.retracted", "retracted"),
+    ("criterion.already-exists", "A criterion with that name already exists."),
+    ("criterion.invalid-scale", "A criterion's minimum must be below its maximum, and both finite."),
+    ("criterion.unknown", "There is no criterion \"{criterion}\"."),
+    ("score.out-of-scale", "The score is outside the criterion's scale."),
+    ("estimate.invalid", "Estimates must be finite and not negative, and costs need a currency."),
+    ("status.invalid-transition", "A question can't go from {from} to {to}."),
+    ("status.use-set-decision", "A question becomes decided by setting its decision."),
+    ("status.use-defer-question", "Deferring a question needs a reason."),
+    ("status.use-cancel-question", "Cancelling a question needs a reason."),
+    ("status.use-reopen-question", "Reopening a question needs a reason."),
+    ("status.reason-required", "The reason can't be blank."),
+    ("status.open", "open"),
+    ("status.decided", "decided"),
+    ("status.deferred", "deferred"),
+    ("status.cancelled", "cancelled"),
+    ("pick.no-options", "There are no options to pick from."),
+    ("pick.invalid-weights", "The weights must be finite, not negative, name existing options, and not all be zero."),
+    ("pick.random", "Selected at random (seed {seed}) from {count} equivalent options."),
+    ("pick.weighted", "Selected by weighted draw (seed {seed}) with weights {weights}."),
+    ("branches.invalid-probabilities", "The probabilities must lie between 0 and 1 and add up to 1, and payoffs must be finite."),
+    ("branches.unknown-follow-up", "There is no follow-up question {identifier}."),
+    ("branches.cycle", "The follow-ups would lead back to the question itself."),
+    ("page.invalid-cursor", "The cursor is malformed, or was issued for a different sort."),
+    ("page.zero-limit", "A page must hold at least one question."),
+    ("load.newer-format", "The registry was saved in format {found}, newer than the supported {supported}."),
+    ("stream.json", "Line {line} is not valid JSON: {error}"),
+    ("stream.question", "Line {line}: {error}"),
+    ("policy.rejected", "{rule}: {problems}"),
+    ("policy.needs-context", "needs context"),
+    ("policy.needs-attachment", "needs an attachment"),
+    ("policy.needs-options", "needs at least {count} options"),
+    ("policy.needs-rationale", "needs a rationale"),
+    ("policy.needs-approval", "needs approval from {approvers}"),
+    ("policy.unsupported-format", "Policies can't be read from \"{extension}\" files."),
+    ("backup.invalid-format", "The file is not a backup, or is truncated."),
+    ("backup.checksum-mismatch", "The backup is damaged: its contents don't match the recorded checksum."),
+    ("encryption.invalid-format", "The file is not an encrypted registry, or is truncated."),
+    ("encryption.invalid-key-file", "A key file must hold exactly 32 bytes."),
+    ("encryption.key-derivation", "No key could be derived from the passphrase."),
+    ("encryption.authentication", "The file could not be decrypted: the key is wrong, or the file was changed."),
+    ("storage.failed", "The change was made but not saved, and will be saved with the next one: {error}"),
+    ("storage.connection", "No connection to the database: {error}"),
+    ("storage.database", "The database failed: {error}"),
+    ("storage.conflict", "Question {question} was changed in storage since it was loaded; reload it and try again."),
+    ("remote.http", "The service answered with HTTP status {status}."),
+    ("remote.transport", "The service could not be reached: {error}"),
+    ("remote.invalid-response", "The service sent an unexpected response: {error}"),
+    ("vault.context", "Context"),
+    ("vault.options", "Options"),
+    ("vault.decision", "Decision"),
+    ("vault.leads-to", "leads to {link}"),
+    ("vault.decided-by", "Decided by {makers}."),
+    ("calendar.review", "Review: {question}"),
+    ("calendar.review-description", "Decided: {choice}"),
+    ("calendar.resume", "Due back: {question}"),
+    ("calendar.resume-description", "Deferred: {reason}")
+];
+
+pub trait Catalog: Send + Sync {
+    /// The template for `key`, or `None` to use the English one.
+    fn template(&self, key: &str) -> Option<&str>;
+}
+
+/// The built-in catalog, with a template for every key.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct English;
+
+impl Catalog for English {
+    fn template(&self, key: &str) -> Option<&str> {
+        ENGLISH.iter().find(|(known, _)| *known == key).map(|(_, template)| *template)
+    }
+}
+
+pub(crate) fn english() -> Arc<dyn Catalog> {
+    Arc::new(English)
+}
+
+/// Every key the library uses, for checking a translation is complete.
+pub fn keys() -> impl Iterator<Item = &'static str> {
+    ENGLISH.iter().map(|(key, _)| *key)
+}
+
+/// Templates supplied by an application, for one locale.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Translations {
+    templates: HashMap<String, String>
+}
+
+impl Translations {
+    pub fn new() -> Translations {
+        Translations::default()
+    }
+
+    /// Reads templates from a JSON object of keys to templates.
+    pub fn from_json(json: &str) -> serde_json::Result<Translations> {
+        Result::Ok(Translations { templates: serde_json::from_str(json)? })
+    }
+
+    pub fn with(mut self, key: &str, template: &str) -> Translations {
+        self.templates.insert(key.to_string(), template.to_string());
+        self
+    }
+
+    /// Keys the library uses that have no template here.
+    pub fn missing(&self) -> Vec<&'static str> {
+        keys().filter(|key| !self.templates.contains_key(*key)).collect()
+    }
+}
+
+impl Catalog for Translations {
+    fn template(&self, key: &str) -> Option<&str> {
+        self.templates.get(key).map(String::as_str)
+    }
+}
+
+/// Catalogs by locale, such as `de` or `pt-BR`.
+#[derive(Clone, Default)]
+pub struct Catalogs {
+    locales: HashMap<String, Arc<dyn Catalog>>
+}
+
+/// `locale` lowercased, with `_` written as `-`, so `pt_BR` and `pt-br` match.
+fn normalize_locale(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_lowercase()
+}
+
+impl Catalogs {
+    pub fn new() -> Catalogs {
+        Catalogs::default()
+    }
+
+    pub fn add<C: Catalog + 'static>(&mut self, locale: &str, catalog: C) {
+        self.locales.insert(normalize_locale(locale), Arc::new(catalog));
+    }
+
+    /// The catalog for `locale`, else for its language, else English. So
+    /// `pt-BR` is served by a `pt` catalog when there is no `pt-BR` one.
+    pub fn select(&self, locale: &str) -> Arc<dyn Catalog> {
+        let locale = normalize_locale(locale);
+        let language = locale.split('-').next().unwrap_or_default();
+        self.locales.get(&locale)
+            .or_else(|| self.locales.get(language))
+            .cloned()
+            .unwrap_or_else(english)
+    }
+}
+
+/// `key`'s template from `catalog`, or English, with `{name}` replaced by
+/// each argument. Unknown keys come out as the key itself.
+pub fn text(catalog: &dyn Catalog, key: &str, args: &[(&str, String)]) -> String {
+    let template = catalog.template(key).or_else(|| English.template(key)).unwrap_or(key);
+    let mut text = template.to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+/// A value with a message for people reading it.
+pub trait Localized {
+    fn localize(&self, catalog: &dyn Catalog) -> String;
+}
+
+/// Implements `Display` with the English message.
+macro_rules! display_localized {
+    ($($error:ty),+ $(,)?) => {
+        $(
+            impl std::fmt::Display for $error {
+                fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    formatter.write_str(&$crate::messages::Localized::localize(self, &$crate::messages::English))
+                }
+            }
+        )+
+    };
+}
+pub(crate) use display_localized;
+
+/// The message for a free-form list, such as validator reasons.
+pub(crate) fn list(items: &[String]) -> String {
+    items.join("; ")
+}
+
+/// The message for a question that changed since the caller read it.
+pub(crate) fn conflict(catalog: &dyn Catalog, question: &Question) -> String {
+    text(catalog, "question.conflict", &[("version", question.version.to_string())])
+}
+
+pub(crate) fn io(catalog: &dyn Catalog, error: &dyn std::fmt::Display) -> String {
+    text(catalog, "error.io", &[("error", error.to_string())])
+}
+
+pub(crate) fn encoding(catalog: &dyn Catalog, error: &dyn std::fmt::Display) -> String {
+    text(catalog, "error.encoding", &[("error", error.to_string())])
+}
+
+/// For operations that only fail reading or writing files, such as
+/// `PersistentRegistry::compact`.
+impl Localized for std::io::Error {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        io(catalog, self)
+    }
+}
+
+impl Localized for GetQuestionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            GetQuestionError::InvalidUUID => text(catalog, "question.invalid-uuid", &[]),
+            GetQuestionError::DoesNotExist => text(catalog, "question.does-not-exist", &[])
+        }
+    }
+}
+
+impl Localized for SetDecisionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            SetDecisionError::AlreadyExists => text(catalog, "decision.already-exists", &[]),
+            SetDecisionError::NotOpen(status) => text(catalog, "decision.not-open", &[("status", status.localize(catalog))])
+        }
+    }
+}
+
+impl Localized for AddTagErrors {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            AddTagErrors::AlreadyExists => text(catalog, "tag.already-exists", &[]),
+            AddTagErrors::Invalid(violations) => crate::tags::violations(catalog, violations)
+        }
+    }
+}
+
+impl Localized for AddQuestionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            AddQuestionError::AlreadyExists => text(catalog, "question.already-exists", &[]),
+            AddQuestionError::UsesNonExistentTags(tags) => text(catalog, "question.uses-unknown-tags", &[("tags", tags.join(", "))]),
+            AddQuestionError::Rejected(reasons) => text(catalog, "question.rejected", &[("reasons", list(reasons))]),
+            AddQuestionError::DuplicateExternalId { system, id, existing } => {
+                text(catalog, "question.duplicate-external-id", &[("system", system.clone()), ("id", id.clone()), ("existing", existing.clone())])
+            }
+        }
+    }
+}
+
+impl Localized for UpdateQuestionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            UpdateQuestionError::Question(error) => error.localize(catalog),
+            UpdateQuestionError::Conflict(question) => conflict(catalog, question)
+        }
+    }
+}
+
+impl Localized for SetQuestionDecisionError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            SetQuestionDecisionError::Question(error) => error.localize(catalog),
+            SetQuestionDecisionError::Conflict(question) => conflict(catalog, question),
+            SetQuestionDecisionError::Decision(error) => error.localize(catalog),
+            SetQuestionDecisionError::Rejected(reasons) => text(catalog, "decision.rejected", &[("reasons", list(reasons))])
+        }
+    }
+}
+
+impl Localized for ReorderOptionsError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            ReorderOptionsError::Question(error) => error.localize(catalog),
+            ReorderOptionsError::Conflict(question) => conflict(catalog, question),
+            ReorderOptionsError::UnknownOption(option) => text(catalog, "option.unknown", &[("option", option.clone())]),
+            ReorderOptionsError::NotAPermutation => text(catalog, "options.not-a-permutation", &[])
+        }
+    }
+}
+
+impl Localized for SetOptionOutcomeError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            SetOptionOutcomeError::Question(error) => error.localize(catalog),
+            SetOptionOutcomeError::Conflict(question) => conflict(catalog, question),
+            SetOptionOutcomeError::Outcome(error) => error.localize(catalog)
+        }
+    }
+}
+
+impl Localized for BulkTagError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            BulkTagError::UsesNonExistentTags(tags) => text(catalog, "tag.unknown", &[("tags", tags.join(", "))]),
+            BulkTagError::Question(identifier, error) => {
+                text(catalog, "bulk-tag.question", &[("identifier", identifier.clone()), ("error", error.localize(catalog))])
+            }
+        }
+    }
+}
+
+display_localized!(GetQuestionError, SetDecisionError, AddTagErrors, AddQuestionError, UpdateQuestionError,
+    SetQuestionDecisionError, ReorderOptionsError, SetOptionOutcomeError, BulkTagError);
+
+impl Registry {
+    /// Replaces the catalog the registry's generated text uses. Like the
+    /// clock, it isn't persisted.
+    pub fn set_catalog(&mut self, catalog: Arc<dyn Catalog>) {
+        self.catalog = catalog;
+    }
+
+    pub fn catalog(&self) -> &dyn Catalog {
+        &*self.catalog
+    }
+
+    /// `key`'s text from the registry's catalog.
+    pub(crate) fn text(&self, key: &str, args: &[(&str, String)]) -> String {
+        text(&*self.catalog, key, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::messages::*;
+
+    #[test]
+    fn test_locales_fall_back_to_language_then_english() {
+        let mut catalogs = Catalogs::new();
+        catalogs.add("de", Translations::new().with("option.unknown", "Die Frage hat keine Option \"{option}\"."));
+        let error = ReorderOptionsError::UnknownOption("Kafka".to_string());
+        assert_eq!(error.localize(&*catalogs.select("de_AT")), "Die Frage hat keine Option \"Kafka\".");
+        assert_eq!(error.localize(&*catalogs.select("fr")), "The question has no option \"Kafka\".");
+        assert_eq!(error.to_string(), "The question has no option \"Kafka\".");
+
+        let nested = SetQuestionDecisionError::Decision(SetDecisionError::NotOpen(status::QuestionStatus::Deferred));
+        assert_eq!(nested.to_string(), "Only open questions can be decided, and this one is deferred.");
+        let translations = Translations::from_json(r#"{"status.deferred": "zurückgestellt"}"#).unwrap();
+        assert!(nested.localize(&translations).ends_with("this one is zurückgestellt."));
+        assert!(translations.missing().contains(&"option.unknown"));
+    }
+
+    #[test]
+    fn test_registry_text_uses_its_catalog() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Kafka".to_string()].iter().cloned().collect();
+        registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        registry.set_catalog(std::sync::Arc::new(Translations::new().with("vault.options", "Optionen")));
+        let notes = registry.vault_notes();
+        assert!(notes[0].contents.contains("\n## Optionen\n\n- Kafka\n"));
+    }
+}
@@ -0,0 +1,311 @@
+//! Versioned on-disk format.
+//!
+//! Serialized registries carry a `format_version` next to their fields. Files
+//! from before versioning was introduced have no such field and are treated as
+//! version 1. Older files are upgraded one version at a time, `v1_to_v2`,
+//! `v2_to_v3` and so on, until they reach [`CURRENT_VERSION`]. Version 1 has
+//! its own types; from version 2 on the current types read the file and the
+//! steps work on the loaded `Registry`.
+//!
+//! Since version 4 files also carry a `min_reader_version`: the oldest
+//! library that can load them without losing anything. A newer file is
+//! loaded as long as this library is at least that version, and any fields
+//! it doesn't know, on the registry or on a question, are kept and written
+//! back out. Other newer files are rejected with [`LoadError::NewerFormat`]
+//! rather than being misread.
+
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::Registry;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+/// The format version written by this library. Raising it needs a step in
+/// `STEPS` from the previous version.
+pub const CURRENT_VERSION: u32 = 4;
+/// What this library writes as `min_reader_version`. Raise it only for
+/// changes older libraries would get wrong by passing fields through.
+pub const MIN_READER_VERSION: u32 = 4;
+const LEGACY_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    #[cfg(feature = "cbor")]
+    Cbor(serde_cbor::Error),
+    NewerFormat { found: u32, supported: u32 }
+}
+
+impl Localized for LoadError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            LoadError::Io(error) => messages::io(catalog, error),
+            LoadError::Json(error) => messages::encoding(catalog, error),
+            #[cfg(feature = "cbor")]
+            LoadError::Cbor(error) => messages::encoding(catalog, error),
+            LoadError::NewerFormat { found, supported } => {
+                messages::text(catalog, "load.newer-format", &[("found", found.to_string()), ("supported", supported.to_string())])
+            }
+        }
+    }
+}
+
+impl Coded for LoadError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            LoadError::Io(_) => ErrorCode::Io,
+            LoadError::Json(_) => ErrorCode::Encoding,
+            #[cfg(feature = "cbor")]
+            LoadError::Cbor(_) => ErrorCode::Encoding,
+            LoadError::NewerFormat { .. } => ErrorCode::NewerFormat
+        }
+    }
+}
+
+messages::display_localized!(LoadError);
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(error: serde_json::Error) -> LoadError {
+        LoadError::Json(error)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<serde_cbor::Error> for LoadError {
+    fn from(error: serde_cbor::Error) -> LoadError {
+        LoadError::Cbor(error)
+    }
+}
+
+/// Fields written by a newer library that this one doesn't know, kept so
+/// they can be written back out unchanged.
+pub type UnknownFields = BTreeMap<String, serde_json::Value>;
+
+#[derive(Serialize)]
+pub(crate) struct Versioned<'a> {
+    format_version: u32,
+    min_reader_version: u32,
+    #[serde(flatten)]
+    registry: &'a Registry
+}
+
+impl<'a> Versioned<'a> {
+    pub(crate) fn new(registry: &'a Registry) -> Versioned<'a> {
+        Versioned { format_version: CURRENT_VERSION, min_reader_version: MIN_READER_VERSION, registry }
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    #[serde(default = "legacy_version")]
+    format_version: u32,
+    min_reader_version: Option<u32>
+}
+
+impl Header {
+    fn readable(&self) -> bool {
+        self.format_version <= CURRENT_VERSION || self.min_reader_version.is_some_and(|min| min <= CURRENT_VERSION)
+    }
+}
+
+/// The envelope's own fields are read from the header, not passed through.
+fn without_envelope(mut registry: Registry) -> Registry {
+    registry.unknown_fields.remove("format_version");
+    registry.unknown_fields.remove("min_reader_version");
+    registry
+}
+
+fn legacy_version() -> u32 {
+    LEGACY_VERSION
+}
+
+/// Version 1: the original format, before audit logs and question versions.
+mod v1 {
+    use std::collections::{HashMap, HashSet};
+    use serde::Deserialize;
+    use uuid::Uuid;
+    use crate::{Actor, Decision, Question, Registry};
+
+    #[derive(Deserialize)]
+    pub struct V1Decision {
+        choice: String,
+        rationale: String,
+        decision_makers: HashSet<String>
+    }
+
+    #[derive(Deserialize)]
+    pub struct V1Question {
+        identifier: Uuid,
+        content: String,
+        tags: HashSet<String>,
+        context: HashSet<String>,
+        options: HashSet<String>,
+        decision: Option<V1Decision>
+    }
+
+    #[derive(Deserialize)]
+    pub struct V1Registry {
+        tags: HashSet<String>,
+        questions: HashMap<Uuid, V1Question>
+    }
+
+    pub fn v1_to_v2(old: V1Registry) -> Registry {
+        let mut registry = Registry::new();
+        registry.tags = old.tags;
+        registry.questions = old.questions.into_iter().map(|(uuid, old_question)| {
+            let mut question = Question::new(old_question.content, old_question.tags, old_question.context, old_question.options);
+            question.identifier = old_question.identifier;
+            question.decision = old_question.decision.map(|decision| Decision::new(
+                decision.choice,
+                decision.rationale,
+                decision.decision_makers.iter().map(|maker| Actor::new(maker)).collect()
+            ));
+            (uuid, question)
+        }).collect();
+        registry
+    }
+}
+
+/// Version 2 stored context items as plain strings, which `ContextItem`
+/// still deserializes from, so there is nothing left to convert.
+fn v2_to_v3(registry: Registry) -> Registry {
+    registry
+}
+
+/// Version 4 added `min_reader_version`, which lives in the envelope only.
+fn v3_to_v4(registry: Registry) -> Registry {
+    registry
+}
+
+/// Takes a registry from one version to the next.
+type Step = fn(Registry) -> Registry;
+
+/// The steps, by the version each starts from.
+const STEPS: [(u32, Step); 2] = [(2, v2_to_v3), (3, v3_to_v4)];
+
+/// Applies the steps from `version` on, leaving a registry of the current version.
+fn upgrade(mut registry: Registry, version: u32) -> Registry {
+    for (from, step) in STEPS.iter() {
+        if *from >= version {
+            registry = step(registry);
+        }
+    }
+    registry
+}
+
+pub(crate) fn from_json(json: &str) -> Result<Registry, LoadError> {
+    let header: Header = serde_json::from_str(json)?;
+    match header.format_version {
+        LEGACY_VERSION => Result::Ok(upgrade(v1::v1_to_v2(serde_json::from_str(json)?), LEGACY_VERSION + 1)),
+        found if header.readable() => Result::Ok(upgrade(without_envelope(serde_json::from_str(json)?), found)),
+        found => Result::Err(LoadError::NewerFormat { found, supported: CURRENT_VERSION })
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub(crate) fn from_cbor(bytes: &[u8]) -> Result<Registry, LoadError> {
+    let header: Header = serde_cbor::from_slice(bytes)?;
+    match header.format_version {
+        LEGACY_VERSION => Result::Ok(upgrade(v1::v1_to_v2(serde_cbor::from_slice(bytes)?), LEGACY_VERSION + 1)),
+        found if header.readable() => Result::Ok(upgrade(without_envelope(serde_cbor::from_slice(bytes)?), found)),
+        found => Result::Err(LoadError::NewerFormat { found, supported: CURRENT_VERSION })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use crate::migrations::*;
+
+    const V1_JSON: &str = r#"{
+        "tags": ["Infra"],
+        "questions": {
+            "5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80": {
+                "identifier": "5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80",
+                "content": "Which queue?",
+                "tags": ["Infra"],
+                "context": [],
+                "options": ["RabbitMQ"],
+                "decision": {"choice": "RabbitMQ", "rationale": "We run it already", "decision_makers": ["luke"]}
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_legacy_json_is_upgraded() {
+        let registry = Registry::deserialize_json(V1_JSON).unwrap();
+        let question = registry.get_question("5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80".to_string()).unwrap();
+        assert_eq!(question.version(), 0);
+        assert!(question.get_decision().unwrap().decision_makers.contains(&Actor::new("luke")));
+        assert!(registry.serialize_json().contains(&format!("\"format_version\":{}", CURRENT_VERSION)));
+    }
+
+    #[test]
+    fn test_steps_reach_the_current_version() {
+        let versions: Vec<u32> = STEPS.iter().map(|(from, _)| *from).collect();
+        assert_eq!(versions, (LEGACY_VERSION + 1..CURRENT_VERSION).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_newer_format_is_rejected() {
+        match Registry::deserialize_json(r#"{"format_version": 99, "tags": [], "questions": {}}"#) {
+            Err(LoadError::NewerFormat { found: 99, supported: CURRENT_VERSION }) => (),
+            _ => panic!("A newer format should not be loaded")
+        }
+    }
+
+    #[test]
+    fn test_newer_fields_are_passed_through() {
+        let json = r#"{
+            "format_version": 5, "min_reader_version": 4, "tags": [], "review_board": ["ana"],
+            "questions": {
+                "5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80": {
+                    "identifier": "5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80",
+                    "content": "Which queue?", "tags": [], "context": [], "options": [], "decision": null,
+                    "review_by": {"date": "2025-01-01"}
+                }
+            }
+        }"#;
+        let mut registry = Registry::deserialize_json(json).unwrap();
+        registry.edit_question_content("5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80".to_string(), "Which message queue?".to_string(), 0, &Actor::new("luke")).unwrap();
+
+        let written: serde_json::Value = serde_json::from_str(&registry.serialize_json()).unwrap();
+        assert_eq!(written["format_version"], CURRENT_VERSION);
+        assert_eq!(written["review_board"][0], "ana");
+        let question = &written["questions"]["5c2b4a1e-8f0d-4e9a-9a51-2d3c1f6b7e80"];
+        assert_eq!(question["review_by"]["date"], "2025-01-01");
+        assert_eq!(question["content"], "Which message queue?");
+
+        let unreadable = json.replace(r#""min_reader_version": 4"#, r#""min_reader_version": 5"#);
+        assert!(matches!(Registry::deserialize_json(&unreadable), Err(LoadError::NewerFormat { found: 5, .. })));
+    }
+
+    #[test]
+    #[cfg(feature = "cbor")]
+    fn test_cbor_round_trip() {
+        use std::collections::HashSet;
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"Infra".to_string(), &luke).unwrap();
+        let tags: HashSet<String> = ["Infra".to_string()].iter().cloned().collect();
+        let context: HashSet<String> = ["Needs ordering".to_string()].iter().cloned().collect();
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), tags, context, HashSet::new()), &luke).unwrap();
+        registry.edit_question_content(identifier.clone(), "Which message queue?".to_string(), 0, &luke).unwrap();
+        let path = std::env::temp_dir().join(format!("decis_test_cbor_round_trip_{}", std::process::id()));
+        registry.serialize_cbor(path.to_str().unwrap()).unwrap();
+
+        let loaded = Registry::deserialize_cbor(path.to_str().unwrap()).unwrap();
+        assert!(loaded.get_tags().contains("Infra"));
+        assert_eq!(loaded.audit_log().entries().len(), 3);
+        let question = loaded.get_question(identifier.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&question).unwrap(), serde_json::to_value(registry.get_question(identifier).unwrap()).unwrap());
+        assert_eq!((question.content.as_str(), question.version()), ("Which message queue?", 1));
+    }
+}
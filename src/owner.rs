@@ -0,0 +1,55 @@
+//! Who is responsible for driving a question to a decision.
+
+use crate::{Actor, Question, QuestionSummary, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+
+impl Question {
+    pub fn get_owner(&self) -> Option<&Actor> {
+        self.owner.as_ref()
+    }
+}
+
+impl Registry {
+    /// Hands the question to `owner`, or leaves it unowned with `None`, and
+    /// returns the question's new version. Every reassignment is recorded
+    /// as `OwnerChanged`.
+    pub fn set_question_owner(&mut self, identifier: String, owner: Option<Actor>, expected_version: u64, actor: &Actor) -> Result<u64, UpdateQuestionError> {
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        question.owner = owner.clone();
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::OwnerChanged(owner));
+        Result::Ok(version)
+    }
+
+    pub fn questions_owned_by(&self, owner: &Actor) -> Vec<QuestionSummary> {
+        let now = self.now();
+        self.questions.values()
+            .filter(|question| question.owner.as_ref() == Some(owner))
+            .map(|question| question.summary_at(now))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::audit::AuditAction;
+
+    #[test]
+    fn test_reassignment_is_recorded() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(Question::new("Who runs the migration?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.set_question_owner(identifier.clone(), Some(luke.clone()), 0, &luke).unwrap();
+        registry.set_question_owner(identifier.clone(), Some(ana.clone()), 1, &luke).unwrap();
+
+        assert!(registry.questions_owned_by(&luke).is_empty());
+        assert_eq!(registry.questions_owned_by(&ana)[0].owner, Some(ana.clone()));
+        let owners: Vec<&AuditAction> = registry.audit_log().entries().iter().map(|entry| &entry.action).skip(1).collect();
+        assert_eq!(owners, vec![&AuditAction::OwnerChanged(Some(luke)), &AuditAction::OwnerChanged(Some(ana))]);
+    }
+}
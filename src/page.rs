@@ -0,0 +1,163 @@
+//! Cursor-based paging over listings.
+//!
+//! A cursor records where the previous page ended by its last question's
+//! sort key rather than by position, so the next page starts right after
+//! that question even if questions were added or removed in between.
+
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{Question, QuestionSummary, Registry};
+use crate::query::{self, Sort, SortKey};
+use crate::search::SearchTerm;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass to the same listing for the next page; `None` on the last one.
+    pub next_cursor: Option<String>
+}
+
+#[derive(Debug)]
+pub enum PageError {
+    /// The cursor is malformed, or was issued for a different sort.
+    InvalidCursor,
+    /// A page must hold at least one question, or it couldn't move the
+    /// cursor on.
+    ZeroLimit
+}
+
+impl Localized for PageError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            PageError::InvalidCursor => messages::text(catalog, "page.invalid-cursor", &[]),
+            PageError::ZeroLimit => messages::text(catalog, "page.zero-limit", &[])
+        }
+    }
+}
+
+impl Coded for PageError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            PageError::InvalidCursor => ErrorCode::InvalidCursor,
+            PageError::ZeroLimit => ErrorCode::ZeroPageLimit
+        }
+    }
+}
+
+messages::display_localized!(PageError);
+
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    sort: Sort,
+    key: SortKey,
+    content: String,
+    identifier: Uuid
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursors always serialize");
+        json.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn decode(cursor: &str) -> Option<Cursor> {
+        if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+            return None;
+        }
+        let bytes: Option<Vec<u8>> = (0..cursor.len()).step_by(2)
+            .map(|start| u8::from_str_radix(&cursor[start..start + 2], 16).ok())
+            .collect();
+        serde_json::from_slice(&bytes?).ok()
+    }
+}
+
+impl Registry {
+    /// Up to `limit` of `questions` in `sort` order, starting after `cursor`.
+    fn page(&self, questions: Vec<&Question>, sort: Sort, cursor: Option<&str>, limit: usize) -> Result<Page<QuestionSummary>, PageError> {
+        if limit == 0 {
+            return Result::Err(PageError::ZeroLimit);
+        }
+        let now = self.now();
+        let after = match cursor {
+            Some(cursor) => match Cursor::decode(cursor) {
+                Some(cursor) if cursor.sort == sort => Some(cursor),
+                _ => return Result::Err(PageError::InvalidCursor)
+            },
+            None => None
+        };
+        let mut remaining = self.keyed(questions, sort).into_iter()
+            .filter(|(key, question)| after.as_ref().is_none_or(|after| {
+                query::compare(sort, (key, &question.content, &question.identifier), (&after.key, &after.content, &after.identifier)).is_gt()
+            }))
+            .peekable();
+        let mut items = Vec::new();
+        let mut last = None;
+        while items.len() < limit {
+            match remaining.next() {
+                Some((key, question)) => {
+                    items.push(question.summary_at(now));
+                    last = Some((key, question));
+                }
+                None => break
+            }
+        }
+        let next_cursor = match (remaining.peek(), last) {
+            (Some(_), Some((key, question))) => Some(Cursor { sort, key, content: question.content.clone(), identifier: question.identifier }.encode()),
+            _ => None
+        };
+        Result::Ok(Page { items, next_cursor })
+    }
+
+    /// A page of `list_questions_sorted`.
+    pub fn list_questions_page(&self, sort: Sort, cursor: Option<&str>, limit: usize) -> Result<Page<QuestionSummary>, PageError> {
+        let today = self.now().date_naive();
+        self.page(self.questions.values().filter(|question| question.is_listed_by_default(today)).collect(), sort, cursor, limit)
+    }
+
+    /// A page of `list_questions_with_tag_sorted`.
+    pub fn list_questions_with_tag_page(&self, tag: &str, sort: Sort, cursor: Option<&str>, limit: usize) -> Result<Page<QuestionSummary>, PageError> {
+        let today = self.now().date_naive();
+        self.page(self.questions_with_tag(tag).into_iter().filter(|question| question.is_listed_by_default(today)).collect(), sort, cursor, limit)
+    }
+
+    /// A page of `search_sorted`.
+    pub fn search_page(&self, query: &str, sort: Sort, cursor: Option<&str>, limit: usize) -> Result<Page<QuestionSummary>, PageError> {
+        self.page(self.matching_questions(&SearchTerm::parse(query)), sort, cursor, limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::page::*;
+    use crate::query::{Sort, SortBy};
+
+    #[test]
+    fn test_paging_is_stable_under_changes() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        for content in &["A?", "B?", "C?", "D?"] {
+            registry.add_question(Question::new(content.to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        }
+        let contents = |page: &Page<QuestionSummary>| -> Vec<String> { page.items.iter().map(|summary| summary.content.clone()).collect() };
+
+        let first = registry.list_questions_page(Sort::default(), None, 2).unwrap();
+        assert_eq!(contents(&first), vec!["A?", "B?"]);
+        // Neither a question before the cursor nor one after it shifts the next page.
+        registry.add_question(Question::new("AA?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question(Question::new("BB?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let cursor = first.next_cursor.unwrap();
+        let second = registry.list_questions_page(Sort::default(), Some(&cursor), 2).unwrap();
+        assert_eq!(contents(&second), vec!["BB?", "C?"]);
+        let last = registry.list_questions_page(Sort::default(), second.next_cursor.as_deref(), 2).unwrap();
+        assert_eq!(contents(&last), vec!["D?"]);
+        assert!(last.next_cursor.is_none());
+
+        assert!(matches!(registry.list_questions_page(Sort::descending(SortBy::Content), Some(&cursor), 2), Result::Err(PageError::InvalidCursor)));
+        assert!(matches!(registry.search_page("?", Sort::default(), Some("zz"), 2), Result::Err(PageError::InvalidCursor)));
+        assert!(matches!(registry.list_questions_page(Sort::default(), Some(&cursor), 0), Result::Err(PageError::ZeroLimit)));
+    }
+}
@@ -0,0 +1,718 @@
+//! A registry that writes its changes through to a [`Storage`] backend.
+
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+use crate::{Actor, AddQuestionError, AddTagErrors, BulkResult, BulkTagError, Decision, GetQuestionError, Question, QuestionSelector,
+            Registry, ReorderOptionsError, SetOptionOutcomeError, SetQuestionDecisionError, UpdateQuestionError};
+use crate::estimate::{Estimate, SetEstimateError};
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+#[cfg(feature = "notion")]
+use crate::export::{ExportError, NotionConfig, NotionExport};
+use crate::simulate::Distribution;
+use crate::tree::{Branch, SetBranchesError};
+use crate::arguments::{AddArgumentError, Stance};
+use crate::attachments::Reference;
+use crate::audit::AuditAction;
+use crate::clock::Clock;
+use crate::ids::IdGenerator;
+use crate::merge::MergeReport;
+use crate::comments::CommentError;
+use crate::context::{RetractContextError, RetractionKind};
+use crate::criteria::{AddCriterionError, Criterion, ScoreOptionError};
+use crate::search::SearchAnalysis;
+use crate::storage::Storage;
+use crate::stream::StreamError;
+use crate::subset::{ImportOptions, Subset, SubsetImport};
+use crate::sync::{Resolution, SyncPoint, SyncReport};
+use crate::policy::Policy;
+use crate::priority::Priority;
+use crate::privacy::{ErasurePolicy, ErasureReport};
+use crate::status::{QuestionStatus, SetStatusError};
+use crate::validation::Validator;
+use crate::tags::{RenameTagError, TagNormalization, TagNormalizationError, TagRules};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WritePolicy {
+    /// Persist after every mutation.
+    Immediate,
+    /// Persist on the first mutation after the interval has elapsed since the
+    /// last write, and on `flush` / drop. There is no background thread, so
+    /// changes made during a quiet period wait for one of those.
+    Debounced(Duration)
+}
+
+#[derive(Debug)]
+pub enum WriteThroughError<E, S> {
+    /// The mutation itself was rejected; nothing was written.
+    Operation(E),
+    /// The mutation was applied in memory but persisting it failed. It stays
+    /// pending and is retried on the next write.
+    Storage(S)
+}
+
+impl<E: Localized, S: Localized> Localized for WriteThroughError<E, S> {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            WriteThroughError::Operation(error) => error.localize(catalog),
+            WriteThroughError::Storage(error) => messages::text(catalog, "storage.failed", &[("error", error.localize(catalog))])
+        }
+    }
+}
+
+impl<E: Localized, S: Localized> std::fmt::Display for WriteThroughError<E, S> {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(&self.localize(&messages::English))
+    }
+}
+
+/// Storage failures take the storage error's code.
+impl<E: Coded, S: Coded> Coded for WriteThroughError<E, S> {
+    fn code(&self) -> ErrorCode {
+        match self {
+            WriteThroughError::Operation(error) => error.code(),
+            WriteThroughError::Storage(error) => error.code()
+        }
+    }
+}
+
+/// Reads are served from the wrapped in-memory `Registry` (through `Deref`);
+/// each mutation is applied there first and then persisted. What to write is
+/// worked out from the audit entries recorded since the last write.
+pub struct PersistentRegistry<S: Storage> {
+    registry: Registry,
+    storage: S,
+    policy: WritePolicy,
+    persisted_entries: usize,
+    /// The backend's audit trail has to be replaced rather than appended to.
+    rewrite_audit: bool,
+    /// Entries already written were dropped from the log outside `compact`,
+    /// so it no longer shows what is pending and everything is written again.
+    resync: bool,
+    last_write: Instant
+}
+
+impl<S: Storage> PersistentRegistry<S> {
+    /// Loads the whole registry, every question body included, into memory;
+    /// reads never go back to the backend. For large backends that only
+    /// need a few questions, read them with `Storage::get_question` instead.
+    pub fn open(storage: S, policy: WritePolicy) -> Result<PersistentRegistry<S>, S::Error> {
+        let registry = Registry::load_from(&storage)?;
+        let persisted_entries = registry.audit_log().entries().len();
+        Result::Ok(PersistentRegistry { registry, storage, policy, persisted_entries, rewrite_audit: false, resync: false, last_write: Instant::now() })
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Whether there are changes that have not been written to the backend yet.
+    pub fn is_dirty(&self) -> bool {
+        self.rewrite_audit || self.resync || self.registry.audit_log().entries().len() != self.persisted_entries
+    }
+
+    /// Writes every pending change to the backend.
+    pub fn flush(&mut self) -> Result<(), S::Error> {
+        if self.resync || self.registry.audit_log().entries().len() < self.persisted_entries {
+            self.resync = true;
+            return self.write_everything();
+        }
+        let pending = &self.registry.audit_log().entries()[self.persisted_entries..];
+        // Tags to write (true) or remove (false), in log order.
+        let mut tags: Vec<(&String, bool)> = Vec::new();
+        let mut criteria: Vec<&String> = Vec::new();
+        let mut settings_changed = false;
+        let mut audit_rewritten = self.rewrite_audit;
+        let mut questions: HashSet<Uuid> = HashSet::new();
+        for entry in pending {
+            match &entry.action {
+                AuditAction::TagAdded(tag) => tags.push((tag, true)),
+                AuditAction::TagRenamed { from, to } if entry.question.is_none() => {
+                    tags.push((from, false));
+                    tags.push((to, true));
+                }
+                AuditAction::CriterionAdded(name) => criteria.push(name),
+                AuditAction::SettingsChanged => settings_changed = true,
+                AuditAction::PersonErased if entry.question.is_none() => audit_rewritten = true,
+                _ => ()
+            }
+            if let Some(question) = entry.question {
+                questions.insert(question);
+            }
+        }
+        if settings_changed {
+            self.storage.put_settings(&self.registry.settings)?;
+        }
+        for (tag, present) in tags {
+            if present {
+                self.storage.put_tag(tag)?;
+            } else {
+                self.storage.remove_tag(tag)?;
+            }
+        }
+        for name in criteria {
+            if let Some(criterion) = self.registry.criteria.get(name) {
+                self.storage.put_criterion(criterion)?;
+            }
+        }
+        for identifier in questions {
+            if let Some(question) = self.registry.questions.get(&identifier) {
+                self.storage.put_question(question)?;
+            }
+        }
+        if audit_rewritten {
+            let entries = self.registry.audit_log().entries();
+            self.storage.replace_audit_entries(entries)?;
+            self.persisted_entries = entries.len();
+            self.rewrite_audit = false;
+        } else {
+            for entry in pending {
+                self.storage.append_audit_entry(entry)?;
+                self.persisted_entries += 1;
+            }
+        }
+        self.last_write = Instant::now();
+        Result::Ok(())
+    }
+
+    /// Writes the whole registry and replaces the backend's audit trail.
+    fn write_everything(&mut self) -> Result<(), S::Error> {
+        self.storage.put_settings(&self.registry.settings)?;
+        for tag in self.storage.tags()? {
+            if !self.registry.tags.contains(&tag) {
+                self.storage.remove_tag(&tag)?;
+            }
+        }
+        for tag in &self.registry.tags {
+            self.storage.put_tag(tag)?;
+        }
+        for criterion in self.registry.criteria.values() {
+            self.storage.put_criterion(criterion)?;
+        }
+        for question in self.registry.questions.values() {
+            self.storage.put_question(question)?;
+        }
+        let entries = self.registry.audit_log().entries();
+        self.storage.replace_audit_entries(entries)?;
+        self.persisted_entries = entries.len();
+        self.rewrite_audit = false;
+        self.resync = false;
+        self.last_write = Instant::now();
+        Result::Ok(())
+    }
+
+    fn flush_if_due(&mut self) -> Result<(), S::Error> {
+        let due = match self.policy {
+            WritePolicy::Immediate => true,
+            WritePolicy::Debounced(interval) => self.last_write.elapsed() >= interval
+        };
+        if due {
+            self.flush()?;
+        }
+        Result::Ok(())
+    }
+
+    fn write_through<T, E>(&mut self, result: Result<T, E>) -> Result<T, WriteThroughError<E, S::Error>> {
+        let value = result.map_err(WriteThroughError::Operation)?;
+        self.flush_if_due().map_err(WriteThroughError::Storage)?;
+        Result::Ok(value)
+    }
+
+    pub fn add_tag(&mut self, tag: &String, actor: &Actor) -> Result<bool, WriteThroughError<AddTagErrors, S::Error>> {
+        let result = self.registry.add_tag(tag, actor);
+        self.write_through(result)
+    }
+
+    /// Validators aren't persisted; register them each time the registry is opened.
+    pub fn add_validator<V: Validator + 'static>(&mut self, validator: V) {
+        self.registry.add_validator(validator);
+    }
+
+    pub fn add_policy(&mut self, policy: Policy) {
+        self.registry.add_policy(policy);
+    }
+
+    /// Like validators, the clock isn't persisted.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.registry.set_clock(clock);
+    }
+
+    pub fn set_id_generator<G: IdGenerator + 'static>(&mut self, ids: G) {
+        self.registry.set_id_generator(ids);
+    }
+
+    pub fn set_catalog(&mut self, catalog: Arc<dyn Catalog>) {
+        self.registry.set_catalog(catalog);
+    }
+
+    pub fn rename_tag(&mut self, from: &str, to: &str, actor: &Actor) -> Result<usize, WriteThroughError<RenameTagError, S::Error>> {
+        let result = self.registry.rename_tag(from, to, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_tag_rules(&mut self, rules: TagRules, actor: &Actor) -> Result<(), S::Error> {
+        self.registry.set_tag_rules(rules, actor);
+        self.flush_if_due()
+    }
+
+    pub fn set_search_analysis(&mut self, analysis: SearchAnalysis, actor: &Actor) -> Result<(), S::Error> {
+        self.registry.set_search_analysis(analysis, actor);
+        self.flush_if_due()
+    }
+
+    pub fn add_criterion(&mut self, criterion: Criterion, actor: &Actor) -> Result<(), WriteThroughError<AddCriterionError, S::Error>> {
+        let result = self.registry.add_criterion(criterion, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_tag_normalization(&mut self, normalization: TagNormalization, actor: &Actor) -> Result<(), WriteThroughError<TagNormalizationError, S::Error>> {
+        let result = self.registry.set_tag_normalization(normalization, actor);
+        self.write_through(result)
+    }
+
+    pub fn add_question(&mut self, question: Question, actor: &Actor) -> Result<String, WriteThroughError<AddQuestionError, S::Error>> {
+        let result = self.registry.add_question(question, actor);
+        self.write_through(result)
+    }
+
+    pub fn add_questions(&mut self, questions: Vec<Question>, actor: &Actor) -> Result<BulkResult, S::Error> {
+        let result = self.registry.add_questions(questions, actor);
+        self.flush_if_due()?;
+        Result::Ok(result)
+    }
+
+    pub fn edit_question_content(&mut self, identifier: String, content: String, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<UpdateQuestionError, S::Error>> {
+        let result = self.registry.edit_question_content(identifier, content, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn add_question_context(&mut self, identifier: String, new_contexts: HashSet<String>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<UpdateQuestionError, S::Error>> {
+        let result = self.registry.add_question_context(identifier, new_contexts, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn add_question_context_item(&mut self, identifier: String, text: String, source: Option<String>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<UpdateQuestionError, S::Error>> {
+        let result = self.registry.add_question_context_item(identifier, text, source, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn retract_context(&mut self, identifier: String, text: &str, kind: RetractionKind, note: String, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<RetractContextError, S::Error>> {
+        let result = self.registry.retract_context(identifier, text, kind, note, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn add_question_option(&mut self, identifier: String, new_options: HashSet<String>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<UpdateQuestionError, S::Error>> {
+        let result = self.registry.add_question_option(identifier, new_options, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn reorder_options(&mut self, identifier: String, order: Vec<String>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<ReorderOptionsError, S::Error>> {
+        let result = self.registry.reorder_options(identifier, order, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn move_option(&mut self, identifier: String, option: &str, position: usize, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<ReorderOptionsError, S::Error>> {
+        let result = self.registry.move_option(identifier, option, position, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_question_decision(&mut self, identifier: String, decision: Decision, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetQuestionDecisionError, S::Error>> {
+        let result = self.registry.set_question_decision(identifier, decision, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_question_status(&mut self, identifier: String, status: QuestionStatus, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetStatusError, S::Error>> {
+        let result = self.registry.set_question_status(identifier, status, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn defer_question(&mut self, identifier: String, reason: String, until: Option<NaiveDate>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetStatusError, S::Error>> {
+        let result = self.registry.defer_question(identifier, reason, until, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn cancel_question(&mut self, identifier: String, reason: String, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetStatusError, S::Error>> {
+        let result = self.registry.cancel_question(identifier, reason, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn reopen_question(&mut self, identifier: String, reason: String, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetStatusError, S::Error>> {
+        let result = self.registry.reopen_question(identifier, reason, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_question_owner(&mut self, identifier: String, owner: Option<Actor>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<UpdateQuestionError, S::Error>> {
+        let result = self.registry.set_question_owner(identifier, owner, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_question_priority(&mut self, identifier: String, priority: Priority, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<UpdateQuestionError, S::Error>> {
+        let result = self.registry.set_question_priority(identifier, priority, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn erase_person(&mut self, person_id: &str, policy: ErasurePolicy, actor: &Actor) -> Result<ErasureReport, S::Error> {
+        let report = self.registry.erase_person(person_id, policy, actor);
+        self.flush_if_due()?;
+        Result::Ok(report)
+    }
+
+    /// Like `Registry::import_stream`. The questions imported before a bad
+    /// line are persisted too.
+    pub fn import_stream<R: BufRead>(&mut self, reader: R, actor: &Actor) -> Result<usize, WriteThroughError<StreamError, S::Error>> {
+        let result = self.registry.import_stream(reader, actor);
+        self.flush_if_due().map_err(WriteThroughError::Storage)?;
+        result.map_err(WriteThroughError::Operation)
+    }
+
+    pub fn import_subset(&mut self, subset: Subset, options: &ImportOptions, actor: &Actor) -> Result<SubsetImport, S::Error> {
+        let report = self.registry.import_subset(subset, options, actor);
+        self.flush_if_due()?;
+        Result::Ok(report)
+    }
+
+    /// Syncs with `remote`, see `Registry::sync_with`. Only this side is
+    /// written to the backend; `remote` is the caller's to save.
+    pub fn sync_with<F: FnMut(&Question, &Question) -> Resolution>(&mut self, remote: &mut Registry, since: &SyncPoint, resolve: F, actor: &Actor) -> Result<SyncReport, S::Error> {
+        let report = self.registry.sync_with(remote, since, resolve, actor);
+        self.flush_if_due()?;
+        Result::Ok(report)
+    }
+
+    pub fn merge(&mut self, other: &Registry, actor: &Actor) -> Result<MergeReport, S::Error> {
+        let report = self.registry.merge(other, actor);
+        self.flush_if_due()?;
+        Result::Ok(report)
+    }
+
+    pub fn set_external_id(&mut self, identifier: String, system: &str, id: String, actor: &Actor) -> Result<u64, WriteThroughError<GetQuestionError, S::Error>> {
+        let result = self.registry.set_external_id(identifier, system, id, actor);
+        self.write_through(result)
+    }
+
+    #[cfg(feature = "notion")]
+    pub fn export_to_notion(&mut self, config: &NotionConfig, actor: &Actor) -> Result<NotionExport, WriteThroughError<ExportError, S::Error>> {
+        let result = self.registry.export_to_notion(config, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_option_outcome(&mut self, identifier: String, option: &str, metric: &str, distribution: Distribution, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetOptionOutcomeError, S::Error>> {
+        let result = self.registry.set_option_outcome(identifier, option, metric, distribution, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_option_branches(&mut self, identifier: String, option: &str, branches: Vec<Branch>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetBranchesError, S::Error>> {
+        let result = self.registry.set_option_branches(identifier, option, branches, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn set_option_estimate(&mut self, identifier: String, option: &str, estimate: Estimate, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<SetEstimateError, S::Error>> {
+        let result = self.registry.set_option_estimate(identifier, option, estimate, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn score_option(&mut self, identifier: String, option: &str, criterion: &str, score: f64, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<ScoreOptionError, S::Error>> {
+        let result = self.registry.score_option(identifier, option, criterion, score, expected_version, actor);
+        self.write_through(result)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_argument(&mut self, identifier: String, option: &str, stance: Stance, text: String, weight: u8, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<AddArgumentError, S::Error>> {
+        let result = self.registry.add_argument(identifier, option, stance, text, weight, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn add_comment(&mut self, identifier: String, body: String, reply_to: Option<String>, actor: &Actor) -> Result<String, WriteThroughError<CommentError, S::Error>> {
+        let result = self.registry.add_comment(identifier, body, reply_to, actor);
+        self.write_through(result)
+    }
+
+    pub fn edit_comment(&mut self, identifier: String, comment: String, body: String, actor: &Actor) -> Result<(), WriteThroughError<CommentError, S::Error>> {
+        let result = self.registry.edit_comment(identifier, comment, body, actor);
+        self.write_through(result)
+    }
+
+    pub fn add_attachment(&mut self, identifier: String, reference: Reference, description: Option<String>, expected_version: u64, actor: &Actor) -> Result<u64, WriteThroughError<UpdateQuestionError, S::Error>> {
+        let result = self.registry.add_attachment(identifier, reference, description, expected_version, actor);
+        self.write_through(result)
+    }
+
+    pub fn bulk_tag(&mut self, selector: QuestionSelector, add: &[String], remove: &[String], actor: &Actor) -> Result<usize, WriteThroughError<BulkTagError, S::Error>> {
+        let result = self.registry.bulk_tag(selector, add, remove, actor);
+        self.write_through(result)
+    }
+
+    /// Like `Registry::compact`. Pending changes are written first, then the
+    /// backend's audit trail is replaced with what is left.
+    pub fn compact(&mut self, before: DateTime<Utc>, archive: Option<&Path>) -> Result<usize, WriteThroughError<std::io::Error, S::Error>> {
+        self.flush().map_err(WriteThroughError::Storage)?;
+        let removed = self.registry.compact(before, archive).map_err(WriteThroughError::Operation)?;
+        if removed > 0 {
+            self.persisted_entries = self.registry.audit_log().entries().len();
+            self.rewrite_audit = true;
+            self.flush().map_err(WriteThroughError::Storage)?;
+        }
+        Result::Ok(removed)
+    }
+
+    /// Like `Registry::transaction`; the batch is persisted only if it commits.
+    /// A batch that drops written audit entries, e.g. by compacting, makes the
+    /// next write replace everything in the backend.
+    pub fn transaction<T, E, F>(&mut self, operations: F) -> Result<T, WriteThroughError<E, S::Error>>
+        where F: FnOnce(&mut Registry) -> Result<T, E> {
+        let last_written = self.persisted_entries.checked_sub(1)
+            .and_then(|index| self.registry.audit_log().entries().get(index).cloned().map(|entry| (index, entry)));
+        let result = self.registry.transaction(operations);
+        if let Some((index, entry)) = last_written {
+            if self.registry.audit_log().entries().get(index) != Some(&entry) {
+                self.resync = true;
+            }
+        }
+        self.write_through(result)
+    }
+}
+
+impl<S: Storage> Deref for PersistentRegistry<S> {
+    type Target = Registry;
+
+    fn deref(&self) -> &Registry {
+        &self.registry
+    }
+}
+
+impl<S: Storage> Drop for PersistentRegistry<S> {
+    fn drop(&mut self) {
+        // Errors can't be reported from drop; call `flush` first to see them.
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{HashMap, HashSet};
+    use std::rc::Rc;
+    use std::time::Duration;
+    use uuid::Uuid;
+    use crate::*;
+    use crate::audit::AuditEntry;
+    use crate::clock::{Clock, FixedClock};
+    use crate::criteria::{Criterion, Direction};
+    use crate::persistent::*;
+    use crate::settings::Settings;
+    use crate::storage::Storage;
+    use crate::stream::StreamError;
+    use crate::tags::TagNormalization;
+
+    #[derive(Default)]
+    struct Contents {
+        settings: Option<Settings>,
+        tags: HashSet<String>,
+        criteria: HashMap<String, Criterion>,
+        questions: HashMap<Uuid, Question>,
+        audit: Vec<AuditEntry>,
+        question_writes: usize
+    }
+
+    /// Shares its contents with the test so they can be inspected after the
+    /// registry has taken ownership of the storage.
+    #[derive(Clone, Default)]
+    struct MemoryStorage(Rc<RefCell<Contents>>);
+
+    impl Storage for MemoryStorage {
+        type Error = ();
+
+        fn put_tag(&mut self, tag: &str) -> Result<(), ()> {
+            self.0.borrow_mut().tags.insert(tag.to_string());
+            Result::Ok(())
+        }
+
+        fn remove_tag(&mut self, tag: &str) -> Result<(), ()> {
+            self.0.borrow_mut().tags.remove(tag);
+            Result::Ok(())
+        }
+
+        fn replace_audit_entries(&mut self, entries: &[AuditEntry]) -> Result<(), ()> {
+            self.0.borrow_mut().audit = entries.to_vec();
+            Result::Ok(())
+        }
+
+        fn tags(&self) -> Result<HashSet<String>, ()> {
+            Result::Ok(self.0.borrow().tags.clone())
+        }
+
+        fn put_settings(&mut self, settings: &Settings) -> Result<(), ()> {
+            self.0.borrow_mut().settings = Some(settings.clone());
+            Result::Ok(())
+        }
+
+        fn settings(&self) -> Result<Option<Settings>, ()> {
+            Result::Ok(self.0.borrow().settings.clone())
+        }
+
+        fn put_criterion(&mut self, criterion: &Criterion) -> Result<(), ()> {
+            self.0.borrow_mut().criteria.insert(criterion.name.clone(), criterion.clone());
+            Result::Ok(())
+        }
+
+        fn criteria(&self) -> Result<Vec<Criterion>, ()> {
+            Result::Ok(self.0.borrow().criteria.values().cloned().collect())
+        }
+
+        fn put_question(&mut self, question: &Question) -> Result<(), ()> {
+            let mut contents = self.0.borrow_mut();
+            contents.question_writes += 1;
+            contents.questions.insert(question.identifier, question.clone());
+            Result::Ok(())
+        }
+
+        fn get_question(&self, identifier: &Uuid) -> Result<Option<Question>, ()> {
+            Result::Ok(self.0.borrow().questions.get(identifier).cloned())
+        }
+
+        fn questions(&self) -> Result<Vec<Question>, ()> {
+            Result::Ok(self.0.borrow().questions.values().cloned().collect())
+        }
+
+        fn question_ids_with_tag(&self, tag: &str) -> Result<Vec<Uuid>, ()> {
+            Result::Ok(self.0.borrow().questions.values().filter(|question| question.tags.contains(tag)).map(|question| question.identifier).collect())
+        }
+
+        fn append_audit_entry(&mut self, entry: &AuditEntry) -> Result<(), ()> {
+            self.0.borrow_mut().audit.push(entry.clone());
+            Result::Ok(())
+        }
+
+        fn audit_entries(&self) -> Result<Vec<AuditEntry>, ()> {
+            Result::Ok(self.0.borrow().audit.clone())
+        }
+    }
+
+    #[test]
+    fn test_immediate_writes_each_mutation() {
+        let storage = MemoryStorage::default();
+        let luke = Actor::new("luke");
+        let mut registry = PersistentRegistry::open(storage.clone(), WritePolicy::Immediate).unwrap();
+        registry.add_tag(&"Infra".to_string(), &luke).unwrap();
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let mut options: HashSet<String> = HashSet::new();
+        options.insert("RabbitMQ".to_string());
+        registry.add_question_option(identifier.clone(), options, 0, &luke).unwrap();
+
+        registry.add_criterion(Criterion::new("Throughput", "Messages per second", 1.0, 5.0, Direction::HigherIsBetter), &luke).unwrap();
+        registry.set_tag_normalization(TagNormalization::lenient(), &luke).unwrap();
+
+        assert!(!registry.is_dirty());
+        let reloaded = Registry::load_from(&storage).unwrap();
+        assert_eq!(reloaded.criteria().len(), 1);
+        assert_eq!(reloaded.tag_normalization(), TagNormalization::lenient());
+        assert!(reloaded.get_tags().contains("Infra"));
+        assert!(reloaded.get_question(identifier.clone()).unwrap().get_options().contains("RabbitMQ"));
+        assert_eq!(reloaded.audit_log().entries().len(), 5);
+        assert!(registry.get_question(identifier).is_ok());
+    }
+
+    #[test]
+    fn test_debounced_writes_wait_for_flush() {
+        let storage = MemoryStorage::default();
+        let luke = Actor::new("luke");
+        let mut registry = PersistentRegistry::open(storage.clone(), WritePolicy::Debounced(Duration::from_secs(3600))).unwrap();
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let mut context: HashSet<String> = HashSet::new();
+        context.insert("Needs ordering".to_string());
+        registry.add_question_context(identifier, context, 0, &luke).unwrap();
+
+        assert!(registry.is_dirty());
+        assert_eq!(storage.0.borrow().question_writes, 0);
+        registry.flush().unwrap();
+        assert_eq!(storage.0.borrow().question_writes, 1);
+        assert_eq!(storage.0.borrow().audit.len(), 2);
+    }
+
+    #[test]
+    fn test_rejected_mutation_writes_nothing() {
+        let storage = MemoryStorage::default();
+        let mut registry = PersistentRegistry::open(storage.clone(), WritePolicy::Immediate).unwrap();
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("Unknown".to_string());
+        match registry.add_question(Question::new("Tagged?".to_string(), tags, HashSet::new(), HashSet::new()), &Actor::new("luke")) {
+            Err(WriteThroughError::Operation(AddQuestionError::UsesNonExistentTags(_))) => (),
+            _ => panic!("The unknown tag should have been rejected")
+        }
+        assert!(storage.0.borrow().questions.is_empty());
+    }
+
+    #[test]
+    fn test_erasure_rewrites_the_stored_audit_trail() {
+        let storage = MemoryStorage::default();
+        let mut registry = PersistentRegistry::open(storage.clone(), WritePolicy::Immediate).unwrap();
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &Actor::new("ana")).unwrap();
+        registry.erase_person("ana", ErasurePolicy::Pseudonymize, &Actor::new("luke")).unwrap();
+
+        let stored = storage.0.borrow();
+        assert_eq!(stored.audit.len(), 3);
+        assert!(stored.audit.iter().all(|entry| entry.actor.id() != "ana"));
+        let question = &stored.questions[&uuid::Uuid::parse_str(&identifier).unwrap()];
+        assert!(question.history().iter().all(|change| change.actor.id() != "ana"));
+    }
+
+    #[test]
+    fn test_compaction_and_stream_imports_reach_the_backend() {
+        let storage = MemoryStorage::default();
+        let luke = Actor::new("luke");
+        let clock = FixedClock::new(chrono::Utc::now());
+        let mut registry = PersistentRegistry::open(storage.clone(), WritePolicy::Immediate).unwrap();
+        registry.set_clock(clock.clone());
+        registry.add_tag(&"Old".to_string(), &luke).unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        assert_eq!(registry.compact(clock.now(), None).unwrap(), 1);
+        assert!(!registry.is_dirty());
+        assert!(storage.0.borrow().audit.is_empty());
+
+        let mut source = Registry::new();
+        source.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let mut stream = Vec::new();
+        source.export_stream(&mut stream).unwrap();
+        stream.extend_from_slice(b"not json\n");
+        match registry.import_stream(stream.as_slice(), &luke) {
+            Err(WriteThroughError::Operation(StreamError::Json { line: 2, .. })) => (),
+            _ => panic!("The second line should have been rejected")
+        }
+        assert_eq!(storage.0.borrow().questions.len(), 1);
+    }
+
+    #[test]
+    fn test_compacting_in_a_transaction_rewrites_the_backend() {
+        let storage = MemoryStorage::default();
+        let luke = Actor::new("luke");
+        let clock = FixedClock::new(chrono::Utc::now());
+        let mut registry = PersistentRegistry::open(storage.clone(), WritePolicy::Immediate).unwrap();
+        registry.set_clock(clock.clone());
+        registry.add_tag(&"Old".to_string(), &luke).unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+        registry.add_tag(&"Infra".to_string(), &luke).unwrap();
+        clock.advance(chrono::Duration::minutes(1));
+
+        let cutoff = clock.now() - chrono::Duration::seconds(90);
+        let identifier = registry.transaction(|registry| -> Result<String, AddQuestionError> {
+            registry.compact(cutoff, None).unwrap();
+            registry.add_tag(&"Billing".to_string(), &luke).unwrap();
+            registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke)
+        }).unwrap();
+        assert!(!registry.is_dirty());
+        drop(registry);
+
+        let reopened = PersistentRegistry::open(storage, WritePolicy::Immediate).unwrap();
+        let actions: Vec<&audit::AuditAction> = reopened.audit_log().entries().iter().map(|entry| &entry.action).collect();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0], &audit::AuditAction::TagAdded("Infra".to_string()));
+        assert!(reopened.get_tags().contains("Billing"));
+        assert!(reopened.get_question(identifier).is_ok());
+    }
+}
@@ -0,0 +1,262 @@
+//! Governance rules declared in a file instead of code.
+//!
+//! A [`Policy`] is a list of rules, each applying to questions carrying a set
+//! of tags and requiring context, attachments, a minimum number of options, a
+//! rationale or particular approvers. Context and options are checked when a
+//! question is added; attachments, which can only be added to a question
+//! already in the registry, are checked with the rest when it is decided.
+//! Rules are loaded from JSON, or from TOML with the `toml` feature:
+//!
+//! ```toml
+//! [[rules]]
+//! name = "security review"
+//! when_tagged = ["security"]
+//! require = ["context", "rationale"]
+//! min_options = 2
+//! required_approvers = ["alice"]
+//! ```
+//!
+//! `Registry::add_policy` registers every rule as a [`Validator`].
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::{Decision, Question, Registry};
+use crate::validation::Validator;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    /// At least one current context item.
+    Context,
+    /// At least one attachment, by the time the question is decided.
+    Attachments,
+    /// A non-blank rationale on the decision.
+    Rationale
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Used in rejection messages.
+    pub name: String,
+    /// The rule applies to questions carrying all of these tags, spelled as
+    /// stored; to every question when empty.
+    #[serde(default)]
+    pub when_tagged: Vec<String>,
+    #[serde(default)]
+    pub require: Vec<Field>,
+    pub min_options: Option<usize>,
+    /// Actor ids that must be among the decision makers.
+    #[serde(default)]
+    pub required_approvers: Vec<String>
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    pub rules: Vec<Rule>
+}
+
+#[derive(Debug)]
+pub enum PolicyError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    /// The file extension isn't one of the supported formats.
+    UnsupportedFormat(String)
+}
+
+impl Localized for PolicyError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            PolicyError::Io(error) => messages::io(catalog, error),
+            PolicyError::Json(error) => messages::encoding(catalog, error),
+            #[cfg(feature = "toml")]
+            PolicyError::Toml(error) => messages::encoding(catalog, error),
+            PolicyError::UnsupportedFormat(extension) => messages::text(catalog, "policy.unsupported-format", &[("extension", extension.clone())])
+        }
+    }
+}
+
+impl Coded for PolicyError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            PolicyError::Io(_) => ErrorCode::Io,
+            PolicyError::Json(_) => ErrorCode::Encoding,
+            #[cfg(feature = "toml")]
+            PolicyError::Toml(_) => ErrorCode::Encoding,
+            PolicyError::UnsupportedFormat(_) => ErrorCode::UnsupportedPolicyFormat
+        }
+    }
+}
+
+messages::display_localized!(PolicyError);
+
+impl Policy {
+    pub fn from_json(text: &str) -> Result<Policy, PolicyError> {
+        serde_json::from_str(text).map_err(PolicyError::Json)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Policy, PolicyError> {
+        toml::from_str(text).map_err(PolicyError::Toml)
+    }
+
+    /// Reads a `.json` file, or a `.toml` one with the `toml` feature.
+    pub fn from_file(path: &Path) -> Result<Policy, PolicyError> {
+        let text = fs::read_to_string(path).map_err(PolicyError::Io)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Policy::from_json(&text),
+            #[cfg(feature = "toml")]
+            Some("toml") => Policy::from_toml(&text),
+            other => Result::Err(PolicyError::UnsupportedFormat(other.unwrap_or_default().to_string()))
+        }
+    }
+}
+
+impl Rule {
+    fn applies_to(&self, question: &Question) -> bool {
+        self.when_tagged.iter().all(|tag| question.tags.contains(tag))
+    }
+
+    fn reject(&self, catalog: &dyn Catalog, problems: Vec<String>) -> Result<(), String> {
+        if problems.is_empty() {
+            Result::Ok(())
+        } else {
+            Result::Err(messages::text(catalog, "policy.rejected", &[("rule", self.name.clone()), ("problems", problems.join("; "))]))
+        }
+    }
+}
+
+impl Validator for Rule {
+    fn validate_question(&self, question: &Question) -> Result<(), String> {
+        self.validate_question_with(question, &messages::English)
+    }
+
+    fn validate_decision(&self, question: &Question, decision: &Decision) -> Result<(), String> {
+        self.validate_decision_with(question, decision, &messages::English)
+    }
+
+    fn validate_question_with(&self, question: &Question, catalog: &dyn Catalog) -> Result<(), String> {
+        if !self.applies_to(question) {
+            return Result::Ok(());
+        }
+        let mut problems = Vec::new();
+        if self.require.contains(&Field::Context) && !question.context.iter().any(|item| item.is_current()) {
+            problems.push(messages::text(catalog, "policy.needs-context", &[]));
+        }
+        if let Some(min_options) = self.min_options {
+            if question.options.len() < min_options {
+                problems.push(messages::text(catalog, "policy.needs-options", &[("count", min_options.to_string())]));
+            }
+        }
+        self.reject(catalog, problems)
+    }
+
+    fn validate_decision_with(&self, question: &Question, decision: &Decision, catalog: &dyn Catalog) -> Result<(), String> {
+        if !self.applies_to(question) {
+            return Result::Ok(());
+        }
+        let mut problems = Vec::new();
+        if self.require.contains(&Field::Attachments) && question.attachments.is_empty() {
+            problems.push(messages::text(catalog, "policy.needs-attachment", &[]));
+        }
+        if self.require.contains(&Field::Rationale) && decision.rationale.trim().is_empty() {
+            problems.push(messages::text(catalog, "policy.needs-rationale", &[]));
+        }
+        let missing: Vec<&str> = self.required_approvers.iter()
+            .filter(|approver| !decision.decision_makers.iter().any(|maker| maker.id() == approver.as_str()))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            problems.push(messages::text(catalog, "policy.needs-approval", &[("approvers", missing.join(", "))]));
+        }
+        self.reject(catalog, problems)
+    }
+}
+
+impl Registry {
+    /// Registers each of the policy's rules as a validator.
+    pub fn add_policy(&mut self, policy: Policy) {
+        for rule in policy.rules {
+            self.add_validator(rule);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::policy::*;
+
+    #[test]
+    fn test_policy_rules_are_enforced() {
+        let policy = Policy::from_json(r#"{"rules": [{
+            "name": "security review",
+            "when_tagged": ["security"],
+            "require": ["rationale"],
+            "min_options": 2,
+            "required_approvers": ["alice"]
+        }]}"#).unwrap();
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"security".to_string(), &luke).unwrap();
+        registry.add_policy(policy);
+
+        let tags: HashSet<String> = ["security".to_string()].iter().cloned().collect();
+        let mut options: HashSet<String> = ["Open".to_string()].iter().cloned().collect();
+        match registry.add_question(Question::new("Open the port?".to_string(), tags.clone(), HashSet::new(), options.clone()), &luke) {
+            Err(AddQuestionError::Rejected(messages)) => assert_eq!(messages, vec!["security review: needs at least 2 options".to_string()]),
+            _ => panic!("The policy should reject questions with one option")
+        }
+        options.insert("Keep closed".to_string());
+        let identifier = registry.add_question(Question::new("Open the port?".to_string(), tags, HashSet::new(), options), &luke).unwrap();
+        match registry.set_question_decision(identifier, Decision::new("Open".to_string(), " ".to_string(), HashSet::new()), 0, &luke) {
+            Err(SetQuestionDecisionError::Rejected(messages)) =>
+                assert_eq!(messages, vec!["security review: needs a rationale; needs approval from alice".to_string()]),
+            _ => panic!("The policy should reject the decision")
+        }
+    }
+
+    #[test]
+    fn test_attachments_are_required_when_deciding() {
+        let policy = Policy::from_json(r#"{"rules": [{"name": "docs", "require": ["attachments"]}]}"#).unwrap();
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_policy(policy);
+        let options: HashSet<String> = ["Postgres".to_string()].iter().cloned().collect();
+        let identifier = registry.add_question(Question::new("Which database?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        let decision = || Decision::new("Postgres".to_string(), "Familiar".to_string(), HashSet::new());
+        match registry.set_question_decision(identifier.clone(), decision(), 0, &luke) {
+            Err(SetQuestionDecisionError::Rejected(messages)) => assert_eq!(messages, vec!["docs: needs an attachment".to_string()]),
+            _ => panic!("The policy should require an attachment")
+        }
+
+        registry.set_catalog(std::sync::Arc::new(messages::Translations::new().with("policy.needs-attachment", "braucht einen Anhang")));
+        match registry.set_question_decision(identifier.clone(), decision(), 0, &luke) {
+            Err(SetQuestionDecisionError::Rejected(messages)) => assert_eq!(messages, vec!["docs: braucht einen Anhang".to_string()]),
+            _ => panic!("The policy should require an attachment")
+        }
+        registry.add_attachment(identifier.clone(), attachments::Reference::Url("https://wiki/db".to_string()), None, 0, &luke).unwrap();
+        registry.set_question_decision(identifier, decision(), 1, &luke).unwrap();
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(matches!(Policy::from_json(r#"{"rules": [{"name": "typo", "min_option": 2}]}"#), Err(PolicyError::Json(_))));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_policy_loads_from_toml() {
+        let policy = Policy::from_toml("[[rules]]\nname = \"docs\"\nrequire = [\"context\", \"attachments\"]\n").unwrap();
+        assert_eq!(policy.rules[0].require, vec![Field::Context, Field::Attachments]);
+    }
+}
@@ -0,0 +1,65 @@
+//! How urgently a question needs deciding, for triaging the backlog.
+
+use serde::{Serialize, Deserialize};
+use crate::{Actor, Question, QuestionSummary, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+
+/// Ordered from least to most urgent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent
+}
+
+impl Question {
+    pub fn get_priority(&self) -> Priority {
+        self.priority
+    }
+}
+
+impl Registry {
+    /// Returns the question's new version.
+    pub fn set_question_priority(&mut self, identifier: String, priority: Priority, expected_version: u64, actor: &Actor) -> Result<u64, UpdateQuestionError> {
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let uuid = question.identifier;
+        question.priority = priority;
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::PrioritySet(priority));
+        Result::Ok(version)
+    }
+
+    /// `list_questions`, most urgent first and alphabetical within a priority.
+    pub fn list_questions_by_priority(&self) -> Vec<QuestionSummary> {
+        let mut summaries = self.list_questions();
+        summaries.sort_by(|a, b| b.priority.cmp(&a.priority).then_with(|| a.content.cmp(&b.content)));
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::priority::*;
+
+    #[test]
+    fn test_listing_by_priority() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        for content in &["Rename the repo?", "Which database?", "Rotate the keys?"] {
+            registry.add_question(Question::new(content.to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        }
+        let find = |registry: &Registry, content: &str| registry.list_questions().into_iter().find(|summary| summary.content == content).unwrap().identifier;
+        let keys = find(&registry, "Rotate the keys?");
+        let rename = find(&registry, "Rename the repo?");
+        registry.set_question_priority(keys, Priority::Urgent, 0, &luke).unwrap();
+        registry.set_question_priority(rename, Priority::Low, 0, &luke).unwrap();
+
+        let order: Vec<String> = registry.list_questions_by_priority().into_iter().map(|summary| summary.content).collect();
+        assert_eq!(order, vec!["Rotate the keys?", "Which database?", "Rename the repo?"]);
+    }
+}
@@ -0,0 +1,292 @@
+//! Scrubbing people's identities from a registry.
+//!
+//! `Registry::anonymized` gives a copy in which every person appears under a
+//! consistent placeholder, for sharing a decision log outside the
+//! organisation: export the copy with any of the usual formats. Free text
+//! (question content, context, comments) is left as written.
+//!
+//! `Registry::erase_person` handles erasure requests by removing or
+//! replacing one person's identity in place, audit trail included.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use uuid::Uuid;
+use crate::{Actor, Question, Registry};
+use crate::audit::{AuditAction, AuditEntry};
+
+/// Where in the registry an identity was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Place {
+    DecisionMaker,
+    Owner,
+    CommentAuthor,
+    ArgumentAuthor,
+    Attachment,
+    Context,
+    /// Deferrals, cancellations and reopened decisions.
+    StatusRecord,
+    Score,
+    QuestionHistory,
+    AuditEntry
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErasurePolicy {
+    /// Replace the person with the same placeholder everywhere.
+    Pseudonymize,
+    /// Also delete what they wrote and contributed (comments, arguments,
+    /// scores) and drop them as decision maker and owner. Records that have
+    /// to keep an author, like the audit trail, get the placeholder.
+    Remove
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ErasureReport {
+    /// The placeholder that now stands in for the person.
+    pub placeholder: Option<Actor>,
+    /// Identifiers of the questions that changed.
+    pub questions: Vec<String>,
+    pub replaced: BTreeMap<Place, usize>,
+    pub removed: BTreeMap<Place, usize>
+}
+
+impl ErasureReport {
+    pub fn is_empty(&self) -> bool {
+        self.replaced.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn map_decision_makers<F: FnMut(Place, &Actor) -> Actor>(makers: &mut HashSet<Actor>, f: &mut F) {
+    *makers = makers.iter().map(|maker| f(Place::DecisionMaker, maker)).collect();
+}
+
+fn map_action<F: FnMut(Place, &Actor) -> Actor>(action: &mut AuditAction, place: Place, f: &mut F) {
+    if let AuditAction::OwnerChanged(Some(owner)) = action {
+        *owner = f(place, owner);
+    }
+}
+
+impl Question {
+    fn map_actors<F: FnMut(Place, &Actor) -> Actor>(&mut self, f: &mut F) {
+        if let Some(decision) = &mut self.decision {
+            map_decision_makers(&mut decision.decision_makers, f);
+        }
+        if let Some(owner) = &mut self.owner {
+            *owner = f(Place::Owner, owner);
+        }
+        for comment in &mut self.comments {
+            comment.author = f(Place::CommentAuthor, &comment.author);
+        }
+        for arguments in self.arguments.values_mut() {
+            for argument in arguments.pros.iter_mut().chain(arguments.cons.iter_mut()) {
+                argument.author = f(Place::ArgumentAuthor, &argument.author);
+            }
+        }
+        for attachment in &mut self.attachments {
+            attachment.added_by = f(Place::Attachment, &attachment.added_by);
+        }
+        for item in &mut self.context {
+            if let Some(added_by) = &mut item.added_by {
+                *added_by = f(Place::Context, added_by);
+            }
+            if let Some(retraction) = &mut item.retraction {
+                retraction.by = f(Place::Context, &retraction.by);
+            }
+        }
+        if let Some(deferral) = &mut self.deferral {
+            deferral.by = f(Place::StatusRecord, &deferral.by);
+        }
+        if let Some(cancellation) = &mut self.cancellation {
+            cancellation.by = f(Place::StatusRecord, &cancellation.by);
+        }
+        for past in &mut self.decision_history {
+            past.by = f(Place::StatusRecord, &past.by);
+            map_decision_makers(&mut past.decision.decision_makers, f);
+        }
+        for criteria in self.scores.values_mut() {
+            for scores in criteria.values_mut() {
+                let mapped: BTreeMap<Actor, f64> = scores.iter().map(|(evaluator, score)| (f(Place::Score, evaluator), *score)).collect();
+                *scores = mapped;
+            }
+        }
+        for change in &mut self.history {
+            change.actor = f(Place::QuestionHistory, &change.actor);
+            map_action(&mut change.action, Place::QuestionHistory, f);
+        }
+    }
+}
+
+impl Registry {
+    /// Calls `f` for every identity stored anywhere in the registry and
+    /// replaces it with the result.
+    pub(crate) fn map_actors<F: FnMut(Place, &Actor) -> Actor>(&mut self, mut f: F) {
+        let mut identifiers: Vec<_> = self.questions.keys().copied().collect();
+        // A stable order, so numbering by first appearance is repeatable.
+        identifiers.sort();
+        for identifier in identifiers {
+            self.questions.get_mut(&identifier).unwrap().map_actors(&mut f);
+        }
+        self.map_audit_actors(f);
+    }
+
+    fn map_audit_actors<F: FnMut(Place, &Actor) -> Actor>(&mut self, mut f: F) {
+        let entries: Vec<AuditEntry> = self.audit_log.entries().iter().cloned().map(|mut entry| {
+            entry.actor = f(Place::AuditEntry, &entry.actor);
+            map_action(&mut entry.action, Place::AuditEntry, &mut f);
+            entry
+        }).collect();
+        self.audit_log = crate::audit::AuditLog::from_entries(entries);
+    }
+
+    /// A copy with every person replaced by `person-1`, `person-2` and so on,
+    /// the same placeholder for the same person throughout. People are
+    /// numbered in the order they first appear in the audit log.
+    pub fn anonymized(&self) -> Registry {
+        let mut pseudonyms: HashMap<Actor, Actor> = HashMap::new();
+        for entry in self.audit_log.entries() {
+            let next = pseudonyms.len() + 1;
+            pseudonyms.entry(entry.actor.clone()).or_insert_with(|| Actor::new(&format!("person-{}", next)));
+        }
+        let mut copy = self.clone();
+        copy.map_actors(|_, actor| {
+            let next = pseudonyms.len() + 1;
+            pseudonyms.entry(actor.clone()).or_insert_with(|| Actor::new(&format!("person-{}", next))).clone()
+        });
+        copy
+    }
+}
+
+impl Question {
+    /// Deletes what `person` contributed, counting by place.
+    fn remove_contributions(&mut self, person: &Actor, removed: &mut BTreeMap<Place, usize>) {
+        let mut count = |place: Place, before: usize, after: usize| if before > after {
+            *removed.entry(place).or_insert(0) += before - after;
+        };
+        let before = self.comments.len();
+        self.comments.retain(|comment| &comment.author != person);
+        count(Place::CommentAuthor, before, self.comments.len());
+        for arguments in self.arguments.values_mut() {
+            let before = arguments.pros.len() + arguments.cons.len();
+            arguments.pros.retain(|argument| &argument.author != person);
+            arguments.cons.retain(|argument| &argument.author != person);
+            count(Place::ArgumentAuthor, before, arguments.pros.len() + arguments.cons.len());
+        }
+        for scores in self.scores.values_mut().flat_map(|criteria| criteria.values_mut()) {
+            count(Place::Score, scores.len(), { scores.remove(person); scores.len() });
+        }
+        let decisions = self.decision.iter_mut().chain(self.decision_history.iter_mut().map(|past| &mut past.decision));
+        for decision in decisions {
+            count(Place::DecisionMaker, decision.decision_makers.len(), { decision.decision_makers.remove(person); decision.decision_makers.len() });
+        }
+        if self.owner.as_ref() == Some(person) {
+            self.owner = None;
+            count(Place::Owner, 1, 0);
+        }
+    }
+}
+
+impl Registry {
+    /// Removes or pseudonymizes `person_id` everywhere in the registry,
+    /// including past audit entries, and reports what changed. Each changed
+    /// question records `PersonErased`, without naming the person.
+    pub fn erase_person(&mut self, person_id: &str, policy: ErasurePolicy, actor: &Actor) -> ErasureReport {
+        let person = Actor::new(person_id);
+        let placeholder = Actor::new(&format!("erased-{}", &self.next_id().to_simple().to_string()[..8]));
+        let mut report = ErasureReport::default();
+        let mut identifiers: Vec<Uuid> = self.questions.keys().copied().collect();
+        identifiers.sort();
+        let mut changed = Vec::new();
+        for identifier in identifiers {
+            let question = self.questions.get_mut(&identifier).unwrap();
+            let mut touched = false;
+            if policy == ErasurePolicy::Remove {
+                let before: usize = report.removed.values().sum();
+                question.remove_contributions(&person, &mut report.removed);
+                touched = report.removed.values().sum::<usize>() > before;
+            }
+            question.map_actors(&mut |place, found| if found == &person {
+                touched = true;
+                *report.replaced.entry(place).or_insert(0) += 1;
+                placeholder.clone()
+            } else {
+                found.clone()
+            });
+            if touched {
+                question.version += 1;
+                changed.push(identifier);
+            }
+        }
+        self.map_audit_actors(|place, found| if found == &person {
+            *report.replaced.entry(place).or_insert(0) += 1;
+            placeholder.clone()
+        } else {
+            found.clone()
+        });
+        if report.is_empty() {
+            return report;
+        }
+        for identifier in &changed {
+            self.record_change(actor, *identifier, AuditAction::PersonErased);
+        }
+        self.audit_log.record(self.now(), actor, None, AuditAction::PersonErased);
+        report.placeholder = Some(placeholder);
+        report.questions = changed.iter().map(Uuid::to_string).collect();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+
+    #[test]
+    fn test_anonymized_copy_uses_consistent_placeholders() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let identifier = registry.add_question(Question::new("Ship it?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        registry.add_comment(identifier.clone(), "Looks good".to_string(), None, &ana).unwrap();
+        let makers: HashSet<Actor> = [ana.clone()].iter().cloned().collect();
+        registry.set_question_decision(identifier.clone(), Decision::new("Yes".to_string(), "Tested".to_string(), makers), 0, &luke).unwrap();
+
+        let anonymized = registry.anonymized();
+        let question = anonymized.get_question(identifier).unwrap();
+        let expected: HashSet<Actor> = [Actor::new("person-1"), Actor::new("person-2")].iter().cloned().collect();
+        assert_eq!(question.get_decision().unwrap().get_decision_makers(), &expected);
+        assert_eq!(question.comments[0].author, Actor::new("person-2"));
+        let actors: Vec<&str> = anonymized.audit_log().entries().iter().map(|entry| entry.actor.id()).collect();
+        assert_eq!(actors, vec!["person-1", "person-2", "person-1"]);
+        let json = anonymized.serialize_json();
+        assert!(!json.contains("luke") && !json.contains("\"ana\""));
+        // The original is untouched.
+        assert_eq!(registry.audit_log().entries()[0].actor, luke);
+    }
+
+    #[test]
+    fn test_erase_person_removes_their_contributions() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let identifier = registry.add_question(Question::new("Ship it?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        registry.add_comment(identifier.clone(), "Ana here".to_string(), None, &ana).unwrap();
+        registry.set_question_owner(identifier.clone(), Some(ana.clone()), 0, &luke).unwrap();
+        let makers: HashSet<Actor> = [ana.clone()].iter().cloned().collect();
+        registry.set_question_decision(identifier.clone(), Decision::new("Yes".to_string(), "Tested".to_string(), makers), 1, &luke).unwrap();
+
+        let report = registry.erase_person("ana", privacy::ErasurePolicy::Remove, &luke);
+        assert_eq!(report.questions, vec![identifier.clone()]);
+        assert_eq!(report.removed[&privacy::Place::CommentAuthor], 1);
+        assert_eq!(report.removed[&privacy::Place::Owner], 1);
+        assert_eq!(report.removed[&privacy::Place::DecisionMaker], 1);
+        // Their comment, and the ownership change naming them.
+        assert_eq!(report.replaced[&privacy::Place::AuditEntry], 2);
+
+        let question = registry.get_question(identifier).unwrap();
+        assert!(question.comments.is_empty() && question.get_owner().is_none());
+        assert_eq!(question.version(), 3);
+        assert!(!registry.serialize_json().contains("\"ana\""));
+        assert!(registry.erase_person("ana", privacy::ErasurePolicy::Remove, &luke).is_empty());
+    }
+}
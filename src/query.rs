@@ -0,0 +1,264 @@
+//! Filtered and sorted views over the registry's questions.
+//!
+//! The `_sorted` variants of the listings take a `Sort`, and order the
+//! questions before summarizing them.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{Actor, Question, QuestionSummary, Registry};
+use crate::audit::AuditAction;
+use crate::priority::Priority;
+use crate::search::SearchTerm;
+use crate::status::QuestionStatus;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortBy {
+    /// When the question was added.
+    Created,
+    /// When the question last saw any activity, see `Registry::last_activity`.
+    Updated,
+    /// When the current decision was made; undecided questions sort first.
+    Decided,
+    Priority,
+    #[default]
+    Content,
+    TagCount
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortDirection {
+    #[default]
+    Ascending,
+    Descending
+}
+
+/// Ties are broken by content, then identifier, both ascending, so the
+/// order is the same on every call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sort {
+    pub by: SortBy,
+    pub direction: SortDirection
+}
+
+impl Sort {
+    pub fn ascending(by: SortBy) -> Sort {
+        Sort { by, direction: SortDirection::Ascending }
+    }
+
+    pub fn descending(by: SortBy) -> Sort {
+        Sort { by, direction: SortDirection::Descending }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub(crate) enum SortKey {
+    Time(Option<DateTime<Utc>>),
+    Number(usize),
+    Text(String)
+}
+
+/// Which undecided questions `Registry::undecided` returns. The default
+/// matches all of them; each field set narrows it further.
+#[derive(Clone, Debug, Default)]
+pub struct UndecidedFilter {
+    /// Questions carrying every one of these tags.
+    pub tags: Vec<String>,
+    /// Questions asked at least this long ago.
+    pub min_age: Option<Duration>,
+    pub owner: Option<Actor>,
+    /// Questions at this priority or more urgent.
+    pub min_priority: Option<Priority>
+}
+
+impl Registry {
+    /// When the question was added, from its history or, for questions
+    /// saved before histories were kept, the audit log.
+    pub(crate) fn created_at(&self, question: &Question) -> Option<DateTime<Utc>> {
+        let added = |action: &AuditAction| action == &AuditAction::QuestionAdded;
+        question.history().iter().find(|change| added(&change.action)).map(|change| change.at)
+            .or_else(|| self.audit_log.entries().iter()
+                .find(|entry| entry.question == Some(question.identifier) && added(&entry.action))
+                .map(|entry| entry.at))
+    }
+
+    /// When the question's current decision was set, if it has one.
+    pub(crate) fn decided_at(&self, question: &Question) -> Option<DateTime<Utc>> {
+        question.decision.as_ref()?;
+        let decided = |action: &AuditAction| matches!(action, AuditAction::DecisionSet(_));
+        question.history().iter().rev().find(|change| decided(&change.action)).map(|change| change.at)
+            .or_else(|| self.audit_log.entries().iter().rev()
+                .find(|entry| entry.question == Some(question.identifier) && decided(&entry.action))
+                .map(|entry| entry.at))
+    }
+
+    /// Orders `questions` by `sort` and summarizes them.
+    pub(crate) fn sorted(&self, questions: Vec<&Question>, sort: Sort) -> Vec<QuestionSummary> {
+        let now = self.now();
+        self.keyed(questions, sort).into_iter().map(|(_, question)| question.summary_at(now)).collect()
+    }
+
+    /// `questions` ordered by `sort`, with their keys. Each key is worked
+    /// out once per question.
+    pub(crate) fn keyed<'a>(&self, questions: Vec<&'a Question>, sort: Sort) -> Vec<(SortKey, &'a Question)> {
+        let activity = match sort.by {
+            SortBy::Updated => self.last_activity(),
+            _ => HashMap::new()
+        };
+        let mut keyed: Vec<(SortKey, &Question)> = questions.into_iter()
+            .map(|question| (self.sort_key(question, sort.by, &activity), question))
+            .collect();
+        keyed.sort_by(|(a_key, a), (b_key, b)| compare(sort, (a_key, &a.content, &a.identifier), (b_key, &b.content, &b.identifier)));
+        keyed
+    }
+
+    fn sort_key(&self, question: &Question, by: SortBy, activity: &HashMap<Uuid, DateTime<Utc>>) -> SortKey {
+        match by {
+            SortBy::Created => SortKey::Time(self.created_at(question)),
+            SortBy::Updated => SortKey::Time(activity.get(&question.identifier).copied()),
+            SortBy::Decided => SortKey::Time(self.decided_at(question)),
+            SortBy::Priority => SortKey::Number(question.priority as usize),
+            SortBy::Content => SortKey::Text(question.content.clone()),
+            SortBy::TagCount => SortKey::Number(question.tags.len())
+        }
+    }
+
+    /// `list_questions`, ordered by `sort`.
+    pub fn list_questions_sorted(&self, sort: Sort) -> Vec<QuestionSummary> {
+        let today = self.now().date_naive();
+        self.sorted(self.questions.values().filter(|question| question.is_listed_by_default(today)).collect(), sort)
+    }
+
+    /// `list_questions_with_tag`, ordered by `sort`.
+    pub fn list_questions_with_tag_sorted(&self, tag: &str, sort: Sort) -> Vec<QuestionSummary> {
+        let today = self.now().date_naive();
+        self.sorted(self.questions_with_tag(tag).into_iter().filter(|question| question.is_listed_by_default(today)).collect(), sort)
+    }
+
+    /// `list_questions_in`, ordered by `sort`.
+    pub fn list_questions_in_sorted(&self, statuses: &[QuestionStatus], sort: Sort) -> Vec<QuestionSummary> {
+        self.sorted(self.questions.values().filter(|question| statuses.contains(&question.status())).collect(), sort)
+    }
+
+    /// `search`, ordered by `sort`.
+    pub fn search_sorted(&self, query: &str, sort: Sort) -> Vec<QuestionSummary> {
+        self.sorted(self.matching_questions(&SearchTerm::parse(query)), sort)
+    }
+
+    /// `undecided`, ordered by `sort`.
+    pub fn undecided_sorted(&self, filter: &UndecidedFilter, sort: Sort) -> Vec<QuestionSummary> {
+        self.sorted(self.undecided_questions(self.now(), filter).into_iter().map(|(_, question)| question).collect(), sort)
+    }
+
+    /// Open questions, and deferred ones that are due back, matching
+    /// `filter`. Most urgent first, oldest first within a priority.
+    pub fn undecided(&self, filter: &UndecidedFilter) -> Vec<QuestionSummary> {
+        self.undecided_at(self.now(), filter)
+    }
+
+    /// `undecided` as of `now`. Questions of unknown age never pass a
+    /// `min_age` filter.
+    pub fn undecided_at(&self, now: DateTime<Utc>, filter: &UndecidedFilter) -> Vec<QuestionSummary> {
+        let mut matches = self.undecided_questions(now, filter);
+        matches.sort_by(|(a_created, a), (b_created, b)| b.priority.cmp(&a.priority)
+            .then_with(|| a_created.cmp(b_created))
+            .then_with(|| a.content.cmp(&b.content)));
+        matches.into_iter().map(|(_, question)| question.summary_at(now)).collect()
+    }
+
+    /// The questions `undecided_at` returns, with when each was added.
+    fn undecided_questions(&self, now: DateTime<Utc>, filter: &UndecidedFilter) -> Vec<(Option<DateTime<Utc>>, &Question)> {
+        let tags: Option<Vec<&String>> = filter.tags.iter().map(|tag| self.resolve_tag(tag)).collect();
+        let tags = match tags {
+            Some(tags) => tags,
+            None => return Vec::new()
+        };
+        let candidates: Vec<&Question> = match tags.first() {
+            Some(tag) => self.indexed_questions_with_tag(tag),
+            None => self.questions.values().collect()
+        };
+        candidates.into_iter()
+            .filter(|question| match question.status() {
+                QuestionStatus::Open => true,
+                QuestionStatus::Deferred => question.is_waking_up(now.date_naive()),
+                _ => false
+            })
+            .filter(|question| tags.iter().all(|tag| question.tags.contains(*tag)))
+            .filter(|question| filter.owner.is_none() || question.owner == filter.owner)
+            .filter(|question| filter.min_priority.is_none_or(|priority| question.priority >= priority))
+            .map(|question| (self.created_at(question), question))
+            .filter(|(created, _)| filter.min_age.is_none_or(|age| created.is_some_and(|created| created <= now - age)))
+            .collect()
+    }
+}
+
+/// Compares two keyed questions the way `Registry::keyed` orders them.
+pub(crate) fn compare(sort: Sort, (a_key, a_content, a_identifier): (&SortKey, &str, &Uuid), (b_key, b_content, b_identifier): (&SortKey, &str, &Uuid)) -> Ordering {
+    let primary = match sort.direction {
+        SortDirection::Ascending => a_key.cmp(b_key),
+        SortDirection::Descending => b_key.cmp(a_key)
+    };
+    primary.then_with(|| a_content.cmp(b_content)).then_with(|| a_identifier.cmp(b_identifier))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{Duration, Utc};
+    use crate::*;
+    use crate::priority::Priority;
+    use crate::query::*;
+
+    #[test]
+    fn test_undecided_filters_combine() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut registry = Registry::new();
+        registry.add_tag(&"billing".to_string(), &luke).unwrap();
+        let billing: HashSet<String> = ["billing".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let invoices = registry.add_question(Question::new("Monthly invoices?".to_string(), billing.clone(), HashSet::new(), options.clone()), &luke).unwrap();
+        let refunds = registry.add_question(Question::new("Automatic refunds?".to_string(), billing.clone(), HashSet::new(), options.clone()), &luke).unwrap();
+        let decided = registry.add_question(Question::new("Stripe?".to_string(), billing, HashSet::new(), options), &luke).unwrap();
+        registry.add_question(Question::new("Which CI?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.set_question_decision(decided, Decision::new("Yes".to_string(), "Already integrated".to_string(), HashSet::new()), 0, &luke).unwrap();
+        registry.set_question_owner(refunds.clone(), Some(ana.clone()), 0, &luke).unwrap();
+        registry.set_question_priority(refunds.clone(), Priority::High, 1, &luke).unwrap();
+
+        let project = UndecidedFilter { tags: vec!["billing".to_string()], ..UndecidedFilter::default() };
+        let open: Vec<String> = registry.undecided(&project).into_iter().map(|summary| summary.identifier).collect();
+        assert_eq!(open, vec![refunds.clone(), invoices]);
+        assert_eq!(registry.undecided(&UndecidedFilter::default()).len(), 3);
+
+        let mine = UndecidedFilter { owner: Some(ana), min_priority: Some(Priority::High), ..project.clone() };
+        assert_eq!(registry.undecided(&mine)[0].identifier, refunds);
+        let old = UndecidedFilter { min_age: Some(Duration::days(30)), ..project };
+        assert!(registry.undecided(&old).is_empty());
+        assert_eq!(registry.undecided_at(Utc::now() + Duration::days(31), &old).len(), 2);
+        assert!(registry.undecided(&UndecidedFilter { tags: vec!["nope".to_string()], ..UndecidedFilter::default() }).is_empty());
+    }
+
+    #[test]
+    fn test_sorted_listings() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let infra: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let first = registry.add_question(Question::new("Bump the MSRV?".to_string(), HashSet::new(), HashSet::new(), options.clone()), &luke).unwrap();
+        let second = registry.add_question(Question::new("Archive the wiki?".to_string(), infra, HashSet::new(), options.clone()), &luke).unwrap();
+        let third = registry.add_question(Question::new("Copy the docs?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        registry.set_question_decision(first.clone(), Decision::new("Yes".to_string(), "Needed for let-else".to_string(), HashSet::new()), 0, &luke).unwrap();
+        registry.set_question_priority(third.clone(), Priority::High, 0, &luke).unwrap();
+
+        let order = |sort: Sort| -> Vec<String> { registry.list_questions_sorted(sort).into_iter().map(|summary| summary.identifier).collect() };
+        assert_eq!(order(Sort::default()), vec![second.clone(), first.clone(), third.clone()]);
+        assert_eq!(order(Sort::ascending(SortBy::Created)), vec![first.clone(), second.clone(), third.clone()]);
+        assert_eq!(order(Sort::descending(SortBy::Updated)), vec![third.clone(), first.clone(), second.clone()]);
+        assert_eq!(order(Sort::descending(SortBy::Decided))[0], first);
+        assert_eq!(order(Sort::descending(SortBy::Priority))[0], third);
+        assert_eq!(order(Sort::descending(SortBy::TagCount))[0], second);
+    }
+}
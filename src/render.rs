@@ -0,0 +1,107 @@
+//! Markdown rendering for question content, context and rationales
+//! (feature `markdown`).
+//!
+//! HTML output is safe to embed: raw HTML in the source is escaped rather
+//! than passed through, and links or images using script-capable URL schemes
+//! lose their target.
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use serde::Serialize;
+use crate::Question;
+use crate::messages::Catalog;
+
+const UNSAFE_SCHEMES: &[&str] = &["javascript:", "vbscript:", "data:"];
+
+fn is_unsafe(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    UNSAFE_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+fn sanitize(event: Event) -> Event {
+    match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::Start(Tag::Link { link_type, dest_url, title, id }) if is_unsafe(&dest_url) =>
+            Event::Start(Tag::Link { link_type, dest_url: CowStr::Borrowed(""), title, id }),
+        Event::Start(Tag::Image { link_type, dest_url, title, id }) if is_unsafe(&dest_url) =>
+            Event::Start(Tag::Image { link_type, dest_url: CowStr::Borrowed(""), title, id }),
+        event => event
+    }
+}
+
+fn parser(markdown: &str) -> Parser<'_> {
+    Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH)
+}
+
+pub fn to_html(markdown: &str) -> String {
+    let mut output = String::new();
+    html::push_html(&mut output, parser(markdown).map(sanitize));
+    output
+}
+
+/// The text without any markup, one line per block.
+pub fn to_plain_text(markdown: &str) -> String {
+    let mut output = String::new();
+    for event in parser(markdown) {
+        match event {
+            Event::Text(text) | Event::Code(text) | Event::Html(text) | Event::InlineHtml(text) => output.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => output.push(' '),
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Heading(_)) | Event::End(TagEnd::Item)
+            | Event::End(TagEnd::CodeBlock) | Event::End(TagEnd::TableRow) => output.push('\n'),
+            _ => ()
+        }
+    }
+    output.trim_end().to_string()
+}
+
+/// The plain text on a single line, cut to at most `max_chars` characters
+/// (ending in an ellipsis when cut).
+pub fn summary(markdown: &str, max_chars: usize) -> String {
+    let text = to_plain_text(markdown).split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+    let mut cut: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RenderedQuestion {
+    pub content_html: String,
+    pub content_summary: String,
+    /// In the order the context was added; retracted items are struck through.
+    pub context_html: Vec<String>,
+    pub rationale_html: Option<String>
+}
+
+const SUMMARY_LENGTH: usize = 120;
+
+/// Retraction notes on context are in the catalog's language.
+pub fn question(question: &Question, catalog: &dyn Catalog) -> RenderedQuestion {
+    RenderedQuestion {
+        content_html: to_html(&question.content),
+        content_summary: summary(&question.content, SUMMARY_LENGTH),
+        context_html: question.context.iter().map(|item| to_html(&item.to_markdown(catalog))).collect(),
+        rationale_html: question.decision.as_ref().map(|decision| to_html(&decision.rationale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::render::*;
+
+    #[test]
+    fn test_html_is_sanitized() {
+        let html = to_html("Use **Postgres** <script>alert(1)</script> [docs](javascript:alert(1))");
+        assert!(html.contains("<strong>Postgres</strong>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(!html.contains("javascript:"));
+    }
+
+    #[test]
+    fn test_plain_text_and_summary() {
+        let markdown = "# Queue\n\nWe need *ordering* and `at-least-once` delivery.\n\n- Kafka\n- RabbitMQ";
+        assert_eq!(to_plain_text(markdown), "Queue\nWe need ordering and at-least-once delivery.\nKafka\nRabbitMQ");
+        assert_eq!(summary(markdown, 14), "Queue We need…");
+    }
+}
@@ -0,0 +1,341 @@
+//! Text search over questions.
+//!
+//! A query is a list of terms, all of which must match. A bare term matches
+//! anywhere in a question; a qualified one such as `rationale:latency` or
+//! `tag:infra` only in that field. Double quotes group words into one term,
+//! as in `context:"cold start"`. Matching ignores case.
+//!
+//! By default a term matches any text containing it. A registry can switch
+//! to matching whole words instead, see `SearchAnalysis`. Text is then split
+//! into words, common words can be dropped, and words can be cut to their
+//! stems, so "migrations" finds "migration".
+
+use std::ops::Range;
+use serde::{Serialize, Deserialize};
+use crate::{Actor, Question, QuestionSummary, Registry};
+use crate::audit::AuditAction;
+
+/// Words an English query rarely means to look for.
+pub const ENGLISH_STOPWORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "so", "such", "that", "the", "their", "then", "there", "these", "they",
+    "this", "to", "with"
+];
+
+/// How questions and queries are read for search. The default matches
+/// substrings, as search always has.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchAnalysis {
+    /// Match whole words, split at anything that isn't a letter or digit.
+    /// The other two settings only apply when this is set.
+    pub tokenize: bool,
+    /// Compare English stems, so plurals and -ing/-ed forms match.
+    #[serde(default)]
+    pub stemming: bool,
+    /// Lowercase words left out of both text and queries. A term made only
+    /// of stopwords matches everything.
+    #[serde(default)]
+    pub stopwords: Vec<String>
+}
+
+impl SearchAnalysis {
+    /// Words, stems and the English stopwords.
+    pub fn english() -> SearchAnalysis {
+        SearchAnalysis { tokenize: true, stemming: true, stopwords: ENGLISH_STOPWORDS.iter().map(|word| word.to_string()).collect() }
+    }
+
+    /// The words of `text` that count, with where they are in it.
+    pub(crate) fn words(&self, text: &str) -> Vec<(Range<usize>, String)> {
+        let mut words = Vec::new();
+        let mut start = None;
+        for (offset, character) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+            match (start, character.is_alphanumeric()) {
+                (None, true) => start = Some(offset),
+                (Some(from), false) => {
+                    words.push((from..offset, text[from..offset].to_lowercase()));
+                    start = None;
+                }
+                _ => ()
+            }
+        }
+        words.into_iter()
+            .filter(|(_, word)| !self.stopwords.contains(word))
+            .map(|(range, word)| (range, if self.stemming { stem(&word) } else { word }))
+            .collect()
+    }
+
+    /// Where `needle` occurs in `text`, or `None` if it has nothing to look
+    /// for once stopwords are dropped.
+    pub(crate) fn find(&self, text: &str, needle: &str) -> Option<Vec<Range<usize>>> {
+        if !self.tokenize {
+            return Some(find_matches(text, needle));
+        }
+        let needle: Vec<String> = self.words(needle).into_iter().map(|(_, word)| word).collect();
+        if needle.is_empty() {
+            return None;
+        }
+        let words = self.words(text);
+        let mut matches = Vec::new();
+        let mut from = 0;
+        while from + needle.len() <= words.len() {
+            if words[from..from + needle.len()].iter().map(|(_, word)| word).eq(needle.iter()) {
+                matches.push(words[from].0.start..words[from + needle.len() - 1].0.end);
+                from += needle.len();
+            } else {
+                from += 1;
+            }
+        }
+        Some(matches)
+    }
+}
+
+/// A light English stemmer: drops plural and -ing/-ed/-ly endings and a
+/// final "e". "migrations" becomes "migration", and "migrating",
+/// "migrated" and "migrate" all become "migrat".
+fn stem(word: &str) -> String {
+    let mut word = word.to_string();
+    if word.ends_with("ies") && word.len() > 4 {
+        word.truncate(word.len() - 3);
+        word.push('y');
+    } else if word.ends_with("sses") {
+        word.truncate(word.len() - 2);
+    } else if word.ends_with('s') && !["ss", "us", "is"].iter().any(|ending| word.ends_with(ending)) && word.len() > 3 {
+        word.pop();
+    }
+    for ending in &["ing", "ed", "ly"] {
+        if word.ends_with(ending) && word.chars().count() >= ending.len() + 3 {
+            word.truncate(word.len() - ending.len());
+            // "running" -> "runn" -> "run".
+            let mut last = word.chars().rev();
+            if let (Some(a), Some(b)) = (last.next(), last.next()) {
+                if a == b && !"aeioulsz".contains(a) {
+                    word.pop();
+                }
+            }
+            break;
+        }
+    }
+    if word.ends_with('e') && word.chars().count() > 3 {
+        word.pop();
+    }
+    word
+}
+
+/// Where `needle` occurs in `text`, ignoring case.
+pub(crate) fn find_matches(text: &str, needle: &str) -> Vec<Range<usize>> {
+    let needle: Vec<char> = needle.chars().flat_map(char::to_lowercase).collect();
+    let mut matches = Vec::new();
+    if needle.is_empty() {
+        return matches;
+    }
+    let mut from = 0;
+    for (start, _) in text.char_indices() {
+        if start < from {
+            continue;
+        }
+        let mut lowered: Vec<char> = Vec::new();
+        let mut end = start;
+        for character in text[start..].chars() {
+            if lowered.len() >= needle.len() {
+                break;
+            }
+            lowered.extend(character.to_lowercase());
+            end += character.len_utf8();
+        }
+        if lowered.len() >= needle.len() && lowered[..needle.len()] == needle[..] {
+            matches.push(start..end);
+            from = end;
+        }
+    }
+    matches
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum SearchField {
+    Content,
+    Context,
+    Option,
+    /// Rationales of the current and any earlier decisions.
+    Rationale,
+    Comment,
+    /// Matches whole tag names, under the registry's normalization.
+    Tag
+}
+
+impl SearchField {
+    fn from_qualifier(qualifier: &str) -> Option<SearchField> {
+        match qualifier {
+            "content" => Some(SearchField::Content),
+            "context" => Some(SearchField::Context),
+            "option" => Some(SearchField::Option),
+            "rationale" => Some(SearchField::Rationale),
+            "comment" => Some(SearchField::Comment),
+            "tag" => Some(SearchField::Tag),
+            _ => None
+        }
+    }
+
+    pub(crate) const TEXT: [SearchField; 5] = [SearchField::Content, SearchField::Context, SearchField::Option, SearchField::Rationale, SearchField::Comment];
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchTerm {
+    /// `None` searches every text field.
+    pub field: Option<SearchField>,
+    pub text: String
+}
+
+impl SearchTerm {
+    /// Splits a query into terms. Unknown qualifiers are kept as plain text.
+    pub fn parse(query: &str) -> Vec<SearchTerm> {
+        let mut words: Vec<String> = Vec::new();
+        let mut current = String::new();
+        let mut quoted = false;
+        for character in query.chars() {
+            match character {
+                '"' => quoted = !quoted,
+                character if character.is_whitespace() && !quoted => {
+                    if !current.is_empty() {
+                        words.push(std::mem::take(&mut current));
+                    }
+                }
+                character => current.push(character)
+            }
+        }
+        if !current.is_empty() {
+            words.push(current);
+        }
+        words.into_iter().map(|word| match word.split_once(':') {
+            Some((qualifier, text)) if !text.is_empty() => match SearchField::from_qualifier(&qualifier.to_lowercase()) {
+                Some(field) => SearchTerm { field: Some(field), text: text.to_string() },
+                None => SearchTerm { field: None, text: word }
+            },
+            _ => SearchTerm { field: None, text: word }
+        }).collect()
+    }
+}
+
+impl Question {
+    pub(crate) fn field_texts(&self, field: SearchField) -> Vec<&str> {
+        match field {
+            SearchField::Content => vec![self.content.as_str()],
+            SearchField::Context => self.context.iter().map(|item| item.text.as_str()).collect(),
+            SearchField::Option => self.options.iter().map(String::as_str).collect(),
+            SearchField::Rationale => self.decision.iter()
+                .chain(self.decision_history.iter().map(|past| &past.decision))
+                .map(|decision| decision.rationale.as_str())
+                .collect(),
+            SearchField::Comment => self.comments.iter().map(|comment| comment.body.as_str()).collect(),
+            SearchField::Tag => self.tags.iter().map(String::as_str).collect()
+        }
+    }
+}
+
+impl Registry {
+    pub fn search_analysis(&self) -> &SearchAnalysis {
+        &self.settings.search
+    }
+
+    pub fn set_search_analysis(&mut self, analysis: SearchAnalysis, actor: &Actor) {
+        self.settings.search = analysis;
+        self.audit_log.record(self.now(), actor, None, AuditAction::SettingsChanged);
+    }
+
+    fn term_matches(&self, question: &Question, term: &SearchTerm) -> bool {
+        let analysis = &self.settings.search;
+        let found = |text: &&str| analysis.find(text, &term.text).is_none_or(|matches| !matches.is_empty());
+        match term.field {
+            Some(SearchField::Tag) => self.resolve_tag(&term.text).is_some_and(|tag| question.tags.contains(tag)),
+            Some(field) => question.field_texts(field).iter().any(found),
+            None => SearchField::TEXT.iter().any(|field| question.field_texts(*field).iter().any(found))
+        }
+    }
+
+    /// Questions matching every term of `query`, ordered by content.
+    pub fn search(&self, query: &str) -> Vec<QuestionSummary> {
+        self.search_terms(&SearchTerm::parse(query))
+    }
+
+    /// Like `search`, but unqualified terms only match decision rationales.
+    pub fn search_rationales(&self, query: &str) -> Vec<QuestionSummary> {
+        let terms: Vec<SearchTerm> = SearchTerm::parse(query).into_iter()
+            .map(|term| SearchTerm { field: term.field.or(Some(SearchField::Rationale)), text: term.text })
+            .collect();
+        self.search_terms(&terms)
+    }
+
+    fn search_terms(&self, terms: &[SearchTerm]) -> Vec<QuestionSummary> {
+        let mut matches = self.matching_questions(terms);
+        matches.sort_by(|a, b| a.content.cmp(&b.content));
+        let now = self.now();
+        matches.into_iter().map(|question| question.summary_at(now)).collect()
+    }
+
+    pub(crate) fn matching_questions(&self, terms: &[SearchTerm]) -> Vec<&Question> {
+        self.questions.values()
+            .filter(|question| terms.iter().all(|term| self.term_matches(question, term)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::search::*;
+
+    #[test]
+    fn test_queries_parse_into_terms() {
+        assert_eq!(SearchTerm::parse("rationale:latency  \"cold start\" url:http://x"), vec![
+            SearchTerm { field: Some(SearchField::Rationale), text: "latency".to_string() },
+            SearchTerm { field: None, text: "cold start".to_string() },
+            SearchTerm { field: None, text: "url:http://x".to_string() }
+        ]);
+    }
+
+    #[test]
+    fn test_find_matches_ignores_case_and_keeps_offsets() {
+        assert_eq!(find_matches("Ünïcode and ÜNÏCODE", "ünï"), vec![0..5, 14..19]);
+        assert!(find_matches("short", "").is_empty());
+    }
+
+    #[test]
+    fn test_analysis_matches_words_and_stems() {
+        let english = SearchAnalysis::english();
+        assert_eq!(["migrations", "migrating", "migrate", "studies", "running"].iter().map(|word| stem(word)).collect::<Vec<_>>(),
+            vec!["migration", "migrat", "migrat", "study", "run"]);
+        assert_eq!(english.find("Plan the database migrations", "migration"), Some(vec![Range { start: 18, end: 28 }]));
+        assert_eq!(english.find("The cost of the move", "cost of move"), Some(vec![Range { start: 4, end: 20 }]));
+        assert_eq!(english.find("Cachet is a brand", "cache"), Some(vec![]));
+        assert_eq!(english.find("Anything", "the"), None);
+
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_question(Question::new("When do we run the migration?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        assert!(registry.search("migrations").is_empty());
+        registry.set_search_analysis(english, &luke);
+        assert_eq!(registry.search("migrations").len(), 1);
+        assert_eq!(registry.search("\"the migrations\" running").len(), 1);
+        assert!(registry.search("grat").is_empty());
+    }
+
+    #[test]
+    fn test_search_by_field() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let tags: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Redis".to_string(), "Postgres".to_string()].iter().cloned().collect();
+        let cache = registry.add_question(Question::new("Which cache?".to_string(), tags.clone(), HashSet::new(), options.clone()), &luke).unwrap();
+        let queue = registry.add_question(Question::new("Which queue? Latency matters".to_string(), tags, HashSet::new(), options), &luke).unwrap();
+        registry.set_question_decision(cache.clone(), Decision::new("Redis".to_string(), "Lowest latency".to_string(), HashSet::new()), 0, &luke).unwrap();
+        registry.set_question_decision(queue, Decision::new("Postgres".to_string(), "Fewer moving parts".to_string(), HashSet::new()), 0, &luke).unwrap();
+
+        assert_eq!(registry.search("latency").len(), 2);
+        let cited = registry.search("rationale:LATENCY tag:infra");
+        assert_eq!(cited.len(), 1);
+        assert_eq!(cited[0].identifier, cache);
+        assert_eq!(registry.search_rationales("latency")[0].identifier, cache);
+        assert!(registry.search("tag:security").is_empty());
+    }
+}
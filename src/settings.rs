@@ -0,0 +1,15 @@
+//! Registry-wide configuration, persisted with the registry.
+
+use serde::{Serialize, Deserialize};
+use crate::search::SearchAnalysis;
+use crate::tags::{TagNormalization, TagRules};
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(default)]
+    pub tag_normalization: TagNormalization,
+    #[serde(default)]
+    pub tag_rules: TagRules,
+    #[serde(default)]
+    pub search: SearchAnalysis
+}
@@ -0,0 +1,217 @@
+//! Monte Carlo simulation over the estimated outcomes of a question's options.
+//!
+//! Each option can carry a distribution per metric (say "cost" or "weeks"),
+//! set with `Registry::set_option_outcome`. `simulate` samples every one of
+//! them and summarises the results as percentiles, which `describe` renders
+//! as text suitable for adding to the question's context.
+
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use crate::Question;
+use crate::tiebreak::SplitMix64;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Distribution {
+    Fixed(f64),
+    Uniform { low: f64, high: f64 },
+    /// A three-point estimate: best case, most likely and worst case.
+    Triangular { low: f64, mode: f64, high: f64 }
+}
+
+impl Distribution {
+    fn is_valid(&self) -> bool {
+        match *self {
+            Distribution::Fixed(value) => value.is_finite(),
+            Distribution::Uniform { low, high } => low.is_finite() && high.is_finite() && low <= high,
+            Distribution::Triangular { low, mode, high } =>
+                low.is_finite() && high.is_finite() && low <= mode && mode <= high
+        }
+    }
+
+    fn sample(&self, rng: &mut SplitMix64) -> f64 {
+        match *self {
+            Distribution::Fixed(value) => value,
+            Distribution::Uniform { low, high } => low + rng.next_f64() * (high - low),
+            Distribution::Triangular { low, mode, high } => {
+                if high == low {
+                    return low;
+                }
+                let u = rng.next_f64();
+                if u < (mode - low) / (high - low) {
+                    low + (u * (high - low) * (mode - low)).sqrt()
+                } else {
+                    high - ((1.0 - u) * (high - low) * (high - mode)).sqrt()
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OutcomeError {
+    UnknownOption(String),
+    InvalidDistribution
+}
+
+impl Localized for OutcomeError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            OutcomeError::UnknownOption(option) => messages::text(catalog, "option.unknown", &[("option", option.clone())]),
+            OutcomeError::InvalidDistribution => messages::text(catalog, "outcome.invalid-distribution", &[])
+        }
+    }
+}
+
+impl Coded for OutcomeError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            OutcomeError::UnknownOption(_) => ErrorCode::UnknownOption,
+            OutcomeError::InvalidDistribution => ErrorCode::InvalidDistribution
+        }
+    }
+}
+
+messages::display_localized!(OutcomeError);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub min: f64,
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub max: f64,
+    pub mean: f64
+}
+
+impl Percentiles {
+    fn from_samples(mut samples: Vec<f64>) -> Percentiles {
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let at = |fraction: f64| samples[((samples.len() - 1) as f64 * fraction).round() as usize];
+        Percentiles {
+            min: samples[0],
+            p10: at(0.1),
+            p50: at(0.5),
+            p90: at(0.9),
+            max: samples[samples.len() - 1],
+            mean: samples.iter().sum::<f64>() / samples.len() as f64
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SimulationReport {
+    pub iterations: usize,
+    pub seed: u64,
+    /// Option -> metric -> summary. Options without outcomes are left out.
+    pub options: BTreeMap<String, BTreeMap<String, Percentiles>>
+}
+
+impl SimulationReport {
+    /// One line per option and metric in the catalog's language, e.g.
+    /// `Postgres / cost: p10 10.5, p50 12.0, p90 14.2 (mean 12.1)`.
+    pub fn describe(&self, catalog: &dyn Catalog) -> String {
+        let mut lines = vec![messages::text(catalog, "simulate.summary", &[("iterations", self.iterations.to_string()), ("seed", self.seed.to_string())])];
+        for (option, metrics) in &self.options {
+            for (metric, summary) in metrics {
+                lines.push(messages::text(catalog, "simulate.metric", &[
+                    ("option", option.clone()),
+                    ("metric", metric.clone()),
+                    ("p10", format!("{:.1}", summary.p10)),
+                    ("p50", format!("{:.1}", summary.p50)),
+                    ("p90", format!("{:.1}", summary.p90)),
+                    ("mean", format!("{:.1}", summary.mean))
+                ]));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl Question {
+    /// Sets the distribution of one metric for an existing option, replacing
+    /// any previous estimate.
+    pub fn set_outcome(&mut self, option: &str, metric: &str, distribution: Distribution) -> Result<(), OutcomeError> {
+        if !self.has_option(option) {
+            return Result::Err(OutcomeError::UnknownOption(option.to_string()));
+        }
+        if !distribution.is_valid() {
+            return Result::Err(OutcomeError::InvalidDistribution);
+        }
+        self.outcomes.entry(option.to_string()).or_default().insert(metric.to_string(), distribution);
+        Result::Ok(())
+    }
+
+    pub fn get_outcomes(&self, option: &str) -> BTreeMap<String, Distribution> {
+        self.outcomes.get(option).cloned().unwrap_or_default()
+    }
+}
+
+/// Samples every option's outcomes `iterations` times (at least once). The
+/// same seed always gives the same report.
+pub fn simulate(question: &Question, iterations: usize, seed: u64) -> SimulationReport {
+    let iterations = iterations.max(1);
+    let mut rng = SplitMix64(seed);
+    let mut options = BTreeMap::new();
+    // BTreeMaps keep the sampling order, and so the results, stable.
+    for (option, metrics) in &question.outcomes {
+        let mut summaries = BTreeMap::new();
+        for (metric, distribution) in metrics {
+            let samples = (0..iterations).map(|_| distribution.sample(&mut rng)).collect();
+            summaries.insert(metric.clone(), Percentiles::from_samples(samples));
+        }
+        options.insert(option.clone(), summaries);
+    }
+    SimulationReport { iterations, seed, options }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::simulate::*;
+
+    fn question() -> Question {
+        let mut options: HashSet<String> = HashSet::new();
+        options.insert("Postgres".to_string());
+        options.insert("Redis".to_string());
+        Question::new("Which store?".to_string(), HashSet::new(), HashSet::new(), options)
+    }
+
+    #[test]
+    fn test_simulation_is_reproducible_and_bounded() {
+        let mut question = question();
+        question.set_outcome("Postgres", "cost", Distribution::Triangular { low: 10.0, mode: 12.0, high: 20.0 }).unwrap();
+        question.set_outcome("Redis", "cost", Distribution::Uniform { low: 5.0, high: 6.0 }).unwrap();
+        question.set_outcome("Redis", "weeks", Distribution::Fixed(2.0)).unwrap();
+
+        let report = simulate(&question, 2000, 3);
+        let postgres = &report.options["Postgres"]["cost"];
+        assert!(postgres.min >= 10.0 && postgres.max <= 20.0);
+        assert!(postgres.p10 <= postgres.p50 && postgres.p50 <= postgres.p90);
+        assert_eq!(report.options["Redis"]["weeks"].p50, 2.0);
+        assert_eq!(simulate(&question, 2000, 3).options, report.options);
+        assert!(report.describe(&messages::English).contains("Redis / weeks: p10 2.0"));
+    }
+
+    #[test]
+    fn test_outcomes_need_a_known_option_and_a_valid_range() {
+        let mut question = question();
+        match question.set_outcome("SQLite", "cost", Distribution::Fixed(1.0)) {
+            Err(simulate::OutcomeError::UnknownOption(option)) => assert_eq!(option, "SQLite"),
+            _ => panic!("Outcomes for unknown options should be rejected")
+        }
+        assert!(question.set_outcome("Redis", "cost", Distribution::Uniform { low: 2.0, high: 1.0 }).is_err());
+    }
+
+    #[test]
+    fn test_registry_records_outcomes() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let identifier = registry.add_question(question(), &luke).unwrap();
+        let version = registry.set_option_outcome(identifier.clone(), "Redis", "cost", Distribution::Fixed(4.0), 0, &luke).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(registry.get_question(identifier).unwrap().get_outcomes("Redis")["cost"], Distribution::Fixed(4.0));
+    }
+}
@@ -0,0 +1,176 @@
+//! Excerpts showing where a search matched.
+//!
+//! Each hit carries the snippets of text its terms matched in, with the
+//! byte ranges of the matches, so a caller can show why a question came up
+//! without matching again itself. `highlight_ansi` and `highlight_html`
+//! render a snippet for a terminal or a web page.
+
+use std::ops::Range;
+use serde::Serialize;
+use crate::{Question, QuestionSummary, Registry};
+use crate::search::{SearchField, SearchTerm};
+
+/// Characters of text kept around the matches.
+const CONTEXT: usize = 40;
+/// Matches starting this many characters or more after the first are left
+/// out of its snippet.
+const MAX_SPAN: usize = 120;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Snippet {
+    pub field: SearchField,
+    pub text: String,
+    /// Byte ranges into `text`, in order and not overlapping.
+    pub matches: Vec<Range<usize>>,
+    /// Whether `text` was cut from a longer one at its start or its end.
+    pub elided_before: bool,
+    pub elided_after: bool
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchHit {
+    pub question: QuestionSummary,
+    pub snippets: Vec<Snippet>
+}
+
+/// The byte offset `count` characters before or after `at`, stopping at the
+/// ends of `text`.
+fn step(text: &str, at: usize, count: usize, forward: bool) -> usize {
+    if forward {
+        text[at..].char_indices().nth(count).map_or(text.len(), |(offset, _)| at + offset)
+    } else {
+        text[..at].char_indices().rev().nth(count.saturating_sub(1)).map_or(0, |(offset, _)| offset)
+    }
+}
+
+impl Snippet {
+    /// A snippet around `matches` in `text`, which must be sorted.
+    fn around(field: SearchField, text: &str, matches: Vec<Range<usize>>) -> Snippet {
+        let first = matches[0].start;
+        let limit = step(text, first, MAX_SPAN, true);
+        let matches: Vec<Range<usize>> = matches.into_iter().filter(|range| range.start < limit).collect();
+        let start = step(text, first, CONTEXT, false);
+        let end = step(text, matches.last().expect("at least one match").end, CONTEXT, true);
+        Snippet {
+            field,
+            text: text[start..end].to_string(),
+            matches: matches.into_iter().map(|range| range.start - start..range.end - start).collect(),
+            elided_before: start > 0,
+            elided_after: end < text.len()
+        }
+    }
+
+    fn render<F: Fn(&str) -> String>(&self, escape: F, open: &str, close: &str) -> String {
+        let mut rendered = String::new();
+        if self.elided_before {
+            rendered.push('…');
+        }
+        let mut at = 0;
+        for range in &self.matches {
+            rendered.push_str(&escape(&self.text[at..range.start]));
+            rendered.push_str(open);
+            rendered.push_str(&escape(&self.text[range.clone()]));
+            rendered.push_str(close);
+            at = range.end;
+        }
+        rendered.push_str(&escape(&self.text[at..]));
+        if self.elided_after {
+            rendered.push('…');
+        }
+        rendered
+    }
+
+    /// The snippet with its matches in bold, for a terminal.
+    pub fn highlight_ansi(&self) -> String {
+        self.render(str::to_string, "\u{1b}[1m", "\u{1b}[0m")
+    }
+
+    /// The snippet escaped for HTML, with its matches in `<mark>`.
+    pub fn highlight_html(&self) -> String {
+        let escape = |text: &str| text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+        self.render(escape, "<mark>", "</mark>")
+    }
+}
+
+impl Registry {
+    /// Like `search`, with a snippet for every text a term matched in.
+    pub fn search_with_snippets(&self, query: &str) -> Vec<SearchHit> {
+        let terms = SearchTerm::parse(query);
+        let mut matches = self.matching_questions(&terms);
+        matches.sort_by(|a, b| a.content.cmp(&b.content));
+        let now = self.now();
+        matches.into_iter()
+            .map(|question| SearchHit { question: question.summary_at(now), snippets: self.snippets(question, &terms) })
+            .collect()
+    }
+
+    fn snippets(&self, question: &Question, terms: &[SearchTerm]) -> Vec<Snippet> {
+        let mut snippets = Vec::new();
+        for field in SearchField::TEXT.iter().chain(std::iter::once(&SearchField::Tag)) {
+            let terms: Vec<&SearchTerm> = terms.iter()
+                .filter(|term| match term.field {
+                    Some(only) => only == *field,
+                    None => *field != SearchField::Tag
+                })
+                .collect();
+            for text in question.field_texts(*field) {
+                let mut found: Vec<Range<usize>> = if *field == SearchField::Tag {
+                    terms.iter()
+                        .filter(|term| self.resolve_tag(&term.text).is_some_and(|tag| tag == text))
+                        .map(|_| 0..text.len())
+                        .collect()
+                } else {
+                    terms.iter().flat_map(|term| self.settings.search.find(text, &term.text).unwrap_or_default()).collect()
+                };
+                if found.is_empty() {
+                    continue;
+                }
+                found.sort_by_key(|range| (range.start, range.end));
+                let mut merged: Vec<Range<usize>> = Vec::new();
+                for range in found {
+                    match merged.last_mut() {
+                        Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                        _ => merged.push(range)
+                    }
+                }
+                snippets.push(Snippet::around(*field, text, merged));
+            }
+        }
+        snippets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::search::SearchField;
+
+    #[test]
+    fn test_hits_carry_highlighted_snippets() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let tags: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        let long = format!("{} latency <p99> matters {}", "x".repeat(60), "y".repeat(60));
+        registry.add_question(Question::new("Which cache? Latency first".to_string(), tags, HashSet::new(), HashSet::new()), &luke).unwrap();
+        let context: HashSet<String> = [long].iter().cloned().collect();
+        registry.add_question(Question::new("Which queue?".to_string(), HashSet::new(), context, HashSet::new()), &luke).unwrap();
+
+        let hits = registry.search_with_snippets("latency");
+        assert_eq!(hits.len(), 2);
+        let cache = &hits[0].snippets;
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache[0].highlight_ansi(), "Which cache? \u{1b}[1mLatency\u{1b}[0m first");
+
+        let queue = &hits[1].snippets[0];
+        assert_eq!(queue.field, SearchField::Context);
+        assert!(queue.elided_before && queue.elided_after);
+        assert_eq!(&queue.text[queue.matches[0].clone()], "latency");
+        assert!(queue.highlight_html().contains("<mark>latency</mark> &lt;p99&gt;"));
+        assert!(queue.highlight_html().starts_with('…'));
+
+        let tagged = registry.search_with_snippets("tag:infra first");
+        assert_eq!(tagged[0].snippets.iter().map(|snippet| snippet.field).collect::<Vec<_>>(), vec![SearchField::Content, SearchField::Tag]);
+    }
+}
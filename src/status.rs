@@ -0,0 +1,397 @@
+//! Where a question stands: open, decided, deferred or cancelled.
+//!
+//! `Decided` follows from the question having a decision; the other states
+//! are kept on the question and changed with `Registry::defer_question`,
+//! `Registry::cancel_question`, `Registry::reopen_question` and
+//! `Registry::set_question_status`.
+//!
+//! Decisions can be temporary: one past its `expires_at` still stands, so
+//! the question stays `Decided`, but it is flagged as expired in summaries
+//! and listed by `Registry::expired_decisions` until it is reopened.
+//!
+//! Deferred questions are left out of the default listings until their
+//! wake-up date, so they resurface instead of being forgotten.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Serialize, Deserialize};
+use crate::{Actor, Decision, GetQuestionError, Question, QuestionSummary, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum QuestionStatus {
+    #[default]
+    Open,
+    Decided,
+    /// Put aside for now, to be picked up again.
+    Deferred,
+    /// Withdrawn, but kept for the record.
+    Cancelled
+}
+
+impl Localized for QuestionStatus {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        let key = match self {
+            QuestionStatus::Open => "status.open",
+            QuestionStatus::Decided => "status.decided",
+            QuestionStatus::Deferred => "status.deferred",
+            QuestionStatus::Cancelled => "status.cancelled"
+        };
+        messages::text(catalog, key, &[])
+    }
+}
+
+impl QuestionStatus {
+    /// Open questions can be decided, deferred or cancelled, and deferred ones
+    /// resumed or cancelled. A decided question has to be reopened before it
+    /// can be anything else, and cancelling is final.
+    pub fn can_become(self, next: QuestionStatus) -> bool {
+        use QuestionStatus::*;
+        matches!((self, next), (Open, Decided) | (Open, Deferred) | (Open, Cancelled) | (Deferred, Open) | (Deferred, Cancelled) | (Decided, Open))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Deferral {
+    pub reason: String,
+    /// When to pick the question up again; `None` means no set date.
+    pub until: Option<NaiveDate>,
+    pub by: Actor,
+    pub at: DateTime<Utc>
+}
+
+/// Why a question was withdrawn. Cancelled questions stay in the registry.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cancellation {
+    pub reason: String,
+    pub by: Actor,
+    pub at: DateTime<Utc>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecisionEnding {
+    Reopened
+}
+
+/// A decision that no longer stands, and why.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PastDecision {
+    pub decision: Decision,
+    pub ending: DecisionEnding,
+    pub reason: String,
+    pub by: Actor,
+    pub at: DateTime<Utc>
+}
+
+#[derive(Debug)]
+pub enum SetStatusError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    InvalidTransition { from: QuestionStatus, to: QuestionStatus },
+    /// A question becomes `Decided` by setting its decision.
+    UseSetDecision,
+    /// Deferring needs a reason, see `Registry::defer_question`.
+    UseDeferQuestion,
+    /// Cancelling needs a reason, see `Registry::cancel_question`.
+    UseCancelQuestion,
+    /// Reopening needs a reason, see `Registry::reopen_question`.
+    UseReopenQuestion,
+    /// The reason was blank.
+    ReasonRequired
+}
+
+impl Localized for SetStatusError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            SetStatusError::Question(error) => error.localize(catalog),
+            SetStatusError::Conflict(question) => messages::conflict(catalog, question),
+            SetStatusError::InvalidTransition { from, to } => {
+                messages::text(catalog, "status.invalid-transition", &[("from", from.localize(catalog)), ("to", to.localize(catalog))])
+            }
+            SetStatusError::UseSetDecision => messages::text(catalog, "status.use-set-decision", &[]),
+            SetStatusError::UseDeferQuestion => messages::text(catalog, "status.use-defer-question", &[]),
+            SetStatusError::UseCancelQuestion => messages::text(catalog, "status.use-cancel-question", &[]),
+            SetStatusError::UseReopenQuestion => messages::text(catalog, "status.use-reopen-question", &[]),
+            SetStatusError::ReasonRequired => messages::text(catalog, "status.reason-required", &[])
+        }
+    }
+}
+
+impl Coded for SetStatusError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SetStatusError::Question(error) => error.code(),
+            SetStatusError::Conflict(_) => ErrorCode::VersionConflict,
+            SetStatusError::InvalidTransition { .. } => ErrorCode::InvalidTransition,
+            SetStatusError::UseSetDecision => ErrorCode::UseSetDecision,
+            SetStatusError::UseDeferQuestion => ErrorCode::UseDeferQuestion,
+            SetStatusError::UseCancelQuestion => ErrorCode::UseCancelQuestion,
+            SetStatusError::UseReopenQuestion => ErrorCode::UseReopenQuestion,
+            SetStatusError::ReasonRequired => ErrorCode::ReasonRequired
+        }
+    }
+}
+
+messages::display_localized!(QuestionStatus, SetStatusError);
+
+impl From<UpdateQuestionError> for SetStatusError {
+    fn from(error: UpdateQuestionError) -> SetStatusError {
+        match error {
+            UpdateQuestionError::Question(error) => SetStatusError::Question(error),
+            UpdateQuestionError::Conflict(current) => SetStatusError::Conflict(current)
+        }
+    }
+}
+
+impl Question {
+    pub fn status(&self) -> QuestionStatus {
+        if self.decision.is_some() { QuestionStatus::Decided } else { self.status }
+    }
+
+    /// Why and until when the question is deferred, while it is.
+    pub fn get_deferral(&self) -> Option<&Deferral> {
+        self.deferral.as_ref()
+    }
+
+    /// Deferred with a wake-up date on or before `as_of`.
+    pub fn is_waking_up(&self, as_of: NaiveDate) -> bool {
+        self.status() == QuestionStatus::Deferred
+            && self.deferral.as_ref().and_then(|deferral| deferral.until).is_some_and(|until| until <= as_of)
+    }
+
+    pub fn get_cancellation(&self) -> Option<&Cancellation> {
+        self.cancellation.as_ref()
+    }
+
+    /// Earlier decisions, oldest first.
+    pub fn decision_history(&self) -> &[PastDecision] {
+        &self.decision_history
+    }
+
+    /// Whether the question shows up in listings that don't ask for a status.
+    pub(crate) fn is_listed_by_default(&self, today: NaiveDate) -> bool {
+        self.status() != QuestionStatus::Deferred || self.is_waking_up(today)
+    }
+}
+
+impl Registry {
+    /// Resumes a deferred question by moving it back to `Open`, and returns
+    /// the question's new version. The other statuses have their own methods.
+    pub fn set_question_status(&mut self, identifier: String, status: QuestionStatus, expected_version: u64, actor: &Actor) -> Result<u64, SetStatusError> {
+        match status {
+            QuestionStatus::Decided => return Result::Err(SetStatusError::UseSetDecision),
+            QuestionStatus::Deferred => return Result::Err(SetStatusError::UseDeferQuestion),
+            QuestionStatus::Cancelled => return Result::Err(SetStatusError::UseCancelQuestion),
+            QuestionStatus::Open => ()
+        }
+        self.change_status(identifier, status, expected_version, actor, AuditAction::StatusChanged(status), |question| {
+            if question.decision.is_some() {
+                return Result::Err(SetStatusError::UseReopenQuestion);
+            }
+            question.deferral = None;
+            Result::Ok(())
+        })
+    }
+
+    /// Sets the question aside with a reason and, optionally, the date to pick
+    /// it up again. Resume it early with `set_question_status(.., Open, ..)`.
+    pub fn defer_question(&mut self, identifier: String, reason: String, until: Option<NaiveDate>, expected_version: u64, actor: &Actor) -> Result<u64, SetStatusError> {
+        if reason.trim().is_empty() {
+            return Result::Err(SetStatusError::ReasonRequired);
+        }
+        let deferral = Deferral { reason, until, by: actor.clone(), at: self.now() };
+        self.change_status(identifier, QuestionStatus::Deferred, expected_version, actor, AuditAction::StatusChanged(QuestionStatus::Deferred), |question| {
+            question.deferral = Some(deferral);
+            Result::Ok(())
+        })
+    }
+
+    /// Withdraws an open or deferred question, keeping it for the record. A
+    /// decided question can't be cancelled.
+    pub fn cancel_question(&mut self, identifier: String, reason: String, expected_version: u64, actor: &Actor) -> Result<u64, SetStatusError> {
+        if reason.trim().is_empty() {
+            return Result::Err(SetStatusError::ReasonRequired);
+        }
+        let cancellation = Cancellation { reason, by: actor.clone(), at: self.now() };
+        self.change_status(identifier, QuestionStatus::Cancelled, expected_version, actor, AuditAction::StatusChanged(QuestionStatus::Cancelled), |question| {
+            question.deferral = None;
+            question.cancellation = Some(cancellation);
+            Result::Ok(())
+        })
+    }
+
+    /// Moves the decision into the question's history, marked `Reopened`,
+    /// and opens the question again.
+    pub fn reopen_question(&mut self, identifier: String, reason: String, expected_version: u64, actor: &Actor) -> Result<u64, SetStatusError> {
+        if reason.trim().is_empty() {
+            return Result::Err(SetStatusError::ReasonRequired);
+        }
+        let action = AuditAction::QuestionReopened(reason.clone());
+        let now = self.now();
+        self.change_status(identifier, QuestionStatus::Open, expected_version, actor, action, |question| {
+            let decision = question.decision.take()
+                .ok_or(SetStatusError::InvalidTransition { from: question.status(), to: QuestionStatus::Open })?;
+            question.decision_history.push(PastDecision {
+                decision,
+                ending: DecisionEnding::Reopened,
+                reason: reason.clone(),
+                by: actor.clone(),
+                at: now
+            });
+            Result::Ok(())
+        })
+    }
+
+    fn change_status<F: FnOnce(&mut Question) -> Result<(), SetStatusError>>(&mut self, identifier: String, status: QuestionStatus, expected_version: u64, actor: &Actor, action: AuditAction, apply: F) -> Result<u64, SetStatusError> {
+        let question = self.get_question_for_update(identifier, expected_version)?;
+        let current = question.status();
+        if !current.can_become(status) {
+            return Result::Err(SetStatusError::InvalidTransition { from: current, to: status });
+        }
+        apply(question)?;
+        let uuid = question.identifier;
+        question.status = status;
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, action);
+        Result::Ok(version)
+    }
+
+    /// Deferred questions due to be picked up again by `as_of`, oldest
+    /// wake-up date first.
+    pub fn questions_waking_up(&self, as_of: NaiveDate) -> Vec<QuestionSummary> {
+        let mut waking: Vec<&Question> = self.questions.values().filter(|question| question.is_waking_up(as_of)).collect();
+        waking.sort_by_key(|question| question.deferral.as_ref().and_then(|deferral| deferral.until));
+        let now = self.now();
+        waking.into_iter().map(|question| question.summary_at(now)).collect()
+    }
+
+    /// Decided questions whose decision has expired by `now`, earliest
+    /// expiry first.
+    pub fn expired_decisions(&self, now: DateTime<Utc>) -> Vec<QuestionSummary> {
+        let mut expired: Vec<(DateTime<Utc>, &Question)> = self.questions.values()
+            .filter_map(|question| question.decision.as_ref()
+                .filter(|decision| decision.is_expired(now))
+                .and_then(|decision| decision.expires_at)
+                .map(|expires_at| (expires_at, question)))
+            .collect();
+        expired.sort_by_key(|(expires_at, _)| *expires_at);
+        expired.into_iter().map(|(_, question)| question.summary_at(now)).collect()
+    }
+
+    /// Decided questions whose decision expires after `now` but within
+    /// `within` of it, earliest expiry first.
+    pub fn decisions_due_for_review(&self, now: DateTime<Utc>, within: Duration) -> Vec<QuestionSummary> {
+        let window = now + within;
+        let mut due: Vec<(DateTime<Utc>, &Question)> = self.questions.values()
+            .filter_map(|question| question.decision.as_ref()
+                .and_then(|decision| decision.expires_at)
+                .filter(|expires_at| *expires_at > now && *expires_at <= window)
+                .map(|expires_at| (expires_at, question)))
+            .collect();
+        due.sort_by_key(|(expires_at, _)| *expires_at);
+        due.into_iter().map(|(_, question)| question.summary_at(now)).collect()
+    }
+
+    /// Summaries of the questions in any of `statuses`.
+    pub fn list_questions_in(&self, statuses: &[QuestionStatus]) -> Vec<QuestionSummary> {
+        let now = self.now();
+        self.questions.values()
+            .filter(|question| statuses.contains(&question.status()))
+            .map(|question| question.summary_at(now))
+            .collect()
+    }
+
+    /// Like `list_questions_with_tag`, limited to questions in any of `statuses`.
+    pub fn list_questions_with_tag_in(&self, tag: &str, statuses: &[QuestionStatus]) -> Vec<QuestionSummary> {
+        let now = self.now();
+        self.questions_with_tag(tag).into_iter()
+            .filter(|question| statuses.contains(&question.status()))
+            .map(|question| question.summary_at(now))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use uuid::Uuid;
+    use crate::*;
+    use crate::status::*;
+
+    #[test]
+    fn test_status_transitions() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        let first = registry.add_question(Question::new("First?".to_string(), HashSet::new(), HashSet::new(), options.clone()), &luke).unwrap();
+        let second = registry.add_question(Question::new("Second?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+
+        assert_eq!(registry.defer_question(first.clone(), "Later".to_string(), None, 0, &luke).unwrap(), 1);
+        match registry.set_question_decision(first.clone(), Decision::new("Yes".to_string(), String::new(), HashSet::new()), 1, &luke) {
+            Err(SetQuestionDecisionError::Decision(SetDecisionError::NotOpen(QuestionStatus::Deferred))) => (),
+            _ => panic!("Deferred questions can't be decided")
+        }
+        registry.set_question_decision(second.clone(), Decision::new("Yes".to_string(), String::new(), HashSet::new()), 0, &luke).unwrap();
+        match registry.cancel_question(second.clone(), "Moot".to_string(), 1, &luke) {
+            Err(SetStatusError::InvalidTransition { from: QuestionStatus::Decided, to: QuestionStatus::Cancelled }) => (),
+            _ => panic!("Decided questions can't be cancelled")
+        }
+        assert!(matches!(registry.cancel_question(first.clone(), " ".to_string(), 1, &luke), Err(SetStatusError::ReasonRequired)));
+        assert!(matches!(registry.defer_question(first.clone(), "".to_string(), None, 1, &luke), Err(SetStatusError::ReasonRequired)));
+        registry.cancel_question(first.clone(), "Vendor went away".to_string(), 1, &luke).unwrap();
+        let cancelled = registry.get_question(first.clone()).unwrap();
+        assert_eq!(cancelled.get_cancellation().unwrap().reason, "Vendor went away");
+        assert!(cancelled.get_deferral().is_none());
+
+        assert!(matches!(registry.set_question_status(second.clone(), QuestionStatus::Open, 1, &luke), Err(SetStatusError::UseReopenQuestion)));
+        assert_eq!(registry.reopen_question(second.clone(), "The vendor raised prices".to_string(), 1, &luke).unwrap(), 2);
+        let reopened = registry.get_question(second.clone()).unwrap();
+        assert_eq!(reopened.status(), QuestionStatus::Open);
+        assert_eq!(reopened.decision_history()[0].ending, DecisionEnding::Reopened);
+        assert_eq!(reopened.decision_history()[0].decision.get_choice(), "Yes");
+
+        assert_eq!(registry.list_questions_in(&[QuestionStatus::Cancelled])[0].identifier, first);
+        assert_eq!(registry.list_questions_in(&[QuestionStatus::Open])[0].identifier, second);
+        assert!(registry.list_questions_in(&[QuestionStatus::Decided]).is_empty());
+    }
+
+    #[test]
+    fn test_expired_decisions_are_flagged() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let options: HashSet<String> = ["Free tier".to_string()].iter().cloned().collect();
+        let identifier = registry.add_question(Question::new("Which plan?".to_string(), HashSet::new(), HashSet::new(), options), &luke).unwrap();
+        let expiry = Utc.with_ymd_and_hms(2020, 7, 1, 0, 0, 0).unwrap();
+        let decision = Decision::new("Free tier".to_string(), "Until Q3".to_string(), HashSet::new()).with_expiry(expiry);
+        registry.set_question_decision(identifier.clone(), decision, 0, &luke).unwrap();
+
+        assert!(registry.expired_decisions(expiry - chrono::Duration::days(1)).is_empty());
+        assert_eq!(registry.expired_decisions(expiry)[0].identifier, identifier);
+        let summary = &registry.list_questions()[0];
+        assert!(summary.expired && summary.status == QuestionStatus::Decided);
+    }
+
+    #[test]
+    fn test_deferred_questions_wake_up() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let later = registry.add_question(Question::new("Later?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let someday = registry.add_question(Question::new("Someday?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let wake_up = NaiveDate::from_ymd_opt(2020, 9, 1).unwrap();
+        registry.defer_question(later.clone(), "Waiting on budget".to_string(), Some(wake_up), 0, &luke).unwrap();
+        registry.defer_question(someday, "Not now".to_string(), None, 0, &luke).unwrap();
+
+        assert!(registry.questions_waking_up(NaiveDate::from_ymd_opt(2020, 8, 31).unwrap()).is_empty());
+        assert_eq!(registry.questions_waking_up(wake_up)[0].identifier, later);
+        // The wake-up date has long passed, so only the undated one stays hidden.
+        assert_eq!(registry.list_questions().len(), 1);
+        assert_eq!(registry.get_question(later.clone()).unwrap().get_deferral().unwrap().reason, "Waiting on budget");
+
+        registry.set_question_status(later.clone(), QuestionStatus::Open, 1, &luke).unwrap();
+        assert!(registry.get_question(later).unwrap().get_deferral().is_none());
+        assert!(matches!(registry.set_question_status(Uuid::new_v4().to_string(), QuestionStatus::Deferred, 0, &luke), Err(SetStatusError::UseDeferQuestion)));
+    }
+}
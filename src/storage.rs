@@ -0,0 +1,94 @@
+//! Persistent backends for registries.
+//!
+//! A [`Storage`] keeps tags, questions (with a tag index) and audit entries
+//! individually, so a change to one question doesn't rewrite the whole
+//! registry. The in-memory `Registry` is loaded from and saved to a backend
+//! with `Registry::load_from` / `Registry::save_to`.
+
+use std::collections::HashSet;
+use uuid::Uuid;
+use crate::{Question, Registry};
+use crate::criteria::Criterion;
+use crate::settings::Settings;
+use crate::audit::{AuditEntry, AuditLog};
+
+#[cfg(feature = "embedded-db")]
+mod embedded;
+#[cfg(feature = "embedded-db")]
+pub use self::embedded::{EmbeddedError, EmbeddedStorage};
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "postgres")]
+pub use self::postgres::{PostgresError, PostgresStorage};
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "redis")]
+pub use self::redis::{Change, RedisError, RedisStorage};
+
+pub trait Storage {
+    type Error;
+
+    fn put_tag(&mut self, tag: &str) -> Result<(), Self::Error>;
+    /// Removes a tag from the tag list. Questions are updated separately.
+    fn remove_tag(&mut self, tag: &str) -> Result<(), Self::Error>;
+    fn tags(&self) -> Result<HashSet<String>, Self::Error>;
+
+    fn put_settings(&mut self, settings: &Settings) -> Result<(), Self::Error>;
+    /// `None` until settings have been written.
+    fn settings(&self) -> Result<Option<Settings>, Self::Error>;
+
+    /// Inserts or replaces the criterion with the same name.
+    fn put_criterion(&mut self, criterion: &Criterion) -> Result<(), Self::Error>;
+    fn criteria(&self) -> Result<Vec<Criterion>, Self::Error>;
+
+    /// Inserts or replaces a question, keeping the tag index in step.
+    fn put_question(&mut self, question: &Question) -> Result<(), Self::Error>;
+    fn get_question(&self, identifier: &Uuid) -> Result<Option<Question>, Self::Error>;
+    fn questions(&self) -> Result<Vec<Question>, Self::Error>;
+    fn question_ids_with_tag(&self, tag: &str) -> Result<Vec<Uuid>, Self::Error>;
+
+    fn append_audit_entry(&mut self, entry: &AuditEntry) -> Result<(), Self::Error>;
+    /// All audit entries in the order they were appended.
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, Self::Error>;
+    /// Replaces the whole audit trail, for when past entries are rewritten
+    /// (see `Registry::erase_person`).
+    fn replace_audit_entries(&mut self, entries: &[AuditEntry]) -> Result<(), Self::Error>;
+}
+
+impl Registry {
+    pub fn load_from<S: Storage>(storage: &S) -> Result<Registry, S::Error> {
+        let mut registry = Registry::new();
+        registry.settings = storage.settings()?.unwrap_or_default();
+        registry.tags = storage.tags()?;
+        registry.criteria = storage.criteria()?
+            .into_iter()
+            .map(|criterion| (criterion.name.clone(), criterion))
+            .collect();
+        registry.questions = storage.questions()?
+            .into_iter()
+            .map(|question| (question.identifier, question))
+            .collect();
+        registry.audit_log = AuditLog::from_entries(storage.audit_entries()?);
+        Result::Ok(registry)
+    }
+
+    /// Writes the whole registry to `storage`. The backend's audit entries are
+    /// taken to be a prefix of this registry's log, and only the rest are appended.
+    pub fn save_to<S: Storage>(&self, storage: &mut S) -> Result<(), S::Error> {
+        storage.put_settings(&self.settings)?;
+        for tag in &self.tags {
+            storage.put_tag(tag)?;
+        }
+        for criterion in self.criteria.values() {
+            storage.put_criterion(criterion)?;
+        }
+        for question in self.questions.values() {
+            storage.put_question(question)?;
+        }
+        let persisted = storage.audit_entries()?.len();
+        for entry in self.audit_log.entries().iter().skip(persisted) {
+            storage.append_audit_entry(entry)?;
+        }
+        Result::Ok(())
+    }
+}
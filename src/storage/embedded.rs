@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+use std::path::Path;
+use redb::{Database, MultimapTableDefinition, ReadableTable, TableDefinition};
+use uuid::Uuid;
+use crate::Question;
+use crate::audit::AuditEntry;
+use crate::criteria::Criterion;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+use crate::settings::Settings;
+use crate::storage::Storage;
+
+const TAGS: TableDefinition<&str, ()> = TableDefinition::new("tags");
+const QUESTIONS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("questions");
+/// Secondary index: tag -> identifiers of the questions carrying it.
+const TAG_INDEX: MultimapTableDefinition<&str, &[u8]> = MultimapTableDefinition::new("tag_index");
+const AUDIT: TableDefinition<u64, &[u8]> = TableDefinition::new("audit");
+const CRITERIA: TableDefinition<&str, &[u8]> = TableDefinition::new("criteria");
+/// Holds a single row under `SETTINGS_KEY`.
+const SETTINGS: TableDefinition<&str, &[u8]> = TableDefinition::new("settings");
+const SETTINGS_KEY: &str = "registry";
+
+#[derive(Debug)]
+pub enum EmbeddedError {
+    Database(Box<redb::Error>),
+    Encoding(serde_cbor::Error)
+}
+
+impl Localized for EmbeddedError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            EmbeddedError::Database(error) => messages::text(catalog, "storage.database", &[("error", error.to_string())]),
+            EmbeddedError::Encoding(error) => messages::encoding(catalog, error)
+        }
+    }
+}
+
+impl Coded for EmbeddedError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            EmbeddedError::Database(_) => ErrorCode::StorageFailed,
+            EmbeddedError::Encoding(_) => ErrorCode::Encoding
+        }
+    }
+}
+
+messages::display_localized!(EmbeddedError);
+
+impl<E: Into<redb::Error>> From<E> for EmbeddedError {
+    fn from(error: E) -> EmbeddedError {
+        EmbeddedError::Database(Box::new(error.into()))
+    }
+}
+
+/// A [`Storage`] backed by a single redb database file (feature `embedded-db`).
+pub struct EmbeddedStorage {
+    database: Database
+}
+
+impl EmbeddedStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<EmbeddedStorage, EmbeddedError> {
+        let database = Database::create(path)?;
+        let transaction = database.begin_write()?;
+        transaction.open_table(TAGS)?;
+        transaction.open_table(QUESTIONS)?;
+        transaction.open_multimap_table(TAG_INDEX)?;
+        transaction.open_table(AUDIT)?;
+        transaction.open_table(CRITERIA)?;
+        transaction.open_table(SETTINGS)?;
+        transaction.commit()?;
+        Result::Ok(EmbeddedStorage { database })
+    }
+}
+
+fn decode_question(bytes: &[u8]) -> Result<Question, EmbeddedError> {
+    serde_cbor::from_slice(bytes).map_err(EmbeddedError::Encoding)
+}
+
+impl Storage for EmbeddedStorage {
+    type Error = EmbeddedError;
+
+    fn put_tag(&mut self, tag: &str) -> Result<(), EmbeddedError> {
+        let transaction = self.database.begin_write()?;
+        transaction.open_table(TAGS)?.insert(tag, ())?;
+        transaction.commit()?;
+        Result::Ok(())
+    }
+
+    fn remove_tag(&mut self, tag: &str) -> Result<(), EmbeddedError> {
+        let transaction = self.database.begin_write()?;
+        transaction.open_table(TAGS)?.remove(tag)?;
+        transaction.commit()?;
+        Result::Ok(())
+    }
+
+    fn tags(&self) -> Result<HashSet<String>, EmbeddedError> {
+        let transaction = self.database.begin_read()?;
+        let mut tags = HashSet::new();
+        for row in transaction.open_table(TAGS)?.iter()? {
+            tags.insert(row?.0.value().to_string());
+        }
+        Result::Ok(tags)
+    }
+
+    fn put_settings(&mut self, settings: &Settings) -> Result<(), EmbeddedError> {
+        let encoded = serde_cbor::to_vec(settings).map_err(EmbeddedError::Encoding)?;
+        let transaction = self.database.begin_write()?;
+        transaction.open_table(SETTINGS)?.insert(SETTINGS_KEY, encoded.as_slice())?;
+        transaction.commit()?;
+        Result::Ok(())
+    }
+
+    fn settings(&self) -> Result<Option<Settings>, EmbeddedError> {
+        let transaction = self.database.begin_read()?;
+        let settings = transaction.open_table(SETTINGS)?;
+        let found = settings.get(SETTINGS_KEY)?;
+        match found {
+            Some(bytes) => Result::Ok(Some(serde_cbor::from_slice(bytes.value()).map_err(EmbeddedError::Encoding)?)),
+            None => Result::Ok(None)
+        }
+    }
+
+    fn put_criterion(&mut self, criterion: &Criterion) -> Result<(), EmbeddedError> {
+        let encoded = serde_cbor::to_vec(criterion).map_err(EmbeddedError::Encoding)?;
+        let transaction = self.database.begin_write()?;
+        transaction.open_table(CRITERIA)?.insert(criterion.name.as_str(), encoded.as_slice())?;
+        transaction.commit()?;
+        Result::Ok(())
+    }
+
+    fn criteria(&self) -> Result<Vec<Criterion>, EmbeddedError> {
+        let transaction = self.database.begin_read()?;
+        let mut criteria = Vec::new();
+        for row in transaction.open_table(CRITERIA)?.iter()? {
+            criteria.push(serde_cbor::from_slice(row?.1.value()).map_err(EmbeddedError::Encoding)?);
+        }
+        Result::Ok(criteria)
+    }
+
+    fn put_question(&mut self, question: &Question) -> Result<(), EmbeddedError> {
+        let key = question.identifier.as_bytes();
+        let encoded = serde_cbor::to_vec(question).map_err(EmbeddedError::Encoding)?;
+        let transaction = self.database.begin_write()?;
+        {
+            let mut questions = transaction.open_table(QUESTIONS)?;
+            let mut index = transaction.open_multimap_table(TAG_INDEX)?;
+            let previous = match questions.get(&key[..])? {
+                Some(bytes) => Some(decode_question(bytes.value())?),
+                None => None
+            };
+            if let Some(previous) = previous {
+                for tag in previous.tags.difference(&question.tags) {
+                    index.remove(tag.as_str(), &key[..])?;
+                }
+            }
+            for tag in &question.tags {
+                index.insert(tag.as_str(), &key[..])?;
+            }
+            questions.insert(&key[..], encoded.as_slice())?;
+        }
+        transaction.commit()?;
+        Result::Ok(())
+    }
+
+    fn get_question(&self, identifier: &Uuid) -> Result<Option<Question>, EmbeddedError> {
+        let transaction = self.database.begin_read()?;
+        let questions = transaction.open_table(QUESTIONS)?;
+        let found = questions.get(&identifier.as_bytes()[..])?;
+        match found {
+            Some(bytes) => Result::Ok(Some(decode_question(bytes.value())?)),
+            None => Result::Ok(None)
+        }
+    }
+
+    fn questions(&self) -> Result<Vec<Question>, EmbeddedError> {
+        let transaction = self.database.begin_read()?;
+        let mut questions = Vec::new();
+        for row in transaction.open_table(QUESTIONS)?.iter()? {
+            questions.push(decode_question(row?.1.value())?);
+        }
+        Result::Ok(questions)
+    }
+
+    fn question_ids_with_tag(&self, tag: &str) -> Result<Vec<Uuid>, EmbeddedError> {
+        let transaction = self.database.begin_read()?;
+        let mut identifiers = Vec::new();
+        for value in transaction.open_multimap_table(TAG_INDEX)?.get(tag)? {
+            // Keys are always written from Uuid::as_bytes, so they are 16 bytes long.
+            identifiers.push(Uuid::from_slice(value?.value()).expect("index holds 16 byte identifiers"));
+        }
+        Result::Ok(identifiers)
+    }
+
+    fn append_audit_entry(&mut self, entry: &AuditEntry) -> Result<(), EmbeddedError> {
+        let encoded = serde_cbor::to_vec(entry).map_err(EmbeddedError::Encoding)?;
+        let transaction = self.database.begin_write()?;
+        {
+            let mut audit = transaction.open_table(AUDIT)?;
+            let next = match audit.last()? {
+                Some((sequence, _)) => sequence.value() + 1,
+                None => 0
+            };
+            audit.insert(next, encoded.as_slice())?;
+        }
+        transaction.commit()?;
+        Result::Ok(())
+    }
+
+    fn replace_audit_entries(&mut self, entries: &[AuditEntry]) -> Result<(), EmbeddedError> {
+        let transaction = self.database.begin_write()?;
+        {
+            let mut audit = transaction.open_table(AUDIT)?;
+            audit.retain(|_, _| false)?;
+            for (sequence, entry) in entries.iter().enumerate() {
+                let encoded = serde_cbor::to_vec(entry).map_err(EmbeddedError::Encoding)?;
+                audit.insert(sequence as u64, encoded.as_slice())?;
+            }
+        }
+        transaction.commit()?;
+        Result::Ok(())
+    }
+
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, EmbeddedError> {
+        let transaction = self.database.begin_read()?;
+        let mut entries = Vec::new();
+        for row in transaction.open_table(AUDIT)?.iter()? {
+            entries.push(serde_cbor::from_slice(row?.1.value()).map_err(EmbeddedError::Encoding)?);
+        }
+        Result::Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::storage::*;
+
+    fn storage(name: &str) -> EmbeddedStorage {
+        let path = std::env::temp_dir().join(format!("decis_test_{}_{}.redb", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        EmbeddedStorage::open(path).unwrap()
+    }
+
+    #[test]
+    fn test_registry_round_trip() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"Infra".to_string(), &luke).unwrap();
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("Infra".to_string());
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), tags, HashSet::new(), HashSet::new()), &luke).unwrap();
+
+        let mut storage = storage("embedded_round_trip");
+        registry.save_to(&mut storage).unwrap();
+        registry.save_to(&mut storage).unwrap();
+
+        let loaded = Registry::load_from(&storage).unwrap();
+        assert!(loaded.get_tags().contains("Infra"));
+        assert!(loaded.get_question(identifier).is_ok());
+        assert_eq!(loaded.audit_log().entries().len(), 2);
+    }
+
+    #[test]
+    fn test_tag_index_follows_question_updates() {
+        let mut storage = storage("embedded_tag_index");
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("Infra".to_string());
+        let mut question = Question::new("Which queue?".to_string(), tags, HashSet::new(), HashSet::new());
+        storage.put_question(&question).unwrap();
+        assert_eq!(storage.question_ids_with_tag("Infra").unwrap(), vec![question.identifier]);
+
+        question.tags.remove("Infra");
+        question.tags.insert("Messaging".to_string());
+        storage.put_question(&question).unwrap();
+        assert!(storage.question_ids_with_tag("Infra").unwrap().is_empty());
+        assert_eq!(storage.question_ids_with_tag("Messaging").unwrap(), vec![question.identifier]);
+    }
+}
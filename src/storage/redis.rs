@@ -0,0 +1,283 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use redis::{Commands, Connection};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::Question;
+use crate::audit::AuditEntry;
+use crate::criteria::Criterion;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+use crate::settings::Settings;
+use crate::storage::Storage;
+
+#[derive(Debug)]
+pub enum RedisError {
+    Redis(redis::RedisError),
+    Encoding(serde_json::Error)
+}
+
+impl Localized for RedisError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            RedisError::Redis(error) => messages::text(catalog, "storage.database", &[("error", error.to_string())]),
+            RedisError::Encoding(error) => messages::encoding(catalog, error)
+        }
+    }
+}
+
+impl Coded for RedisError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            RedisError::Redis(_) => ErrorCode::StorageFailed,
+            RedisError::Encoding(_) => ErrorCode::Encoding
+        }
+    }
+}
+
+messages::display_localized!(RedisError);
+
+impl From<redis::RedisError> for RedisError {
+    fn from(error: redis::RedisError) -> RedisError {
+        RedisError::Redis(error)
+    }
+}
+
+impl From<serde_json::Error> for RedisError {
+    fn from(error: serde_json::Error) -> RedisError {
+        RedisError::Encoding(error)
+    }
+}
+
+/// Published as JSON on the `<namespace>:changes` channel after every write.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    SettingsSaved,
+    TagAdded(String),
+    TagRemoved(String),
+    CriterionSaved(String),
+    QuestionSaved(Uuid),
+    AuditEntryAppended,
+    AuditRewritten
+}
+
+/// A [`Storage`] in Redis (feature `redis`). Every key lives under a
+/// namespace so several registries can share one server:
+///
+/// - `<ns>:tags` and `<ns>:questions` are sets of tag names and question ids,
+/// - `<ns>:settings` holds the JSON registry settings,
+/// - `<ns>:criteria` is a hash of criterion name to JSON criterion,
+/// - `<ns>:question:<id>` is a hash with `content`, `version` and the JSON `body`,
+/// - `<ns>:tag:<tag>` is the set of question ids carrying that tag,
+/// - `<ns>:audit` is a list of JSON audit entries.
+pub struct RedisStorage {
+    client: redis::Client,
+    connection: Mutex<Connection>,
+    namespace: String
+}
+
+impl RedisStorage {
+    pub fn connect(url: &str, namespace: &str) -> Result<RedisStorage, RedisError> {
+        let client = redis::Client::open(url)?;
+        let connection = Mutex::new(client.get_connection()?);
+        Result::Ok(RedisStorage { client, connection, namespace: namespace.to_string() })
+    }
+
+    fn key(&self, suffix: &str) -> String {
+        format!("{}:{}", self.namespace, suffix)
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn publish(&self, connection: &mut Connection, change: &Change) -> Result<(), RedisError> {
+        let _: i64 = connection.publish(self.key("changes"), serde_json::to_string(change)?)?;
+        Result::Ok(())
+    }
+
+    fn load_question(&self, connection: &mut Connection, identifier: &Uuid) -> Result<Option<Question>, RedisError> {
+        let body: Option<String> = connection.hget(self.key(&format!("question:{}", identifier)), "body")?;
+        match body {
+            Some(body) => Result::Ok(Some(serde_json::from_str(&body)?)),
+            None => Result::Ok(None)
+        }
+    }
+
+    /// Blocks, handing every change published for this namespace to `on_change`
+    /// until it returns `false`. Uses a dedicated connection.
+    pub fn watch<F: FnMut(Change) -> bool>(&self, mut on_change: F) -> Result<(), RedisError> {
+        let mut connection = self.client.get_connection()?;
+        let mut pubsub = connection.as_pubsub();
+        pubsub.subscribe(self.key("changes"))?;
+        loop {
+            let payload: String = pubsub.get_message()?.get_payload()?;
+            if !on_change(serde_json::from_str(&payload)?) {
+                return Result::Ok(());
+            }
+        }
+    }
+}
+
+impl Storage for RedisStorage {
+    type Error = RedisError;
+
+    fn put_tag(&mut self, tag: &str) -> Result<(), RedisError> {
+        let mut connection = self.connection();
+        let added: i64 = connection.sadd(self.key("tags"), tag)?;
+        if added > 0 {
+            self.publish(&mut connection, &Change::TagAdded(tag.to_string()))?;
+        }
+        Result::Ok(())
+    }
+
+    fn remove_tag(&mut self, tag: &str) -> Result<(), RedisError> {
+        let mut connection = self.connection();
+        let removed: i64 = connection.srem(self.key("tags"), tag)?;
+        if removed > 0 {
+            self.publish(&mut connection, &Change::TagRemoved(tag.to_string()))?;
+        }
+        Result::Ok(())
+    }
+
+    fn tags(&self) -> Result<HashSet<String>, RedisError> {
+        Result::Ok(self.connection().smembers(self.key("tags"))?)
+    }
+
+    fn put_settings(&mut self, settings: &Settings) -> Result<(), RedisError> {
+        let mut connection = self.connection();
+        let _: () = connection.set(self.key("settings"), serde_json::to_string(settings)?)?;
+        self.publish(&mut connection, &Change::SettingsSaved)
+    }
+
+    fn settings(&self) -> Result<Option<Settings>, RedisError> {
+        let body: Option<String> = self.connection().get(self.key("settings"))?;
+        match body {
+            Some(body) => Result::Ok(Some(serde_json::from_str(&body)?)),
+            None => Result::Ok(None)
+        }
+    }
+
+    fn put_criterion(&mut self, criterion: &Criterion) -> Result<(), RedisError> {
+        let mut connection = self.connection();
+        let _: i64 = connection.hset(self.key("criteria"), &criterion.name, serde_json::to_string(criterion)?)?;
+        self.publish(&mut connection, &Change::CriterionSaved(criterion.name.clone()))
+    }
+
+    fn criteria(&self) -> Result<Vec<Criterion>, RedisError> {
+        let bodies: Vec<String> = self.connection().hvals(self.key("criteria"))?;
+        let mut criteria = Vec::with_capacity(bodies.len());
+        for body in bodies {
+            criteria.push(serde_json::from_str(&body)?);
+        }
+        Result::Ok(criteria)
+    }
+
+    /// Index maintenance reads the previous tags before the atomic write, so
+    /// concurrent writers to the same question should coordinate through the
+    /// question version.
+    fn put_question(&mut self, question: &Question) -> Result<(), RedisError> {
+        let mut connection = self.connection();
+        let identifier = question.identifier.to_string();
+        let previous_tags = self.load_question(&mut connection, &question.identifier)?
+            .map(|previous| previous.tags)
+            .unwrap_or_default();
+
+        let mut pipeline = redis::pipe();
+        pipeline.atomic()
+            .hset_multiple(self.key(&format!("question:{}", identifier)), &[
+                ("content", question.content.clone()),
+                ("version", question.version.to_string()),
+                ("body", serde_json::to_string(question)?),
+            ]).ignore()
+            .sadd(self.key("questions"), &identifier).ignore();
+        for tag in previous_tags.difference(&question.tags) {
+            pipeline.srem(self.key(&format!("tag:{}", tag)), &identifier).ignore();
+        }
+        for tag in &question.tags {
+            pipeline.sadd(self.key(&format!("tag:{}", tag)), &identifier).ignore();
+        }
+        pipeline.query::<()>(&mut *connection)?;
+        self.publish(&mut connection, &Change::QuestionSaved(question.identifier))
+    }
+
+    fn get_question(&self, identifier: &Uuid) -> Result<Option<Question>, RedisError> {
+        self.load_question(&mut self.connection(), identifier)
+    }
+
+    fn questions(&self) -> Result<Vec<Question>, RedisError> {
+        let mut connection = self.connection();
+        let identifiers: Vec<String> = connection.smembers(self.key("questions"))?;
+        let mut questions = Vec::with_capacity(identifiers.len());
+        for identifier in identifiers {
+            let body: Option<String> = connection.hget(self.key(&format!("question:{}", identifier)), "body")?;
+            if let Some(body) = body {
+                questions.push(serde_json::from_str(&body)?);
+            }
+        }
+        Result::Ok(questions)
+    }
+
+    fn question_ids_with_tag(&self, tag: &str) -> Result<Vec<Uuid>, RedisError> {
+        let members: Vec<String> = self.connection().smembers(self.key(&format!("tag:{}", tag)))?;
+        // Members are only ever written from Uuid::to_string.
+        Result::Ok(members.iter().filter_map(|member| Uuid::parse_str(member).ok()).collect())
+    }
+
+    fn append_audit_entry(&mut self, entry: &AuditEntry) -> Result<(), RedisError> {
+        let mut connection = self.connection();
+        let _: i64 = connection.rpush(self.key("audit"), serde_json::to_string(entry)?)?;
+        self.publish(&mut connection, &Change::AuditEntryAppended)
+    }
+
+    fn replace_audit_entries(&mut self, entries: &[AuditEntry]) -> Result<(), RedisError> {
+        let mut pipeline = redis::pipe();
+        pipeline.atomic().del(self.key("audit")).ignore();
+        for entry in entries {
+            pipeline.rpush(self.key("audit"), serde_json::to_string(entry)?).ignore();
+        }
+        let mut connection = self.connection();
+        let _: () = pipeline.query(&mut connection)?;
+        self.publish(&mut connection, &Change::AuditRewritten)
+    }
+
+    fn audit_entries(&self) -> Result<Vec<AuditEntry>, RedisError> {
+        let lines: Vec<String> = self.connection().lrange(self.key("audit"), 0, -1)?;
+        let mut entries = Vec::with_capacity(lines.len());
+        for line in lines {
+            entries.push(serde_json::from_str(&line)?);
+        }
+        Result::Ok(entries)
+    }
+}
+
+/// These run against the server named by `DECIS_TEST_REDIS_URL` and are
+/// skipped when it isn't set.
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::storage::*;
+
+    #[test]
+    fn test_registry_round_trip() {
+        let url = match std::env::var("DECIS_TEST_REDIS_URL") {
+            Ok(url) => url,
+            Err(_) => return
+        };
+        let namespace = format!("decis-test-{}", uuid::Uuid::new_v4());
+        let mut storage = RedisStorage::connect(&url, &namespace).unwrap();
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"Infra".to_string(), &luke).unwrap();
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("Infra".to_string());
+        let identifier = registry.add_question(Question::new("Which queue?".to_string(), tags, HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.save_to(&mut storage).unwrap();
+
+        let loaded = Registry::load_from(&storage).unwrap();
+        assert!(loaded.get_question(identifier).is_ok());
+        assert_eq!(storage.question_ids_with_tag("Infra").unwrap().len(), 1);
+        assert_eq!(loaded.audit_log().entries().len(), 2);
+    }
+}
@@ -0,0 +1,201 @@
+//! Newline-delimited JSON export and import, one question per line, for
+//! moving registries too large to serialize in one piece.
+
+use std::io::{BufRead, Write};
+use crate::{Actor, AddQuestionError, Question, Registry};
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(std::io::Error),
+    /// `line` is 1-based.
+    Json { line: usize, error: serde_json::Error },
+    Question { line: usize, error: AddQuestionError }
+}
+
+impl Localized for StreamError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            StreamError::Io(error) => messages::io(catalog, error),
+            StreamError::Json { line, error } => {
+                messages::text(catalog, "stream.json", &[("line", line.to_string()), ("error", error.to_string())])
+            }
+            StreamError::Question { line, error } => {
+                messages::text(catalog, "stream.question", &[("line", line.to_string()), ("error", error.localize(catalog))])
+            }
+        }
+    }
+}
+
+impl Coded for StreamError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            StreamError::Io(_) => ErrorCode::Io,
+            StreamError::Json { .. } => ErrorCode::InvalidStreamLine,
+            StreamError::Question { error, .. } => error.code()
+        }
+    }
+}
+
+messages::display_localized!(StreamError);
+
+/// What importing a stream would do, as `validate_stream` reports it.
+#[derive(Debug, Default)]
+pub struct StreamValidation {
+    /// How many lines would be imported.
+    pub valid: usize,
+    /// Tags the import would create.
+    pub created_tags: Vec<String>,
+    /// Every line that would stop the import, in order.
+    pub errors: Vec<StreamError>
+}
+
+impl StreamValidation {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl From<std::io::Error> for StreamError {
+    fn from(error: std::io::Error) -> StreamError {
+        StreamError::Io(error)
+    }
+}
+
+impl Registry {
+    /// Writes every question as a single JSON line, returning how many were written.
+    pub fn export_stream<W: Write>(&self, mut writer: W) -> Result<usize, StreamError> {
+        let mut written = 0;
+        for question in self.questions.values() {
+            serde_json::to_writer(&mut writer, question)
+                .map_err(|error| StreamError::Json { line: written + 1, error })?;
+            writer.write_all(b"\n")?;
+            written += 1;
+        }
+        writer.flush()?;
+        Result::Ok(written)
+    }
+
+    /// Reads questions written by `export_stream` one line at a time, returning
+    /// how many were added. Tags the registry doesn't know yet are created, so
+    /// a stream can be imported into an empty registry. Stops at the first bad
+    /// line; questions before it stay imported.
+    pub fn import_stream<R: BufRead>(&mut self, reader: R, actor: &Actor) -> Result<usize, StreamError> {
+        let mut imported = 0;
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.import_line(index + 1, &line, actor)?;
+            imported += 1;
+        }
+        Result::Ok(imported)
+    }
+
+    /// What `import_stream` would do, without changing the registry. Each line
+    /// goes through the same checks, including the registry's validators and
+    /// the questions on earlier lines, but every bad line is reported instead
+    /// of stopping at the first.
+    pub fn validate_stream<R: BufRead>(&self, reader: R, actor: &Actor) -> StreamValidation {
+        let mut scratch = self.scratch();
+        let mut validation = StreamValidation::default();
+        for (index, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    validation.errors.push(StreamError::Io(error));
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match scratch.import_line(index + 1, &line, actor) {
+                Ok(()) => validation.valid += 1,
+                Err(error) => validation.errors.push(error)
+            }
+        }
+        validation.created_tags = scratch.tags.difference(&self.tags).cloned().collect();
+        validation.created_tags.sort();
+        validation
+    }
+
+    /// Adds the question on line `number`, creating the tags it needs.
+    fn import_line(&mut self, number: usize, line: &str, actor: &Actor) -> Result<(), StreamError> {
+        let question: Question = serde_json::from_str(line)
+            .map_err(|error| StreamError::Json { line: number, error })?;
+        for tag in &question.tags {
+            if self.resolve_tag(tag).is_none() {
+                // Can't fail: the tag was just checked to be missing.
+                let _ = self.add_tag(tag, actor);
+            }
+        }
+        self.add_question(question, actor)
+            .map_err(|error| StreamError::Question { line: number, error })?;
+        Result::Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::stream::*;
+
+    #[test]
+    fn test_stream_round_trip() {
+        let luke = Actor::new("luke");
+        let mut source = Registry::new();
+        source.add_tag(&"Infra".to_string(), &luke).unwrap();
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("Infra".to_string());
+        for content in ["Which queue?", "Which database?"].iter() {
+            source.add_question(Question::new(content.to_string(), tags.clone(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        }
+
+        let mut exported = Vec::new();
+        assert_eq!(source.export_stream(&mut exported).unwrap(), 2);
+        assert_eq!(String::from_utf8(exported.clone()).unwrap().lines().count(), 2);
+
+        let mut target = Registry::new();
+        assert_eq!(target.import_stream(exported.as_slice(), &luke).unwrap(), 2);
+        assert!(target.get_tags().contains("Infra"));
+        assert_eq!(target.list_questions().len(), 2);
+    }
+
+    #[test]
+    fn test_import_reports_bad_line() {
+        let input = "\n{\"not\": \"a question\"}\n";
+        match Registry::new().import_stream(input.as_bytes(), &Actor::new("luke")) {
+            Err(StreamError::Json { line: 2, .. }) => (),
+            _ => panic!("The malformed second line should be reported")
+        }
+    }
+
+    #[test]
+    fn test_validation_reports_every_bad_line_and_changes_nothing() {
+        let luke = Actor::new("luke");
+        let mut source = Registry::new();
+        source.add_tag(&"infra".to_string(), &luke).unwrap();
+        let tags: HashSet<String> = ["infra".to_string()].iter().cloned().collect();
+        source.add_question(Question::new("Which queue?".to_string(), tags, HashSet::new(), HashSet::new()), &luke).unwrap();
+        let mut exported = Vec::new();
+        source.export_stream(&mut exported).unwrap();
+        let line = String::from_utf8(exported).unwrap();
+        // The repeated line is a duplicate of the first by the time it's read.
+        let input = format!("{}not json\n{}", line, line);
+
+        let target = Registry::new();
+        let validation = target.validate_stream(input.as_bytes(), &luke);
+        assert_eq!((validation.valid, validation.created_tags.clone()), (1, vec!["infra".to_string()]));
+        match validation.errors.as_slice() {
+            [StreamError::Json { line: 2, .. }, StreamError::Question { line: 3, error: AddQuestionError::AlreadyExists }] => (),
+            errors => panic!("Both bad lines should be reported, got {:?}", errors)
+        }
+        assert!(!validation.is_valid());
+        assert!(target.list_questions().is_empty() && target.get_tags().is_empty());
+        assert!(target.audit_log().entries().is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+//! Handing part of a registry to someone else: the questions matching a
+//! search, with the tags they use, to import into another registry.
+
+use serde::{Serialize, Deserialize};
+use crate::{Actor, AddQuestionError, Question, Registry};
+use crate::search::SearchTerm;
+
+/// Questions exported from a registry, as `export_subset` returns them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Subset {
+    pub tags: Vec<String>,
+    pub questions: Vec<Question>
+}
+
+/// What to do with a question whose identifier is already taken.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnCollision {
+    /// Import it under a new identifier.
+    #[default]
+    Renumber,
+    /// Leave the existing question and don't import this one.
+    Skip
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    pub on_collision: OnCollision,
+    /// Create the subset's tags the registry doesn't have. Without it,
+    /// questions with unknown tags are rejected.
+    pub create_tags: bool,
+    /// Report what the import would do without changing the registry.
+    /// Identifiers it would renumber to are only examples.
+    pub validate_only: bool
+}
+
+#[derive(Debug, Default)]
+pub struct SubsetImport {
+    /// Identifiers of the imported questions, as they are in this registry.
+    pub added: Vec<String>,
+    /// Identifiers changed on import, from the subset's to this registry's.
+    pub renumbered: Vec<(String, String)>,
+    pub skipped: Vec<String>,
+    pub rejected: Vec<(String, AddQuestionError)>
+}
+
+impl Registry {
+    /// The questions matching `query`, in `search` syntax (`tag:billing`,
+    /// say), and the tags they carry.
+    pub fn export_subset(&self, query: &str) -> Subset {
+        let mut questions: Vec<Question> = self.matching_questions(&SearchTerm::parse(query)).into_iter().cloned().collect();
+        questions.sort_by(|a, b| a.content.cmp(&b.content));
+        let mut tags: Vec<String> = questions.iter().flat_map(|question| question.tags.iter().cloned()).collect();
+        tags.sort();
+        tags.dedup();
+        Subset { tags, questions }
+    }
+
+    /// Adds the subset's questions, keeping their histories, and reports
+    /// what happened to each.
+    pub fn import_subset(&mut self, subset: Subset, options: &ImportOptions, actor: &Actor) -> SubsetImport {
+        if options.validate_only {
+            let options = ImportOptions { validate_only: false, ..options.clone() };
+            return self.scratch().import_subset(subset, &options, actor);
+        }
+        let mut report = SubsetImport::default();
+        if options.create_tags {
+            for tag in &subset.tags {
+                if self.resolve_tag(tag).is_none() {
+                    // A tag this registry's rules refuse leaves its questions to be rejected.
+                    let _ = self.add_tag(tag, actor);
+                }
+            }
+        }
+        for mut question in subset.questions {
+            let original = question.identifier;
+            if self.questions.contains_key(&original) {
+                match options.on_collision {
+                    OnCollision::Skip => {
+                        report.skipped.push(original.to_string());
+                        continue;
+                    }
+                    OnCollision::Renumber => question = question.with_id(self.next_id())
+                }
+            }
+            match self.add_question(question, actor) {
+                Ok(identifier) => {
+                    if identifier != original.to_string() {
+                        report.renumbered.push((original.to_string(), identifier.clone()));
+                    }
+                    report.added.push(identifier);
+                }
+                Err(error) => report.rejected.push((original.to_string(), error))
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::subset::*;
+
+    #[test]
+    fn test_subset_moves_between_registries() {
+        let luke = Actor::new("luke");
+        let mut source = Registry::new();
+        source.add_tag(&"billing".to_string(), &luke).unwrap();
+        source.add_tag(&"infra".to_string(), &luke).unwrap();
+        let billing: HashSet<String> = ["billing".to_string()].iter().cloned().collect();
+        let shared = source.add_question(Question::new("Bill annually?".to_string(), billing.clone(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        source.add_question(Question::new("Refunds?".to_string(), billing, HashSet::new(), HashSet::new()), &luke).unwrap();
+        source.add_question(Question::new("Which CI?".to_string(), ["infra".to_string()].iter().cloned().collect(), HashSet::new(), HashSet::new()), &luke).unwrap();
+
+        let subset = source.export_subset("tag:billing");
+        assert_eq!(subset.tags, vec!["billing".to_string()]);
+        let json = serde_json::to_string(&subset).unwrap();
+
+        let ana = Actor::new("ana");
+        let mut target = Registry::new();
+        let refused = target.import_subset(serde_json::from_str(&json).unwrap(), &ImportOptions::default(), &ana);
+        assert_eq!(refused.rejected.len(), 2);
+
+        let options = ImportOptions { create_tags: true, ..ImportOptions::default() };
+        let dry_run = ImportOptions { validate_only: true, ..options.clone() };
+        assert_eq!(target.import_subset(serde_json::from_str(&json).unwrap(), &dry_run, &ana).added.len(), 2);
+        assert!(target.get_tags().is_empty() && target.list_questions().is_empty());
+        let first = target.import_subset(serde_json::from_str(&json).unwrap(), &options, &ana);
+        assert_eq!(first.added.len(), 2);
+        assert!(first.added.contains(&shared));
+        assert_eq!(target.question(&shared).unwrap().history().len(), 2);
+
+        let again = target.import_subset(serde_json::from_str(&json).unwrap(), &options, &ana);
+        assert_eq!(again.renumbered.len(), 2);
+        assert_eq!(target.list_questions_with_tag("billing").len(), 4);
+        let skip = ImportOptions { on_collision: OnCollision::Skip, ..options };
+        assert_eq!(target.import_subset(subset, &skip, &ana).skipped.len(), 2);
+    }
+}
@@ -0,0 +1,106 @@
+//! Suggesting existing tags for a question from the words it uses.
+//!
+//! A tag scores for each word the question shares with the questions that
+//! already carry it. Each word counts by how many of those questions use it
+//! and by how rare it is across the whole registry, so a word every question
+//! uses counts for nothing. Words are read with the registry's
+//! `SearchAnalysis`, always split into whole words.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::{Question, Registry};
+use crate::search::{SearchAnalysis, SearchField};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagSuggestion {
+    pub tag: String,
+    pub score: f64,
+    /// The shared words behind the score, strongest first. They are stems
+    /// when the registry's search uses stemming.
+    pub words: Vec<String>
+}
+
+impl Registry {
+    fn question_words(&self, analysis: &SearchAnalysis, question: &Question) -> BTreeSet<String> {
+        [SearchField::Content, SearchField::Context].iter()
+            .flat_map(|field| question.field_texts(*field))
+            .flat_map(|text| analysis.words(text).into_iter().map(|(_, word)| word))
+            .collect()
+    }
+
+    /// Tags `question` doesn't have yet, best first, for the ones sharing
+    /// any telling words with questions already carrying them.
+    pub fn suggest_tags(&self, question: &Question) -> Vec<TagSuggestion> {
+        let analysis = SearchAnalysis { tokenize: true, ..self.settings.search.clone() };
+        let words = self.question_words(&analysis, question);
+        let mut frequency: HashMap<String, usize> = HashMap::new();
+        // Tag -> how many of its questions use each of `words`, and how many it has.
+        let mut tagged: BTreeMap<&String, (HashMap<&String, usize>, usize)> = BTreeMap::new();
+        let mut others = 0;
+        for other in self.questions.values().filter(|other| other.identifier != question.identifier) {
+            others += 1;
+            let other_words = self.question_words(&analysis, other);
+            other_words.iter().for_each(|word| *frequency.entry(word.clone()).or_default() += 1);
+            for tag in other.tags.iter().filter(|tag| !question.tags.contains(*tag)) {
+                let (counts, total) = tagged.entry(tag).or_default();
+                *total += 1;
+                words.iter().filter(|word| other_words.contains(*word)).for_each(|word| *counts.entry(word).or_default() += 1);
+            }
+        }
+        let mut suggestions: Vec<TagSuggestion> = tagged.into_iter()
+            .filter_map(|(tag, (counts, total))| {
+                let mut weights: Vec<(f64, &String)> = counts.into_iter()
+                    .map(|(word, count)| {
+                        let rarity = (others as f64 / frequency[word] as f64).ln();
+                        (count as f64 / total as f64 * rarity, word)
+                    })
+                    .filter(|(weight, _)| *weight > 0.0)
+                    .collect();
+                if weights.is_empty() {
+                    return None;
+                }
+                weights.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+                Some(TagSuggestion {
+                    tag: tag.clone(),
+                    score: weights.iter().map(|(weight, _)| weight).sum(),
+                    words: weights.into_iter().map(|(_, word)| word.clone()).collect()
+                })
+            })
+            .collect();
+        suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.tag.cmp(&b.tag)));
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::search::SearchAnalysis;
+
+    #[test]
+    fn test_tags_are_suggested_from_shared_words() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.set_search_analysis(SearchAnalysis::english(), &luke);
+        for tag in &["database", "frontend"] {
+            registry.add_tag(&tag.to_string(), &luke).unwrap();
+        }
+        let tagged = |tag: &str| -> HashSet<String> { [tag.to_string()].iter().cloned().collect() };
+        for (content, tag) in &[("Which database for the orders service?", "database"), ("Which tool for database migrations?", "database"),
+                                ("Which framework for the admin UI?", "frontend"), ("Which icons for the UI?", "frontend")] {
+            registry.add_question(Question::new(content.to_string(), tagged(tag), HashSet::new(), HashSet::new()), &luke).unwrap();
+        }
+
+        let new = Question::new("Which migration tool for the database?".to_string(), HashSet::new(), HashSet::new(), HashSet::new());
+        let suggestions = registry.suggest_tags(&new);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].tag, "database");
+        let mut words = suggestions[0].words.clone();
+        words.sort();
+        assert_eq!(words, vec!["databas", "migration", "tool"]);
+
+        // "which" is in every question, so it suggests nothing on its own.
+        let vague = Question::new("Which one?".to_string(), tagged("database"), HashSet::new(), HashSet::new());
+        assert!(registry.suggest_tags(&vague).is_empty());
+    }
+}
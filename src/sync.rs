@@ -0,0 +1,190 @@
+//! Keeping two registries in step, for teams that work offline.
+//!
+//! A `SyncPoint` records how far each question's history had got at the
+//! last sync, by its version and its count of history entries, since not
+//! every change (comments, for one) bumps the version. Since
+//! then, each side's change set holds the questions it added or changed and
+//! any tags it added. Syncing swaps the change sets. A question changed on
+//! only one side is copied whole to the other. One changed on both is a
+//! conflict, which a callback resolves. Afterwards both registries share a
+//! new sync point.
+//!
+//! Copied questions keep their histories, and the receiving side's audit
+//! log records a `QuestionSynced` entry. Removed tags, tag renames, settings
+//! and criteria are not synced.
+
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{Actor, Question, Registry};
+use crate::audit::AuditAction;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncPoint {
+    pub questions: BTreeMap<Uuid, Seen>,
+    pub tags: BTreeSet<String>
+}
+
+/// How far a question had got when it was last synced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Seen {
+    pub version: u64,
+    pub changes: usize
+}
+
+impl Seen {
+    fn of(question: &Question) -> Seen {
+        Seen { version: question.version, changes: question.history.len() }
+    }
+}
+
+/// What one registry did after `since`, in a form that can be sent across.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub since: SyncPoint,
+    pub tags: Vec<String>,
+    pub questions: Vec<Question>
+}
+
+/// How to settle a question changed on both sides.
+#[derive(Clone, Debug)]
+pub enum Resolution {
+    KeepLocal,
+    KeepRemote,
+    /// A merge of the two, made by the callback.
+    Replace(Box<Question>)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SyncReport {
+    /// Questions copied to the remote registry.
+    pub sent: usize,
+    /// Questions copied from it.
+    pub received: usize,
+    /// Identifiers of the questions changed on both sides.
+    pub conflicts: Vec<String>,
+    /// Where the two registries now agree, for the next sync.
+    pub point: SyncPoint
+}
+
+impl Registry {
+    /// The state to sync against next time.
+    pub fn sync_point(&self) -> SyncPoint {
+        SyncPoint {
+            questions: self.questions.values().map(|question| (question.identifier, Seen::of(question))).collect(),
+            tags: self.tags.iter().cloned().collect()
+        }
+    }
+
+    /// Questions added or changed, and tags added, after `since`.
+    pub fn changes_since(&self, since: &SyncPoint) -> ChangeSet {
+        let mut questions: Vec<Question> = self.questions.values()
+            .filter(|question| since.questions.get(&question.identifier) != Some(&Seen::of(question)))
+            .cloned()
+            .collect();
+        questions.sort_by_key(|question| question.identifier);
+        let mut tags: Vec<String> = self.tags.iter().filter(|tag| !since.tags.contains(*tag)).cloned().collect();
+        tags.sort();
+        ChangeSet { since: since.clone(), tags, questions }
+    }
+
+    /// Exchanges changes made since `since` with `remote`, calling `resolve`
+    /// with the local and remote copies of every question changed on both.
+    pub fn sync_with<F: FnMut(&Question, &Question) -> Resolution>(&mut self, remote: &mut Registry, since: &SyncPoint, mut resolve: F, actor: &Actor) -> SyncReport {
+        let local_changes = self.changes_since(since);
+        let remote_changes = remote.changes_since(since);
+        let mut remote_questions: BTreeMap<Uuid, Question> = remote_changes.questions.into_iter()
+            .map(|question| (question.identifier, question))
+            .collect();
+        let mut report = SyncReport::default();
+
+        for tag in &local_changes.tags {
+            remote.adopt_tag(tag, actor);
+        }
+        for tag in &remote_changes.tags {
+            self.adopt_tag(tag, actor);
+        }
+        for local in local_changes.questions {
+            match remote_questions.remove(&local.identifier) {
+                None => {
+                    remote.adopt_question(local, actor);
+                    report.sent += 1;
+                }
+                Some(other) => {
+                    report.conflicts.push(local.identifier.to_string());
+                    let version = local.version.max(other.version) + 1;
+                    let mut settled = match resolve(&local, &other) {
+                        Resolution::KeepLocal => local,
+                        Resolution::KeepRemote => other,
+                        Resolution::Replace(merged) => *merged
+                    };
+                    // Both sides move past either version, so neither copy looks current.
+                    settled.version = version;
+                    self.adopt_question(settled.clone(), actor);
+                    remote.adopt_question(settled, actor);
+                }
+            }
+        }
+        for (_, question) in remote_questions {
+            self.adopt_question(question, actor);
+            report.received += 1;
+        }
+        report.point = self.sync_point();
+        report
+    }
+
+    pub(crate) fn adopt_tag(&mut self, tag: &str, actor: &Actor) {
+        if self.tags.insert(tag.to_string()) {
+            self.audit_log.record(self.now(), actor, None, AuditAction::TagAdded(tag.to_string()));
+        }
+    }
+
+    /// Takes `question` as is, replacing any copy of it.
+    pub(crate) fn adopt_question(&mut self, question: Question, actor: &Actor) {
+        let uuid = question.identifier;
+        let tags: Vec<String> = question.tags.iter().cloned().collect();
+        if let Some(previous) = self.questions.insert(uuid, question) {
+            previous.tags.iter().for_each(|tag| self.index_untagged(uuid, tag));
+        }
+        tags.iter().for_each(|tag| self.index_tagged(uuid, tag));
+        self.audit_log.record(self.now(), actor, Some(uuid), AuditAction::QuestionSynced);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::sync::*;
+
+    #[test]
+    fn test_sync_exchanges_changes_and_resolves_conflicts() {
+        let luke = Actor::new("luke");
+        let ana = Actor::new("ana");
+        let mut laptop = Registry::new();
+        let shared = laptop.add_question(Question::new("Which queue?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let other = laptop.add_question(Question::new("Which cache?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let mut server = Registry::new();
+        let first = laptop.sync_with(&mut server, &SyncPoint::default(), |_, _| Resolution::KeepLocal, &luke);
+        assert_eq!((first.sent, first.received), (2, 0));
+        assert_eq!(server.sync_point(), first.point);
+
+        // Offline, both edit the shared question; only the server adds one and a tag.
+        laptop.edit_question_content(shared.clone(), "Which message queue?".to_string(), 0, &luke).unwrap();
+        server.edit_question_content(shared.clone(), "Which job queue?".to_string(), 0, &ana).unwrap();
+        laptop.add_comment(other.clone(), "Redis?".to_string(), None, &luke).unwrap();
+        server.add_tag(&"infra".to_string(), &ana).unwrap();
+        let added = server.add_question(Question::new("Which CI?".to_string(), ["infra".to_string()].iter().cloned().collect(), HashSet::new(), HashSet::new()), &ana).unwrap();
+
+        let second = laptop.sync_with(&mut server, &first.point, |_, _| Resolution::KeepRemote, &luke);
+        assert_eq!(second.conflicts, vec![shared.clone()]);
+        assert_eq!((second.sent, second.received), (1, 1));
+        assert_eq!(server.sync_point(), second.point);
+        assert_eq!(laptop.question(&shared).unwrap().content, "Which job queue?");
+        assert_eq!(server.question(&other).unwrap().comments.len(), 1);
+        assert_eq!(laptop.list_questions_with_tag("infra")[0].identifier, added);
+
+        let quiet = laptop.sync_with(&mut server, &second.point, |_, _| panic!("nothing to resolve"), &luke);
+        assert_eq!((quiet.sent, quiet.received), (0, 0));
+    }
+}
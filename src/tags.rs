@@ -0,0 +1,427 @@
+//! How tag names are compared.
+//!
+//! Under a normalization policy, tags that only differ in case, Unicode
+//! composition or surrounding whitespace are the same tag. The registry keeps
+//! the spelling the tag was created with and maps every other spelling onto
+//! it, when tags are added, when questions are validated, and in queries.
+//!
+//! Naming rules (`TagRules`) limit what new tag names may look like, so a
+//! shared registry doesn't fill up with junk tags.
+
+use std::collections::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "unicode-tags")]
+use unicode_normalization::UnicodeNormalization;
+use crate::{Actor, Question, QuestionSummary, Registry};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagNormalization {
+    pub case_insensitive: bool,
+    /// Compare NFC forms, so precomposed and combining accents match.
+    /// Needs the `unicode-tags` feature; without it this does nothing.
+    pub unicode: bool,
+    pub trim: bool
+}
+
+impl TagNormalization {
+    /// Everything on.
+    pub fn lenient() -> TagNormalization {
+        TagNormalization { case_insensitive: true, unicode: true, trim: true }
+    }
+
+    /// The form a new tag is stored in: trimmed and composed as configured,
+    /// but keeping its case.
+    pub fn clean(&self, tag: &str) -> String {
+        let tag = if self.trim { tag.trim() } else { tag };
+        self.compose(tag)
+    }
+
+    #[cfg(feature = "unicode-tags")]
+    fn compose(&self, tag: &str) -> String {
+        if self.unicode { tag.nfc().collect() } else { tag.to_string() }
+    }
+
+    #[cfg(not(feature = "unicode-tags"))]
+    fn compose(&self, tag: &str) -> String {
+        tag.to_string()
+    }
+
+    /// Two tags are the same when their keys are equal.
+    pub fn key(&self, tag: &str) -> String {
+        let clean = self.clean(tag);
+        if self.case_insensitive { clean.to_lowercase() } else { clean }
+    }
+}
+
+/// Characters a tag name may contain.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TagCharset {
+    #[default]
+    Any,
+    /// Letters and digits, plus the characters listed.
+    AlphanumericAnd(String),
+    /// A regular expression the whole tag must match. A pattern that
+    /// doesn't compile matches no tag.
+    #[cfg(feature = "tag-patterns")]
+    Pattern(String)
+}
+
+impl TagCharset {
+    fn allows(&self, character: char) -> bool {
+        match self {
+            TagCharset::Any => true,
+            TagCharset::AlphanumericAnd(extra) => character.is_alphanumeric() || extra.contains(character),
+            #[cfg(feature = "tag-patterns")]
+            TagCharset::Pattern(_) => true
+        }
+    }
+
+    /// The pattern `tag` doesn't match, if there is one.
+    #[cfg(feature = "tag-patterns")]
+    fn mismatched_pattern(&self, tag: &str) -> Option<&str> {
+        match self {
+            TagCharset::Pattern(pattern) => {
+                let matches = regex::Regex::new(&format!("^(?:{})$", pattern)).is_ok_and(|regex| regex.is_match(tag));
+                if matches { None } else { Some(pattern) }
+            }
+            _ => None
+        }
+    }
+
+    #[cfg(not(feature = "tag-patterns"))]
+    fn mismatched_pattern(&self, _tag: &str) -> Option<&str> {
+        None
+    }
+}
+
+/// Rules new tag names must follow. The default allows anything but an
+/// empty name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagRules {
+    /// In characters.
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub charset: TagCharset,
+    /// Prefixes kept for the registry's own tags, e.g. `system:`.
+    #[serde(default)]
+    pub reserved_prefixes: Vec<String>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagRuleViolation {
+    Empty,
+    TooLong { length: usize, max_length: usize },
+    /// The offending characters, each listed once.
+    DisallowedCharacters(Vec<char>),
+    /// The pattern of `TagCharset::Pattern` the tag doesn't match.
+    PatternMismatch(String),
+    ReservedPrefix(String)
+}
+
+impl Localized for TagRuleViolation {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            TagRuleViolation::Empty => messages::text(catalog, "tag.empty", &[]),
+            TagRuleViolation::TooLong { length, max_length } => {
+                messages::text(catalog, "tag.too-long", &[("length", length.to_string()), ("max_length", max_length.to_string())])
+            }
+            TagRuleViolation::DisallowedCharacters(characters) => {
+                let characters: Vec<String> = characters.iter().map(|character| format!("{:?}", character)).collect();
+                messages::text(catalog, "tag.disallowed-characters", &[("characters", characters.join(", "))])
+            }
+            TagRuleViolation::PatternMismatch(pattern) => messages::text(catalog, "tag.pattern-mismatch", &[("pattern", pattern.clone())]),
+            TagRuleViolation::ReservedPrefix(prefix) => messages::text(catalog, "tag.reserved-prefix", &[("prefix", prefix.clone())])
+        }
+    }
+}
+
+/// The message for a tag breaking `violations`.
+pub(crate) fn violations(catalog: &dyn Catalog, violations: &[TagRuleViolation]) -> String {
+    let violations: Vec<String> = violations.iter().map(|violation| violation.localize(catalog)).collect();
+    messages::text(catalog, "tag.invalid", &[("violations", violations.join("; "))])
+}
+
+impl TagRules {
+    /// Every rule `tag` breaks, or `Ok` if none.
+    pub fn check(&self, tag: &str) -> Result<(), Vec<TagRuleViolation>> {
+        let mut violations = Vec::new();
+        let length = tag.chars().count();
+        if length == 0 {
+            violations.push(TagRuleViolation::Empty);
+        }
+        if let Some(max_length) = self.max_length {
+            if length > max_length {
+                violations.push(TagRuleViolation::TooLong { length, max_length });
+            }
+        }
+        let mut disallowed: Vec<char> = Vec::new();
+        for character in tag.chars().filter(|character| !self.charset.allows(*character)) {
+            if !disallowed.contains(&character) {
+                disallowed.push(character);
+            }
+        }
+        if !disallowed.is_empty() {
+            violations.push(TagRuleViolation::DisallowedCharacters(disallowed));
+        }
+        if let Some(pattern) = self.charset.mismatched_pattern(tag) {
+            violations.push(TagRuleViolation::PatternMismatch(pattern.to_string()));
+        }
+        if let Some(prefix) = self.reserved_prefixes.iter().find(|prefix| tag.starts_with(prefix.as_str())) {
+            violations.push(TagRuleViolation::ReservedPrefix(prefix.clone()));
+        }
+        if violations.is_empty() { Result::Ok(()) } else { Result::Err(violations) }
+    }
+}
+
+#[derive(Debug)]
+pub enum TagNormalizationError {
+    /// Existing tags that would become the same tag under the new policy.
+    Collisions(Vec<Vec<String>>)
+}
+
+impl Localized for TagNormalizationError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            TagNormalizationError::Collisions(collisions) => {
+                let collisions: Vec<String> = collisions.iter().map(|tags| tags.join(", ")).collect();
+                messages::text(catalog, "tag.collisions", &[("collisions", collisions.join("; "))])
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RenameTagError {
+    DoesNotExist,
+    /// Another tag already has the new name.
+    AlreadyExists,
+    Invalid(Vec<TagRuleViolation>)
+}
+
+impl Localized for RenameTagError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            RenameTagError::DoesNotExist => messages::text(catalog, "tag.does-not-exist", &[]),
+            RenameTagError::AlreadyExists => messages::text(catalog, "tag.already-exists", &[]),
+            RenameTagError::Invalid(violations) => self::violations(catalog, violations)
+        }
+    }
+}
+
+impl Coded for TagNormalizationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            TagNormalizationError::Collisions(_) => ErrorCode::TagCollisions
+        }
+    }
+}
+
+impl Coded for RenameTagError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            RenameTagError::DoesNotExist => ErrorCode::TagNotFound,
+            RenameTagError::AlreadyExists => ErrorCode::TagAlreadyExists,
+            RenameTagError::Invalid(_) => ErrorCode::InvalidTag
+        }
+    }
+}
+
+messages::display_localized!(TagRuleViolation, TagNormalizationError, RenameTagError);
+
+impl Registry {
+    /// The stored spelling of `tag`, if the registry has it.
+    pub fn resolve_tag(&self, tag: &str) -> Option<&String> {
+        let normalization = self.settings.tag_normalization;
+        if normalization == TagNormalization::default() {
+            return self.tags.get(tag);
+        }
+        let key = normalization.key(tag);
+        self.tags.iter().find(|existing| normalization.key(existing) == key)
+    }
+
+    /// Maps every tag onto its stored spelling, or returns the ones that
+    /// don't exist.
+    pub(crate) fn resolve_tags(&self, tags: &HashSet<String>) -> Result<HashSet<String>, Vec<String>> {
+        let mut resolved = HashSet::new();
+        let mut missing = Vec::new();
+        for tag in tags {
+            match self.resolve_tag(tag) {
+                Some(existing) => { resolved.insert(existing.clone()); }
+                None => missing.push(tag.clone())
+            }
+        }
+        if missing.is_empty() { Result::Ok(resolved) } else { Result::Err(missing) }
+    }
+
+    pub fn tag_normalization(&self) -> TagNormalization {
+        self.settings.tag_normalization
+    }
+
+    /// Switches policy. Refused if existing tags would merge under it, since
+    /// which spelling to keep is for the caller to decide.
+    pub fn set_tag_normalization(&mut self, normalization: TagNormalization, actor: &Actor) -> Result<(), TagNormalizationError> {
+        let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+        for tag in &self.tags {
+            groups.entry(normalization.key(tag)).or_default().push(tag.clone());
+        }
+        let mut collisions: Vec<Vec<String>> = groups.into_values().filter(|group| group.len() > 1).collect();
+        if !collisions.is_empty() {
+            collisions.iter_mut().for_each(|group| group.sort());
+            collisions.sort();
+            return Result::Err(TagNormalizationError::Collisions(collisions));
+        }
+        self.settings.tag_normalization = normalization;
+        self.audit_log.record(self.now(), actor, None, AuditAction::SettingsChanged);
+        Result::Ok(())
+    }
+
+    pub fn tag_rules(&self) -> &TagRules {
+        &self.settings.tag_rules
+    }
+
+    /// Applies to tags added or renamed from now on; existing tags are kept.
+    pub fn set_tag_rules(&mut self, rules: TagRules, actor: &Actor) {
+        self.settings.tag_rules = rules;
+        self.audit_log.record(self.now(), actor, None, AuditAction::SettingsChanged);
+    }
+
+    /// Renames a tag on the registry and on every question carrying it, and
+    /// returns how many questions changed. The new name must follow the tag
+    /// rules and not clash with another tag.
+    pub fn rename_tag(&mut self, from: &str, to: &str, actor: &Actor) -> Result<usize, RenameTagError> {
+        let from = self.resolve_tag(from).cloned().ok_or(RenameTagError::DoesNotExist)?;
+        let to = self.settings.tag_normalization.clean(to);
+        self.settings.tag_rules.check(&to).map_err(RenameTagError::Invalid)?;
+        if self.resolve_tag(&to).is_some_and(|existing| existing != &from) {
+            return Result::Err(RenameTagError::AlreadyExists);
+        }
+        if to == from {
+            return Result::Ok(0);
+        }
+        self.tags.remove(&from);
+        self.tags.insert(to.clone());
+        let renamed = AuditAction::TagRenamed { from: from.clone(), to: to.clone() };
+        self.audit_log.record(self.now(), actor, None, renamed.clone());
+        let mut modified = Vec::new();
+        for question in self.questions.values_mut().filter(|question| question.tags.contains(&from)) {
+            question.tags.remove(&from);
+            question.tags.insert(to.clone());
+            question.version += 1;
+            modified.push(question.identifier);
+        }
+        for uuid in &modified {
+            self.index_untagged(*uuid, &from);
+            self.index_tagged(*uuid, &to);
+            self.record_change(actor, *uuid, renamed.clone());
+        }
+        Result::Ok(modified.len())
+    }
+
+    /// Summaries of the questions carrying `tag`, in any spelling that
+    /// normalizes to it. Like `list_questions`, deferred questions are left
+    /// out until they are due.
+    pub fn list_questions_with_tag(&self, tag: &str) -> Vec<QuestionSummary> {
+        let now = self.now();
+        let today = now.date_naive();
+        self.questions_with_tag(tag).into_iter()
+            .filter(|question| question.is_listed_by_default(today))
+            .map(|question| question.summary_at(now))
+            .collect()
+    }
+
+    pub(crate) fn questions_with_tag(&self, tag: &str) -> Vec<&Question> {
+        match self.resolve_tag(tag) {
+            Some(tag) => self.indexed_questions_with_tag(tag),
+            None => Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::tags::*;
+
+    #[test]
+    #[cfg(feature = "unicode-tags")]
+    fn test_spellings_resolve_to_the_stored_tag() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.set_tag_normalization(TagNormalization::lenient(), &luke).unwrap();
+        registry.add_tag(&"Infra".to_string(), &luke).unwrap();
+        assert!(registry.add_tag(&" INFRA ".to_string(), &luke).is_err());
+        // A precomposed "é" and an "e" with a combining accent.
+        registry.add_tag(&"Caf\u{e9}".to_string(), &luke).unwrap();
+
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("infra".to_string());
+        tags.insert("cafe\u{301}".to_string());
+        let identifier = registry.add_question(Question::new("Where?".to_string(), tags, HashSet::new(), HashSet::new()), &luke).unwrap();
+        let stored = registry.get_question(identifier).unwrap().get_tags();
+        assert!(stored.contains("Infra") && stored.contains("Caf\u{e9}"));
+        assert_eq!(registry.get_tags().len(), 2);
+        assert_eq!(registry.list_questions_with_tag("INFRA").len(), 1);
+    }
+
+    #[test]
+    fn test_policy_change_refuses_to_merge_tags() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"Infra".to_string(), &luke).unwrap();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        match registry.set_tag_normalization(TagNormalization::lenient(), &luke) {
+            Err(TagNormalizationError::Collisions(groups)) => assert_eq!(groups, vec![vec!["Infra".to_string(), "infra".to_string()]]),
+            _ => panic!("Tags that would merge should block the policy change")
+        }
+        assert_eq!(registry.tag_normalization(), TagNormalization::default());
+    }
+
+    #[test]
+    fn test_tag_rules_apply_to_new_and_renamed_tags() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"infra".to_string(), &luke).unwrap();
+        let mut tags: HashSet<String> = HashSet::new();
+        tags.insert("infra".to_string());
+        let identifier = registry.add_question(Question::new("Where?".to_string(), tags, HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.set_tag_rules(TagRules {
+            max_length: Some(8),
+            charset: TagCharset::AlphanumericAnd("-".to_string()),
+            reserved_prefixes: vec!["sys".to_string()]
+        }, &luke);
+
+        match registry.add_tag(&"sys tem/wide".to_string(), &luke) {
+            Err(AddTagErrors::Invalid(violations)) => assert_eq!(violations, vec![
+                TagRuleViolation::TooLong { length: 12, max_length: 8 },
+                TagRuleViolation::DisallowedCharacters(vec![' ', '/']),
+                TagRuleViolation::ReservedPrefix("sys".to_string())
+            ]),
+            _ => panic!("Tags breaking the rules should be rejected")
+        }
+        assert!(matches!(registry.rename_tag("infra", "", &luke), Err(RenameTagError::Invalid(_))));
+        assert_eq!(registry.rename_tag("infra", "platform", &luke).unwrap(), 1);
+        let question = registry.get_question(identifier).unwrap();
+        assert!(question.get_tags().contains("platform") && question.version == 1);
+        assert_eq!(registry.get_tags(), ["platform".to_string()].iter().cloned().collect());
+    }
+
+    #[cfg(feature = "tag-patterns")]
+    #[test]
+    fn test_tags_must_match_the_whole_pattern() {
+        let rules = TagRules { charset: TagCharset::Pattern("[a-z]+(-[a-z]+)*".to_string()), ..TagRules::default() };
+        assert!(rules.check("team-infra").is_ok());
+        assert_eq!(rules.check("Team infra"), Err(vec![TagRuleViolation::PatternMismatch("[a-z]+(-[a-z]+)*".to_string())]));
+        assert!(rules.check("infra-").is_err());
+
+        let broken = TagRules { charset: TagCharset::Pattern("[a-z".to_string()), ..TagRules::default() };
+        assert_eq!(broken.check("infra"), Err(vec![TagRuleViolation::PatternMismatch("[a-z".to_string())]));
+        assert_eq!(
+            TagRuleViolation::PatternMismatch("[a-z]+".to_string()).localize(&messages::English),
+            "it doesn't match the pattern \"[a-z]+\""
+        );
+    }
+}
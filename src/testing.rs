@@ -0,0 +1,228 @@
+//! Fixtures for tests against decis, behind the `test-util` feature.
+//!
+//! Registries made here run on a `FixedClock` at `EPOCH` and `SeededIds`,
+//! so identifiers and timestamps come out the same on every run.
+//!
+//! ```
+//! use decis::testing;
+//!
+//! let mut registry = testing::empty_registry();
+//! let id = testing::question("Which CI?").tags(["infra"]).decided("GitHub Actions").add_to(&mut registry);
+//! testing::assert_decided(&registry, &id, "GitHub Actions");
+//! ```
+
+use std::collections::HashSet;
+use chrono::{DateTime, TimeZone, Utc};
+use crate::{Actor, Decision, Question, Registry};
+use crate::audit::AuditAction;
+use crate::priority::Priority;
+use crate::status::QuestionStatus;
+
+pub use crate::clock::{FixedClock, SteppingClock};
+pub use crate::ids::SeededIds;
+
+/// When the fixture registries' clocks start.
+pub fn epoch() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap()
+}
+
+/// Who the fixtures act as.
+pub fn actor() -> Actor {
+    Actor::new("tester")
+}
+
+/// An empty registry on a fixed clock and seeded identifiers.
+pub fn empty_registry() -> Registry {
+    let mut registry = Registry::new();
+    registry.set_clock(FixedClock::new(epoch()));
+    registry.set_id_generator(SeededIds::new(0));
+    registry
+}
+
+/// A small registry with a question in each status, tagged `infra` or
+/// `billing`.
+pub fn sample_registry() -> Registry {
+    let mut registry = empty_registry();
+    question("Which CI service?").tags(["infra"]).options(["GitHub Actions", "Jenkins"]).add_to(&mut registry);
+    question("Which cloud?").tags(["infra"]).options(["AWS", "GCP"]).decided("AWS").add_to(&mut registry);
+    question("Bill annually?").tags(["billing"]).priority(Priority::High).add_to(&mut registry);
+    question("Move regions?").tags(["infra", "billing"]).deferred("After the migration").add_to(&mut registry);
+    question("Rewrite in Go?").cancelled("Not worth it").add_to(&mut registry);
+    registry
+}
+
+/// Starts describing a question to add to a registry.
+pub fn question(content: &str) -> QuestionBuilder {
+    QuestionBuilder {
+        content: content.to_string(),
+        tags: Vec::new(),
+        context: Vec::new(),
+        options: Vec::new(),
+        decision: None,
+        owner: None,
+        priority: None,
+        status: None
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct QuestionBuilder {
+    content: String,
+    tags: Vec<String>,
+    context: Vec<String>,
+    options: Vec<String>,
+    /// Choice and rationale.
+    decision: Option<(String, String)>,
+    owner: Option<Actor>,
+    priority: Option<Priority>,
+    /// Deferred or cancelled, with the reason.
+    status: Option<(QuestionStatus, String)>
+}
+
+fn strings<I: IntoIterator<Item = S>, S: AsRef<str>>(items: I) -> Vec<String> {
+    items.into_iter().map(|item| item.as_ref().to_string()).collect()
+}
+
+impl QuestionBuilder {
+    /// Tags missing from the registry are created when the question is added.
+    pub fn tags<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, tags: I) -> QuestionBuilder {
+        self.tags.extend(strings(tags));
+        self
+    }
+
+    pub fn context<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, context: I) -> QuestionBuilder {
+        self.context.extend(strings(context));
+        self
+    }
+
+    pub fn options<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, options: I) -> QuestionBuilder {
+        self.options.extend(strings(options));
+        self
+    }
+
+    /// Decides the question for `choice`, which is added as an option if it
+    /// isn't one already.
+    pub fn decided(self, choice: &str) -> QuestionBuilder {
+        self.decided_because(choice, "Decided in a test")
+    }
+
+    pub fn decided_because(mut self, choice: &str, rationale: &str) -> QuestionBuilder {
+        if !self.options.iter().any(|option| option == choice) {
+            self.options.push(choice.to_string());
+        }
+        self.decision = Some((choice.to_string(), rationale.to_string()));
+        self
+    }
+
+    pub fn owner(mut self, owner: &Actor) -> QuestionBuilder {
+        self.owner = Some(owner.clone());
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> QuestionBuilder {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn deferred(mut self, reason: &str) -> QuestionBuilder {
+        self.status = Some((QuestionStatus::Deferred, reason.to_string()));
+        self
+    }
+
+    pub fn cancelled(mut self, reason: &str) -> QuestionBuilder {
+        self.status = Some((QuestionStatus::Cancelled, reason.to_string()));
+        self
+    }
+
+    /// The question on its own, with its decision but without an owner,
+    /// priority or status, which only a registry can set.
+    pub fn build(&self) -> Question {
+        let mut question = Question::new(self.content.clone(), self.tags.iter().cloned().collect(), self.context.iter().cloned().collect(), self.options.iter().cloned().collect());
+        if let Some((choice, rationale)) = &self.decision {
+            question.set_decision(Decision::new(choice.clone(), rationale.clone(), HashSet::new())).expect("new questions are open");
+        }
+        question
+    }
+
+    /// Adds the question to `registry` as `testing::actor()`, through the
+    /// registry's own methods, and returns its identifier. Panics if the
+    /// registry refuses any step.
+    pub fn add_to(&self, registry: &mut Registry) -> String {
+        let actor = actor();
+        for tag in &self.tags {
+            if registry.resolve_tag(tag).is_none() {
+                registry.add_tag(tag, &actor).expect("fixture tag rejected");
+            }
+        }
+        let question = Question::new(self.content.clone(), self.tags.iter().cloned().collect(), self.context.iter().cloned().collect(), self.options.iter().cloned().collect());
+        let identifier = registry.add_question(question, &actor).expect("fixture question rejected");
+        let mut version = 0;
+        if let Some(owner) = &self.owner {
+            version = registry.set_question_owner(identifier.clone(), Some(owner.clone()), version, &actor).expect("fixture owner rejected");
+        }
+        if let Some(priority) = self.priority {
+            version = registry.set_question_priority(identifier.clone(), priority, version, &actor).expect("fixture priority rejected");
+        }
+        if let Some((choice, rationale)) = &self.decision {
+            let decision = Decision::new(choice.clone(), rationale.clone(), HashSet::new());
+            version = registry.set_question_decision(identifier.clone(), decision, version, &actor).expect("fixture decision rejected");
+        }
+        match &self.status {
+            Some((QuestionStatus::Deferred, reason)) => {
+                registry.defer_question(identifier.clone(), reason.clone(), None, version, &actor).expect("fixture deferral rejected");
+            }
+            Some((QuestionStatus::Cancelled, reason)) => {
+                registry.cancel_question(identifier.clone(), reason.clone(), version, &actor).expect("fixture cancellation rejected");
+            }
+            _ => ()
+        }
+        identifier
+    }
+}
+
+/// Panics unless the question is decided for `choice`.
+#[track_caller]
+pub fn assert_decided(registry: &Registry, identifier: &str, choice: &str) {
+    let question = registry.get_question(identifier.to_string()).unwrap_or_else(|error| panic!("no question {}: {:?}", identifier, error));
+    match question.get_decision() {
+        Some(decision) => assert_eq!(decision.get_choice(), choice, "question {:?} decided differently", question.content),
+        None => panic!("question {:?} is undecided", question.content)
+    }
+}
+
+#[track_caller]
+pub fn assert_status(registry: &Registry, identifier: &str, status: QuestionStatus) {
+    let question = registry.get_question(identifier.to_string()).unwrap_or_else(|error| panic!("no question {}: {:?}", identifier, error));
+    assert_eq!(question.status(), status, "status of question {:?}", question.content);
+}
+
+/// Panics unless the audit log has an entry with `action`.
+#[track_caller]
+pub fn assert_audited(registry: &Registry, action: &AuditAction) {
+    assert!(registry.audit_log().entries().iter().any(|entry| &entry.action == action), "no {:?} in the audit log", action);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Registry;
+    use crate::audit::AuditAction;
+    use crate::query::Sort;
+    use crate::status::QuestionStatus;
+    use crate::testing::*;
+
+    #[test]
+    fn test_fixtures_are_reproducible() {
+        let identifiers = |registry: &Registry| -> Vec<String> { registry.list_questions_sorted(Sort::default()).into_iter().map(|summary| summary.identifier).collect() };
+        let first = sample_registry();
+        assert_eq!(identifiers(&first), identifiers(&sample_registry()));
+        assert_eq!(first.audit_log().entries().last().unwrap().at, epoch());
+        assert_eq!(first.list_questions_in(&[QuestionStatus::Cancelled]).len(), 1);
+
+        let mut registry = empty_registry();
+        let identifier = question("Which CI?").tags(["infra"]).decided("GitHub Actions").add_to(&mut registry);
+        assert_decided(&registry, &identifier, "GitHub Actions");
+        assert_status(&registry, &identifier, QuestionStatus::Decided);
+        assert_audited(&registry, &AuditAction::TagAdded("infra".to_string()));
+        assert!(question("Which CD?").decided("Argo").build().get_decision().is_some());
+    }
+}
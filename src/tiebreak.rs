@@ -0,0 +1,153 @@
+//! Reproducible tie-breaking between equivalent options.
+//!
+//! Both pickers sort the options by name before drawing, so the same seed
+//! selects the same option wherever it runs and however the options are ordered.
+
+use std::collections::{HashMap, HashSet};
+use crate::{Decision, Question};
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+#[derive(Debug, PartialEq)]
+pub enum PickError {
+    NoOptions,
+    /// Weights must be finite, non-negative, name existing options, and not all be zero.
+    InvalidWeights
+}
+
+impl Localized for PickError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            PickError::NoOptions => messages::text(catalog, "pick.no-options", &[]),
+            PickError::InvalidWeights => messages::text(catalog, "pick.invalid-weights", &[])
+        }
+    }
+}
+
+impl Coded for PickError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            PickError::NoOptions => ErrorCode::NoOptions,
+            PickError::InvalidWeights => ErrorCode::InvalidWeights
+        }
+    }
+}
+
+messages::display_localized!(PickError);
+
+/// SplitMix64: tiny, and stable across platforms and releases, unlike the
+/// generators in `rand`. Also drives `simulate`.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1).
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Question {
+    fn sorted_options(&self) -> Vec<&String> {
+        let mut options: Vec<&String> = self.options.iter().collect();
+        options.sort();
+        options
+    }
+
+    /// Picks one of the options uniformly at random and returns an unrecorded
+    /// decision for it, with the method and seed in the rationale, written in
+    /// the catalog's language.
+    pub fn pick_random(&self, seed: u64, catalog: &dyn Catalog) -> Result<Decision, PickError> {
+        let options = self.sorted_options();
+        if options.is_empty() {
+            return Result::Err(PickError::NoOptions);
+        }
+        let choice = options[(SplitMix64(seed).next() % options.len() as u64) as usize];
+        let rationale = messages::text(catalog, "pick.random", &[("seed", seed.to_string()), ("count", options.len().to_string())]);
+        Result::Ok(Decision::new(choice.clone(), rationale, HashSet::new()))
+    }
+
+    /// Picks an option with probability proportional to its weight. Options
+    /// without a weight are never picked.
+    pub fn pick_weighted(&self, weights: &HashMap<String, f64>, seed: u64, catalog: &dyn Catalog) -> Result<Decision, PickError> {
+        if self.options.is_empty() {
+            return Result::Err(PickError::NoOptions);
+        }
+        if weights.iter().any(|(option, weight)| !self.has_option(option) || !weight.is_finite() || *weight < 0.0) {
+            return Result::Err(PickError::InvalidWeights);
+        }
+        let weighted: Vec<(&String, f64)> = self.sorted_options()
+            .into_iter()
+            .map(|option| (option, weights.get(option).copied().unwrap_or(0.0)))
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect();
+        let total: f64 = weighted.iter().map(|(_, weight)| weight).sum();
+        if weighted.is_empty() {
+            return Result::Err(PickError::InvalidWeights);
+        }
+
+        let mut remaining = SplitMix64(seed).next_f64() * total;
+        let mut choice = weighted[weighted.len() - 1].0;
+        for (option, weight) in &weighted {
+            if remaining < *weight {
+                choice = option;
+                break;
+            }
+            remaining -= weight;
+        }
+        let listed: Vec<String> = weighted.iter().map(|(option, weight)| format!("{}={}", option, weight)).collect();
+        let rationale = messages::text(catalog, "pick.weighted", &[("seed", seed.to_string()), ("weights", listed.join(", "))]);
+        Result::Ok(Decision::new(choice.clone(), rationale, HashSet::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use crate::*;
+    use crate::messages::{English, Translations};
+    use crate::tiebreak::*;
+
+    fn question_with_options(options: &[&str]) -> Question {
+        let options: HashSet<String> = options.iter().map(|option| option.to_string()).collect();
+        Question::new("Which colour for the bikeshed?".to_string(), HashSet::new(), HashSet::new(), options)
+    }
+
+    #[test]
+    fn test_pick_random_is_reproducible() {
+        let question = question_with_options(&["Red", "Green", "Blue", "Yellow"]);
+        let first = question.pick_random(7, &English).unwrap();
+        for _ in 0..10 {
+            assert_eq!(question_with_options(&["Yellow", "Blue", "Green", "Red"]).pick_random(7, &English).unwrap().choice, first.choice);
+        }
+        assert!(first.rationale.contains("seed 7"));
+        let german = Translations::new().with("pick.random", "Zufällig gewählt (Seed {seed})");
+        assert_eq!(question.pick_random(7, &german).unwrap().rationale, "Zufällig gewählt (Seed 7)");
+    }
+
+    #[test]
+    fn test_pick_weighted_never_picks_zero_weight() {
+        let question = question_with_options(&["Red", "Green"]);
+        let mut weights: HashMap<String, f64> = HashMap::new();
+        weights.insert("Red".to_string(), 0.0);
+        weights.insert("Green".to_string(), 1.0);
+        for seed in 0..50 {
+            assert_eq!(question.pick_weighted(&weights, seed, &English).unwrap().choice, "Green");
+        }
+    }
+
+    #[test]
+    fn test_pick_rejects_bad_input() {
+        assert_eq!(question_with_options(&[]).pick_random(1, &English).unwrap_err(), PickError::NoOptions);
+        let mut weights: HashMap<String, f64> = HashMap::new();
+        weights.insert("Purple".to_string(), 1.0);
+        assert_eq!(question_with_options(&["Red"]).pick_weighted(&weights, 1, &English).unwrap_err(), PickError::InvalidWeights);
+    }
+}
@@ -0,0 +1,314 @@
+//! Multi-stage decisions.
+//!
+//! An option can lead to one or more branches, each reached with some
+//! probability, worth a payoff, and optionally leading on to a follow-up
+//! question. A single certain branch models "if we pick vendor A we then have
+//! to choose a plan"; several model chance outcomes. From a question the
+//! whole tree can be walked and its expected value rolled up.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::{Actor, GetQuestionError, Question, Registry, UpdateQuestionError};
+use crate::audit::AuditAction;
+use crate::codes::{Coded, ErrorCode};
+use crate::messages::{self, Catalog, Localized};
+
+/// Probabilities of an option's branches may be off from 1 by this much.
+const PROBABILITY_TOLERANCE: f64 = 1e-9;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Branch {
+    pub probability: f64,
+    pub payoff: f64,
+    pub follow_up: Option<Uuid>
+}
+
+impl Branch {
+    pub fn new(probability: f64, payoff: f64, follow_up: Option<Uuid>) -> Branch {
+        Branch { probability, payoff, follow_up }
+    }
+
+    /// The option always leads to `follow_up`, with no payoff of its own.
+    pub fn certain(follow_up: Uuid) -> Branch {
+        Branch::new(1.0, 0.0, Some(follow_up))
+    }
+}
+
+#[derive(Debug)]
+pub enum SetBranchesError {
+    Question(GetQuestionError),
+    Conflict(Box<Question>),
+    UnknownOption(String),
+    /// Probabilities must lie in 0..=1 and add up to 1, payoffs must be finite.
+    InvalidProbabilities,
+    UnknownFollowUp(Uuid),
+    /// The follow-ups would lead back to the question itself.
+    Cycle
+}
+
+impl Localized for SetBranchesError {
+    fn localize(&self, catalog: &dyn Catalog) -> String {
+        match self {
+            SetBranchesError::Question(error) => error.localize(catalog),
+            SetBranchesError::Conflict(question) => messages::conflict(catalog, question),
+            SetBranchesError::UnknownOption(option) => messages::text(catalog, "option.unknown", &[("option", option.clone())]),
+            SetBranchesError::InvalidProbabilities => messages::text(catalog, "branches.invalid-probabilities", &[]),
+            SetBranchesError::UnknownFollowUp(identifier) => {
+                messages::text(catalog, "branches.unknown-follow-up", &[("identifier", identifier.to_string())])
+            }
+            SetBranchesError::Cycle => messages::text(catalog, "branches.cycle", &[])
+        }
+    }
+}
+
+impl Coded for SetBranchesError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SetBranchesError::Question(error) => error.code(),
+            SetBranchesError::Conflict(_) => ErrorCode::VersionConflict,
+            SetBranchesError::UnknownOption(_) => ErrorCode::UnknownOption,
+            SetBranchesError::InvalidProbabilities => ErrorCode::InvalidProbabilities,
+            SetBranchesError::UnknownFollowUp(_) => ErrorCode::UnknownFollowUp,
+            SetBranchesError::Cycle => ErrorCode::FollowUpCycle
+        }
+    }
+}
+
+messages::display_localized!(SetBranchesError);
+
+impl From<UpdateQuestionError> for SetBranchesError {
+    fn from(error: UpdateQuestionError) -> SetBranchesError {
+        match error {
+            UpdateQuestionError::Question(error) => SetBranchesError::Question(error),
+            UpdateQuestionError::Conflict(current) => SetBranchesError::Conflict(current)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TreeNode {
+    pub identifier: String,
+    pub content: String,
+    /// The chosen option, once the question is decided.
+    pub chosen: Option<String>,
+    /// Only options that have branches, sorted by name.
+    pub options: Vec<OptionNode>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct OptionNode {
+    pub option: String,
+    pub branches: Vec<BranchNode>
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BranchNode {
+    pub probability: f64,
+    pub payoff: f64,
+    pub follow_up: Option<Box<TreeNode>>
+}
+
+impl OptionNode {
+    pub fn expected_value(&self) -> f64 {
+        self.branches.iter()
+            .map(|branch| {
+                let onward = branch.follow_up.as_ref().and_then(|node| node.expected_value()).unwrap_or(0.0);
+                branch.probability * (branch.payoff + onward)
+            })
+            .sum()
+    }
+}
+
+impl TreeNode {
+    /// The value of the chosen option if the question is decided, otherwise
+    /// of the best option. `None` when no option has branches.
+    pub fn expected_value(&self) -> Option<f64> {
+        if let Some(chosen) = &self.chosen {
+            return self.options.iter().find(|node| &node.option == chosen).map(OptionNode::expected_value);
+        }
+        self.options.iter().map(OptionNode::expected_value).fold(None, |best, value| match best {
+            Some(best) if best >= value => Some(best),
+            _ => Some(value)
+        })
+    }
+
+    /// The undecided option with the highest expected value.
+    pub fn best_option(&self) -> Option<&OptionNode> {
+        self.options.iter().fold(None, |best: Option<&OptionNode>, node| match best {
+            Some(best) if best.expected_value() >= node.expected_value() => Some(best),
+            _ => Some(node)
+        })
+    }
+
+    /// Identifiers of this question and every follow-up, depth first.
+    pub fn identifiers(&self) -> Vec<String> {
+        let mut identifiers = vec![self.identifier.clone()];
+        for node in &self.options {
+            for branch in &node.branches {
+                if let Some(follow_up) = &branch.follow_up {
+                    identifiers.extend(follow_up.identifiers());
+                }
+            }
+        }
+        identifiers
+    }
+}
+
+impl Question {
+    pub fn get_branches(&self, option: &str) -> Vec<Branch> {
+        self.branches.get(option).cloned().unwrap_or_default()
+    }
+}
+
+impl Registry {
+    /// Replaces the branches of one of the question's options (an empty list
+    /// removes them) and returns the question's new version.
+    pub fn set_option_branches(&mut self, identifier: String, option: &str, branches: Vec<Branch>, expected_version: u64, actor: &Actor) -> Result<u64, SetBranchesError> {
+        let valid = branches.iter().all(|branch| (0.0..=1.0).contains(&branch.probability) && branch.payoff.is_finite());
+        let total: f64 = branches.iter().map(|branch| branch.probability).sum();
+        if !valid || (!branches.is_empty() && (total - 1.0).abs() > PROBABILITY_TOLERANCE) {
+            return Result::Err(SetBranchesError::InvalidProbabilities);
+        }
+        let uuid = self.get_question_for_update(identifier, expected_version)?.identifier;
+        for follow_up in branches.iter().filter_map(|branch| branch.follow_up) {
+            if !self.questions.contains_key(&follow_up) {
+                return Result::Err(SetBranchesError::UnknownFollowUp(follow_up));
+            }
+            if follow_up == uuid || self.reachable_from(follow_up).contains(&uuid) {
+                return Result::Err(SetBranchesError::Cycle);
+            }
+        }
+
+        let question = self.questions.get_mut(&uuid).unwrap();
+        if !question.has_option(option) {
+            return Result::Err(SetBranchesError::UnknownOption(option.to_string()));
+        }
+        if branches.is_empty() {
+            question.branches.remove(option);
+        } else {
+            question.branches.insert(option.to_string(), branches);
+        }
+        question.version += 1;
+        let version = question.version;
+        self.record_change(actor, uuid, AuditAction::BranchesSet(option.to_string()));
+        Result::Ok(version)
+    }
+
+    fn reachable_from(&self, start: Uuid) -> HashSet<Uuid> {
+        let mut seen: HashSet<Uuid> = HashSet::new();
+        let mut pending = vec![start];
+        while let Some(identifier) = pending.pop() {
+            if !seen.insert(identifier) {
+                continue;
+            }
+            if let Some(question) = self.questions.get(&identifier) {
+                pending.extend(question.branches.values().flatten().filter_map(|branch| branch.follow_up));
+            }
+        }
+        seen
+    }
+
+    /// The follow-up questions directly reachable from each option, as
+    /// `(option, follow-up identifier)` pairs.
+    pub fn follow_ups(&self, identifier: String) -> Result<Vec<(String, String)>, GetQuestionError> {
+        let question = self.get_question_ref(&identifier)?;
+        Result::Ok(question.branches.iter()
+            .flat_map(|(option, branches)| branches.iter()
+                .filter_map(move |branch| branch.follow_up.map(|follow_up| (option.clone(), follow_up.to_string()))))
+            .collect())
+    }
+
+    /// The tree of options, branches and follow-ups rooted at the question.
+    pub fn decision_tree(&self, identifier: String) -> Result<TreeNode, GetQuestionError> {
+        Result::Ok(self.tree_node(self.get_question_ref(&identifier)?))
+    }
+
+    pub fn expected_value(&self, identifier: String) -> Result<Option<f64>, GetQuestionError> {
+        Result::Ok(self.decision_tree(identifier)?.expected_value())
+    }
+
+    fn get_question_ref(&self, identifier: &str) -> Result<&Question, GetQuestionError> {
+        match Uuid::from_str(identifier) {
+            Ok(uuid) => self.questions.get(&uuid).ok_or(GetQuestionError::DoesNotExist),
+            _ => Result::Err(GetQuestionError::InvalidUUID)
+        }
+    }
+
+    // Cycles are rejected when branches are set, so this terminates.
+    fn tree_node(&self, question: &Question) -> TreeNode {
+        let options = question.branches.iter().map(|(option, branches)| OptionNode {
+            option: option.clone(),
+            branches: branches.iter().map(|branch| BranchNode {
+                probability: branch.probability,
+                payoff: branch.payoff,
+                follow_up: branch.follow_up
+                    .and_then(|follow_up| self.questions.get(&follow_up))
+                    .map(|follow_up| Box::new(self.tree_node(follow_up)))
+            }).collect()
+        }).collect();
+        TreeNode {
+            identifier: question.identifier.to_string(),
+            content: question.content.clone(),
+            chosen: question.decision.as_ref().map(|decision| decision.choice.clone()),
+            options
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use uuid::Uuid;
+    use crate::*;
+    use crate::tree::*;
+
+    fn question(content: &str, options: &[&str]) -> Question {
+        let options: HashSet<String> = options.iter().map(|option| option.to_string()).collect();
+        Question::new(content.to_string(), HashSet::new(), HashSet::new(), options)
+    }
+
+    #[test]
+    fn test_expected_value_rolls_up_through_follow_ups() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let vendor = registry.add_question(question("Which vendor?", &["A", "B"]), &luke).unwrap();
+        let plan = registry.add_question(question("Which plan with A?", &["X", "Y"]), &luke).unwrap();
+        let plan_uuid = Uuid::parse_str(&plan).unwrap();
+
+        registry.set_option_branches(plan.clone(), "X", vec![Branch::new(0.5, 100.0, None), Branch::new(0.5, 0.0, None)], 0, &luke).unwrap();
+        registry.set_option_branches(plan.clone(), "Y", vec![Branch::new(1.0, 40.0, None)], 1, &luke).unwrap();
+        registry.set_option_branches(vendor.clone(), "A", vec![Branch::new(1.0, -10.0, Some(plan_uuid))], 0, &luke).unwrap();
+        registry.set_option_branches(vendor.clone(), "B", vec![Branch::new(1.0, 30.0, None)], 1, &luke).unwrap();
+
+        // A: -10 + max(50, 40) = 40 beats B's 30.
+        let tree = registry.decision_tree(vendor.clone()).unwrap();
+        assert_eq!(tree.expected_value(), Some(40.0));
+        assert_eq!(tree.best_option().unwrap().option, "A");
+        assert_eq!(tree.identifiers(), vec![vendor.clone(), plan.clone()]);
+        assert_eq!(registry.follow_ups(vendor).unwrap(), vec![("A".to_string(), plan)]);
+    }
+
+    #[test]
+    fn test_branches_are_validated() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let first = registry.add_question(question("First?", &["Go"]), &luke).unwrap();
+        let second = registry.add_question(question("Second?", &["Back"]), &luke).unwrap();
+        registry.set_option_branches(first.clone(), "Go", vec![Branch::certain(Uuid::parse_str(&second).unwrap())], 0, &luke).unwrap();
+
+        match registry.set_option_branches(second.clone(), "Back", vec![Branch::certain(Uuid::parse_str(&first).unwrap())], 0, &luke) {
+            Err(SetBranchesError::Cycle) => (),
+            _ => panic!("A follow-up leading back to the question should be rejected")
+        }
+        match registry.set_option_branches(second.clone(), "Back", vec![Branch::new(0.5, 1.0, None)], 0, &luke) {
+            Err(SetBranchesError::InvalidProbabilities) => (),
+            _ => panic!("Probabilities that don't add up to 1 should be rejected")
+        }
+        match registry.set_option_branches(second, "Elsewhere", vec![Branch::new(1.0, 1.0, None)], 0, &luke) {
+            Err(SetBranchesError::UnknownOption(_)) => (),
+            _ => panic!("Branches for unknown options should be rejected")
+        }
+    }
+}
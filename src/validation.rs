@@ -0,0 +1,100 @@
+//! Hooks for an organisation's own decision hygiene rules.
+//!
+//! A [`Validator`] registered with `Registry::add_validator` sees every
+//! question before it is added and every decision before it is set, and can
+//! reject either with a message such as "questions tagged `security` must
+//! include a threat-model context item". Validators are code, so they are not
+//! saved with the registry and have to be registered again after loading.
+
+use std::sync::Arc;
+use crate::{Decision, Question, Registry};
+use crate::messages::Catalog;
+
+pub trait Validator: Send + Sync {
+    /// Called with the question as it would be stored, tags resolved.
+    fn validate_question(&self, _question: &Question) -> Result<(), String> {
+        Result::Ok(())
+    }
+
+    /// Called with the question as it is before the decision is set.
+    fn validate_decision(&self, _question: &Question, _decision: &Decision) -> Result<(), String> {
+        Result::Ok(())
+    }
+
+    /// What the registry calls, with its catalog for the message. Validators
+    /// with translated messages override this rather than `validate_question`.
+    fn validate_question_with(&self, question: &Question, _catalog: &dyn Catalog) -> Result<(), String> {
+        self.validate_question(question)
+    }
+
+    /// Like `validate_question_with`, for decisions.
+    fn validate_decision_with(&self, question: &Question, decision: &Decision, _catalog: &dyn Catalog) -> Result<(), String> {
+        self.validate_decision(question, decision)
+    }
+}
+
+impl Registry {
+    /// Validators run in the order they were added, and all of them run, so
+    /// a rejection lists every message.
+    pub fn add_validator<V: Validator + 'static>(&mut self, validator: V) {
+        self.validators.push(Arc::new(validator));
+    }
+
+    pub(crate) fn check_question(&self, question: &Question) -> Result<(), Vec<String>> {
+        rejections(self.validators.iter().map(|validator| validator.validate_question_with(question, self.catalog())))
+    }
+
+    pub(crate) fn check_decision(&self, question: &Question, decision: &Decision) -> Result<(), Vec<String>> {
+        rejections(self.validators.iter().map(|validator| validator.validate_decision_with(question, decision, self.catalog())))
+    }
+}
+
+fn rejections<I: Iterator<Item = Result<(), String>>>(results: I) -> Result<(), Vec<String>> {
+    let messages: Vec<String> = results.filter_map(Result::err).collect();
+    if messages.is_empty() { Result::Ok(()) } else { Result::Err(messages) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+
+    struct ThreatModel;
+
+    impl Validator for ThreatModel {
+        fn validate_question(&self, question: &Question) -> Result<(), String> {
+            let needs_model = question.get_tags().contains("security");
+            if needs_model && !question.context_items().iter().any(|item| item.text.starts_with("Threat model")) {
+                return Result::Err("questions tagged `security` must include a threat-model context item".to_string());
+            }
+            Result::Ok(())
+        }
+
+        fn validate_decision(&self, _question: &Question, decision: &Decision) -> Result<(), String> {
+            if decision.get_rationale().is_empty() { Result::Err("decisions need a rationale".to_string()) } else { Result::Ok(()) }
+        }
+    }
+
+    #[test]
+    fn test_validators_reject_questions_and_decisions() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"security".to_string(), &luke).unwrap();
+        registry.add_validator(ThreatModel);
+
+        let tags: HashSet<String> = ["security".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["Yes".to_string()].iter().cloned().collect();
+        match registry.add_question(Question::new("Open the port?".to_string(), tags.clone(), HashSet::new(), options.clone()), &luke) {
+            Err(AddQuestionError::Rejected(messages)) => assert_eq!(messages.len(), 1),
+            _ => panic!("The validator should reject the question")
+        }
+
+        let context: HashSet<String> = ["Threat model: internal only".to_string()].iter().cloned().collect();
+        let identifier = registry.add_question(Question::new("Open the port?".to_string(), tags, context, options), &luke).unwrap();
+        let decision = Decision::new("Yes".to_string(), String::new(), HashSet::new());
+        match registry.set_question_decision(identifier, decision, 0, &luke) {
+            Err(SetQuestionDecisionError::Rejected(messages)) => assert_eq!(messages, vec!["decisions need a rationale".to_string()]),
+            _ => panic!("The validator should reject the decision")
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Export to a folder of Markdown notes, as Obsidian and Logseq read them.
+//!
+//! Each question becomes one note, named after its content. YAML frontmatter
+//! holds the tags, status, owner, priority and dates. The body holds the
+//! context, options and decision. Follow-up questions from `tree` are linked
+//! with `[[wikilinks]]`, so the vault's backlinks show where each question
+//! came from.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+use crate::{Question, Registry};
+
+/// Longest note name, in characters, before the collision suffix.
+const MAX_NAME: usize = 100;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VaultNote {
+    /// Without the `.md` extension; also what links to the note use.
+    pub name: String,
+    pub contents: String
+}
+
+/// `content` with the characters note names can't contain removed.
+fn note_name(content: &str) -> String {
+    let cleaned: String = content.chars()
+        .filter(|character| !"/\\:*?\"<>|#^[]".contains(*character) && !character.is_control())
+        .take(MAX_NAME)
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').to_string();
+    if cleaned.is_empty() { "Untitled".to_string() } else { cleaned }
+}
+
+/// A double-quoted YAML string.
+fn yaml(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+impl Registry {
+    /// Note names by question, unique even when contents clash. Names are
+    /// compared in lowercase, as case-insensitive file systems do, and the
+    /// suffixes added to clashing names never reuse a name already taken.
+    fn note_names(&self) -> HashMap<Uuid, String> {
+        let mut by_key: BTreeMap<String, Vec<(Uuid, String)>> = BTreeMap::new();
+        for question in self.questions.values() {
+            let name = note_name(&question.content);
+            by_key.entry(name.to_lowercase()).or_default().push((question.identifier, name));
+        }
+        let mut names = HashMap::new();
+        let mut taken: HashSet<String> = HashSet::new();
+        for (key, notes) in &by_key {
+            if let [(identifier, name)] = notes.as_slice() {
+                names.insert(*identifier, name.clone());
+                taken.insert(key.clone());
+            }
+        }
+        for mut notes in by_key.into_values().filter(|notes| notes.len() > 1) {
+            notes.sort();
+            for (identifier, name) in notes {
+                let id = identifier.to_string();
+                let candidates = [format!("{} ({})", name, &id[..8]), format!("{} ({})", name, id)];
+                let unique = candidates.iter().cloned()
+                    .chain((2..).map(|count| format!("{} ({}) {}", name, id, count)))
+                    .find(|candidate| !taken.contains(&candidate.to_lowercase()))
+                    .expect("the counter runs until a name is free");
+                taken.insert(unique.to_lowercase());
+                names.insert(identifier, unique);
+            }
+        }
+        names
+    }
+
+    fn vault_note(&self, question: &Question, names: &HashMap<Uuid, String>) -> String {
+        let mut note = String::from("---\n");
+        note.push_str(&format!("id: {}\n", question.identifier));
+        let mut tags: Vec<String> = question.tags.iter()
+            .map(|tag| tag.split_whitespace().collect::<Vec<&str>>().join("-"))
+            .collect();
+        tags.sort();
+        if tags.is_empty() {
+            note.push_str("tags: []\n");
+        } else {
+            note.push_str("tags:\n");
+            tags.iter().for_each(|tag| note.push_str(&format!("  - {}\n", yaml(tag))));
+        }
+        note.push_str(&format!("status: {}\n", format!("{:?}", question.status()).to_lowercase()));
+        note.push_str(&format!("priority: {}\n", format!("{:?}", question.priority).to_lowercase()));
+        if let Some(owner) = &question.owner {
+            note.push_str(&format!("owner: {}\n", yaml(owner.id())));
+        }
+        let dates = [
+            ("created", self.created_at(question)),
+            ("updated", question.history.last().map(|change| change.at)),
+            ("decided", self.decided_at(question))
+        ];
+        for (key, at) in dates.iter() {
+            if let Some(at) = at {
+                note.push_str(&format!("{}: {}\n", key, at.to_rfc3339()));
+            }
+        }
+        note.push_str("---\n\n");
+        note.push_str(&format!("# {}\n", question.content));
+
+        if !question.context.is_empty() {
+            note.push_str(&format!("\n## {}\n\n", self.text("vault.context", &[])));
+            question.context.iter().for_each(|item| note.push_str(&format!("- {}\n", item.to_markdown(self.catalog()))));
+        }
+        if !question.options.is_empty() {
+            note.push_str(&format!("\n## {}\n\n", self.text("vault.options", &[])));
+            for option in &question.options {
+                note.push_str(&format!("- {}\n", option));
+                let follow_ups = question.branches.get(option).into_iter().flatten()
+                    .filter_map(|branch| branch.follow_up.and_then(|follow_up| names.get(&follow_up)));
+                for name in follow_ups {
+                    note.push_str(&format!("  - {}\n", self.text("vault.leads-to", &[("link", format!("[[{}]]", name))])));
+                }
+            }
+        }
+        if let Some(decision) = &question.decision {
+            note.push_str(&format!("\n## {}\n\n**{}**\n", self.text("vault.decision", &[]), decision.choice));
+            if !decision.rationale.is_empty() {
+                note.push_str(&format!("\n{}\n", decision.rationale));
+            }
+            let mut makers: Vec<&str> = decision.decision_makers.iter().map(|maker| maker.id()).collect();
+            if !makers.is_empty() {
+                makers.sort();
+                note.push_str(&format!("\n{}\n", self.text("vault.decided-by", &[("makers", makers.join(", "))])));
+            }
+        }
+        note
+    }
+
+    /// One note per question, ordered by name.
+    pub fn vault_notes(&self) -> Vec<VaultNote> {
+        let names = self.note_names();
+        let mut notes: Vec<VaultNote> = self.questions.values()
+            .map(|question| VaultNote { name: names[&question.identifier].clone(), contents: self.vault_note(question, &names) })
+            .collect();
+        notes.sort_by(|a, b| a.name.cmp(&b.name));
+        notes
+    }
+
+    /// Writes the notes into `directory` as `<name>.md`, creating it if
+    /// needed and overwriting notes of the same name. Returns how many were
+    /// written. Fails with `AlreadyExists`, before writing anything, if two
+    /// notes would end up at the same path.
+    pub fn export_vault<P: AsRef<Path>>(&self, directory: P) -> std::io::Result<usize> {
+        let notes = self.vault_notes();
+        let mut paths: HashSet<String> = HashSet::new();
+        if let Some(note) = notes.iter().find(|note| !paths.insert(note.name.to_lowercase())) {
+            let message = format!("more than one note would be written to {}.md", note.name);
+            return Result::Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, message));
+        }
+        std::fs::create_dir_all(directory.as_ref())?;
+        for note in &notes {
+            std::fs::write(directory.as_ref().join(format!("{}.md", note.name)), &note.contents)?;
+        }
+        Result::Ok(notes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::*;
+    use crate::tree::Branch;
+
+    #[test]
+    fn test_notes_have_frontmatter_and_links() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        registry.add_tag(&"cloud infra".to_string(), &luke).unwrap();
+        let tags: HashSet<String> = ["cloud infra".to_string()].iter().cloned().collect();
+        let options: HashSet<String> = ["AWS".to_string()].iter().cloned().collect();
+        let vendor = registry.add_question(Question::new("Which cloud: AWS or GCP?".to_string(), tags, HashSet::new(), options), &luke).unwrap();
+        let plan = registry.add_question(Question::new("Which AWS plan?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        registry.add_question(Question::new("Which AWS plan?".to_string(), HashSet::new(), HashSet::new(), HashSet::new()), &luke).unwrap();
+        let plan_id = registry.question(&plan).unwrap().identifier;
+        registry.set_option_branches(vendor.clone(), "AWS", vec![Branch::certain(plan_id)], 0, &luke).unwrap();
+        let makers: HashSet<Actor> = [luke.clone()].iter().cloned().collect();
+        registry.set_question_decision(vendor, Decision::new("AWS".to_string(), "Credits".to_string(), makers), 1, &luke).unwrap();
+
+        let notes = registry.vault_notes();
+        assert_eq!(notes.len(), 3);
+        let plan_name = format!("Which AWS plan ({})", &plan[..8]);
+        assert!(notes.iter().any(|note| note.name == plan_name));
+        let vendor_note = notes.iter().find(|note| note.name == "Which cloud AWS or GCP").unwrap();
+        assert!(vendor_note.contents.starts_with("---\nid: "));
+        assert!(vendor_note.contents.contains("tags:\n  - \"cloud-infra\"\nstatus: decided\npriority: normal\ncreated: "));
+        assert!(vendor_note.contents.contains(&format!("- AWS\n  - leads to [[{}]]\n", plan_name)));
+        assert!(vendor_note.contents.ends_with("## Decision\n\n**AWS**\n\nCredits\n\nDecided by luke.\n"));
+
+        let directory = std::env::temp_dir().join(format!("decis-vault-{}", uuid::Uuid::new_v4()));
+        assert_eq!(registry.export_vault(&directory).unwrap(), 3);
+        assert!(directory.join(format!("{}.md", plan_name)).exists());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_note_names_differ_in_more_than_case() {
+        let luke = Actor::new("luke");
+        let mut registry = Registry::new();
+        let question = |content: &str, id: &str| {
+            Question::new(content.to_string(), HashSet::new(), HashSet::new(), HashSet::new()).with_id(uuid::Uuid::parse_str(id).unwrap())
+        };
+        let upper = "11111111-1111-4111-8111-111111111111";
+        registry.add_question(question("Which queue?", upper), &luke).unwrap();
+        registry.add_question(question("which queue?", "22222222-2222-4222-8222-222222222222"), &luke).unwrap();
+        registry.add_question(question("Which queue (11111111)", "33333333-3333-4333-8333-333333333333"), &luke).unwrap();
+
+        let names: Vec<String> = registry.vault_notes().into_iter().map(|note| note.name).collect();
+        assert_eq!(names, vec![
+            "Which queue (11111111)".to_string(),
+            format!("Which queue ({})", upper),
+            "which queue (22222222)".to_string()
+        ]);
+
+        let directory = std::env::temp_dir().join(format!("decis-vault-{}", uuid::Uuid::new_v4()));
+        assert_eq!(registry.export_vault(&directory).unwrap(), 3);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 3);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}